@@ -113,7 +113,7 @@ impl MerigoApiClient {
         }
     }
     // TODO: production
-    #[cfg(not(debug_assertions))]
+    #[cfg(not(all(feature = "local_auth", debug_assertions)))]
     pub async fn login(&self, token: &str) -> anyhow::Result<String> {
         let url = format!("{}/auth", self.api_url);
 
@@ -1,11 +1,14 @@
 #![allow(private_interfaces)]
 
-use std::path::PathBuf;
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
-use docker_api::{conn::TtyChunk, Docker};
+use docker_api::{conn::TtyChunk, opts::LogsOpts, Docker};
 use futures::StreamExt;
 use uuid::Uuid;
 
@@ -21,15 +24,103 @@ pub struct Command {
     #[arg(short, long)]
     pub debug: bool,
 
+    /// Increase the log level of the CLI: `-v` for info, `-vv` for debug and `-vvv` for trace. `RUST_LOG` takes precedence
+    /// if it's set. Must come before the subcommand, e.g. `msde-cli -vv up`.
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log warnings and errors, e.g. for a clean scriptable run. Unlike the `--quiet` flag of some subcommands, this
+    /// doesn't hide progress bars. Must come before the subcommand, e.g. `msde-cli -q versions`.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
     /// Skip building a local cache of the MSDE image registry.
     #[arg(short, long)]
     pub no_cache: bool,
 
+    /// The maximum duration in seconds for a single HTTP request to finish.
+    #[arg(long, env = "MERIGO_HTTP_TIMEOUT", default_value_t = 60)]
+    pub http_timeout: u64,
+
+    /// The maximum duration in seconds to wait for an HTTP connection to be established.
+    #[arg(long, env = "MERIGO_CONNECT_TIMEOUT", default_value_t = 10)]
+    pub connect_timeout: u64,
+
+    /// The maximum duration in seconds for a single RPC call to the MSDE node. Games are imported in batches, and a batch
+    /// gets this much time for each of its games.
+    #[arg(long, env = "MERIGO_RPC_TIMEOUT", default_value_t = crate::game::DEFAULT_RPC_TIMEOUT_SECS)]
+    pub rpc_timeout: u64,
+
+    /// The Docker daemon to connect to, e.g. `unix:///run/user/1000/docker.sock` or `tcp://127.0.0.1:2375`.
+    /// Defaults to the standard socket of the platform. Named pipes (`npipe://`) are not supported by the Docker client
+    /// library, so on Windows expose the daemon over TCP (e.g. "Expose daemon on tcp://localhost:2375" in Docker Desktop).
+    #[arg(long, env = "DOCKER_HOST")]
+    pub docker_host: Option<String>,
+
+    /// The output format of commands that print data, like `versions`, `status`, `containers`, `games` and the diagnostic that runs
+    /// without a subcommand. `json` and `yaml` are printed to stdout, while logs always go to stderr.
+    #[arg(
+        short = 'o',
+        long,
+        visible_alias = "output",
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        global = true
+    )]
+    pub format: OutputFormat,
+
+    /// The format of the log output. `json` prints one JSON object per line, which is useful for log aggregators.
+    #[arg(long, value_enum, env = "MERIGO_LOG_FORMAT", default_value_t = LogFormat::Pretty, global = true)]
+    pub log_format: LogFormat,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    let Some((key, value)) = s.split_once('=') else {
+        return Err(format!(
+            "expected `KEY=VALUE`, but no `=` was found in `{s}`"
+        ));
+    };
+    if key.is_empty() || key.chars().any(char::is_whitespace) {
+        return Err(format!("`{key}` is not a valid environment variable name"));
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
 impl Command {
+    /// Whether the active project is worth mentioning on startup. Commands that don't work with the project files (or that
+    /// print it themselves, like `status`) only log it at debug level, so their output isn't prefixed with it.
+    pub fn should_announce_project(&self) -> bool {
+        matches!(
+            self.command,
+            Some(
+                Commands::CreateGame { .. }
+                    | Commands::RemoveGame { .. }
+                    | Commands::ImportGames { .. }
+                    | Commands::Up { .. }
+                    | Commands::Run { .. }
+                    | Commands::RunHooks { .. }
+                    | Commands::Stop { .. }
+                    | Commands::Start { .. }
+                    | Commands::Down { .. }
+                    | Commands::Reset { .. }
+                    | Commands::Restart { .. }
+                    | Commands::UpdateBeamFiles { .. }
+                    | Commands::VerifyBeamFiles { .. }
+                    | Commands::Volumes { .. }
+                    | Commands::Validate { .. }
+            )
+        )
+    }
+
     pub fn should_ignore_credentials(&self) -> bool {
         matches!(
             self.command,
@@ -38,6 +129,7 @@ impl Command {
                     | Commands::Ssh { .. }
                     | Commands::RunHooks { .. }
                     | Commands::CreateGame { .. }
+                    | Commands::RemoveGame { .. }
                     | Commands::Run { .. }
                     | Commands::ImportGames { .. }
                     | Commands::Rpc { .. }
@@ -45,9 +137,11 @@ impl Command {
                     | Commands::Down { .. }
                     | Commands::Up { .. }
                     | Commands::Docs
-                    | Commands::Status
+                    | Commands::Status { .. }
                     | Commands::AddProfile { .. }
+                    | Commands::Profile { .. }
                     | Commands::SetProject { .. }
+                    | Commands::Recent { .. }
                     | Commands::GenerateCompletions { .. }
                     | Commands::UpgradeProject { .. }
                     | Commands::Clean { .. }
@@ -57,6 +151,15 @@ impl Command {
                     | Commands::Containers { .. }
                     | Commands::UpdateBeamFiles { .. }
                     | Commands::VerifyBeamFiles { .. }
+                    | Commands::Volumes { .. }
+                    | Commands::Validate { .. }
+                    | Commands::SupportBundle { .. }
+                    | Commands::Games { .. }
+                    | Commands::GamesStatus { .. }
+                    | Commands::StopGames { .. }
+                    | Commands::Wait { .. }
+                    | Commands::GameDir { .. }
+                    | Commands::Logout { .. }
             )
         )
     }
@@ -79,6 +182,12 @@ pub enum Commands {
         #[arg(long)]
         token_stdin: bool,
     },
+    /// Remove the stored credentials, both of `login` and `legacy-login`. The project and its config are left untouched.
+    Logout {
+        /// Also remove the local cache of the image registry, that was built with the removed credentials.
+        #[arg(long, action = ArgAction::SetTrue)]
+        all: bool,
+    },
     #[cfg(all(feature = "local_auth", debug_assertions))]
     Register {
         #[arg(short, long)]
@@ -94,13 +203,46 @@ pub enum Commands {
         #[arg(short, long)]
         stage: String,
 
-        /// If given, create the game with the given fixed guid, otherwise it'll be random.
+        /// If given, create the game with the given fixed guid. Otherwise the guid of the game's existing stages is reused,
+        /// or a random one is generated for a new game.
         #[arg(long)]
         guid: Option<Uuid>,
 
+        /// Reuse the guid of the game's existing stages, so the stages share a guid. This is the default.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["guid", "new_guid"])]
+        reuse_guid: bool,
+
+        /// Generate a fresh guid even if the game already has stages.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "guid")]
+        new_guid: bool,
+
         /// If given, create the game with the given fixed suid, otherwise it'll be random.
         #[arg(long)]
         suid: Option<Uuid>,
+
+        /// Derive the ids that aren't given with `--guid` and `--suid` from the game and stage names (as UUIDv5), instead
+        /// of generating random ones. The same names always get the same ids, which is useful for reproducible fixtures.
+        #[arg(long, action = ArgAction::SetTrue)]
+        deterministic: bool,
+    },
+    /// Remove a game stage created by `create-game`: delete its entry from `games/stages.yml` and its `games/<game>/<stage>`
+    /// directory.
+    RemoveGame {
+        /// The name of the game.
+        #[arg(short, long)]
+        game: String,
+
+        /// The stage name of the game.
+        #[arg(short, long)]
+        stage: String,
+
+        /// Remove the stage even if it's loaded in the running MSDE.
+        #[arg(long, action = ArgAction::SetTrue)]
+        force: bool,
+
+        /// Continue without asking for further confirmation.
+        #[arg(short = 'y', long, action = ArgAction::SetTrue)]
+        always_yes: bool,
     },
     /// Import all games from the project directory. This command will look at your active project path in games/stages.yml,
     /// and will import all valid games listed there. For more information how it works, see <https://docs.merigo.co/getting-started/devpackage#using-config-stages.yml>
@@ -108,26 +250,81 @@ pub enum Commands {
         /// Don't print output to the terminal.
         #[arg(short, long, action = ArgAction::SetTrue)]
         quiet: bool,
+
+        /// The stages file to import the games from, instead of the project's `games/stages.yml`. The paths inside are relative to the file.
+        #[arg(long)]
+        import_config_path: Option<PathBuf>,
+
+        /// Fail if any of the configured games couldn't be loaded or imported, instead of skipping them with a warning.
+        #[arg(long, action = ArgAction::SetTrue)]
+        strict: bool,
+
+        /// Import and sync the games, but don't start their stages.
+        #[arg(long, action = ArgAction::SetTrue)]
+        no_start: bool,
+
+        /// The maximum duration in seconds of importing the games. Without it, the import is unbounded, except for waiting
+        /// for the games to sync, which gives up after 30 seconds. Either way, sync jobs that don't finish in time fail the
+        /// command.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        import_timeout: Option<u64>,
+
+        /// The directory of the games, if they are not in the project's `games` directory.
+        #[arg(long, env = "MERIGO_GAMES_DIR")]
+        games_dir: Option<PathBuf>,
     },
     /// Call into the MSDE system with an RPC. The MSDE service must be running.
     ///
     /// Example:
     ///
     /// > msde-cli rpc 'IO.puts("hello")'
+    ///
+    /// > msde-cli rpc --file script.exs
     Rpc {
         /// The Elixir command to run as a quoted string.
-        #[arg(num_args = 1)]
-        cmd: String,
+        #[arg(num_args = 1, required_unless_present_any = ["file", "stdin"], conflicts_with_all = ["file", "stdin"])]
+        cmd: Option<String>,
+
+        /// Read the Elixir source to run from this file. Useful for multi-line scripts.
+        #[arg(long, conflicts_with = "stdin")]
+        file: Option<PathBuf>,
+
+        /// Read the Elixir source to run from the standard input.
+        #[arg(long, action = ArgAction::SetTrue)]
+        stdin: bool,
+
+        /// The name of the running container of the node to run the command on. Useful in a clustered setup to inspect individual nodes.
+        #[arg(long, visible_alias = "container", default_value = crate::game::MSDE_CONTAINER)]
+        node: String,
+
+        /// Run the command on every running MSDE node, and print the output of each.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "node")]
+        all_nodes: bool,
     },
     /// Open the documentation page for this package.
     Docs,
-    /// Show the project status. WIP.
-    Status,
+    /// Show the status of the active project, the running services and the local image registry cache.
+    Status {
+        /// Print the status as JSON, so it can be consumed by scripts.
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+    },
     /// Sets the project path to the given directory. The directory must contain a valid top-level `metadata.json`.
     SetProject {
+        /// The path of the project. Use `-` to switch back to the previously active project.
         #[arg(index = 1)]
         path: Option<PathBuf>,
     },
+    /// List the recently used project paths, or switch to one of them by its number in the list.
+    ///
+    /// Example:
+    ///
+    /// > msde-cli recent 2
+    Recent {
+        /// The number of the project to switch to, as shown in the list.
+        #[arg(index = 1, value_parser = clap::value_parser!(usize))]
+        index: Option<usize>,
+    },
     /// Register a new profile for running the developer package.
     AddProfile {
         /// The name of the profile.
@@ -137,6 +334,11 @@ pub enum Commands {
         #[arg(short, long, value_delimiter = ',', num_args = 1..)]
         features: Vec<crate::env::Feature>,
     },
+    /// Inspect or remove the registered profiles.
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
     /// Generate shell auto-completions for this CLI tool.
     ///
     /// This command writes auto-completions to stdout, so users are encouraged to pipe it to a file.
@@ -148,6 +350,11 @@ pub enum Commands {
         /// The target shell to generate auto-completions for. If not given, the current shell will be detected.
         #[arg(short, long)]
         shell: Option<Shell>,
+
+        /// Only suggest the running targets for `log`, `shell` and `ssh`. This asks the Docker daemon on every completion,
+        /// so it's a bit slower. Only bash and zsh are supported.
+        #[arg(long, action = ArgAction::SetTrue)]
+        dynamic: bool,
     },
     /// Upgrade the active project that was generated with an earlier version of this tool.
     UpgradeProject {
@@ -165,22 +372,29 @@ pub enum Commands {
     },
     /// Start the services, and wait for the MSDE to be healthy.
     Up {
-        /// The features to enable for this run.
+        /// The features to enable for this run. Use `all` to enable every feature, and prefix a feature with `-` to exclude it,
+        /// e.g. `--features all,-bot`. Exclusions on their own are taken from every feature, so `--features=-bot` means the
+        /// same. If the first value is an exclusion, pass it with `=` like that.
         #[arg(short, long, value_delimiter = ',', num_args = 1..)]
-        features: Vec<crate::env::Feature>,
+        features: Vec<crate::env::FeatureSelector>,
 
         /// The maximum duration in seconds to wait for services to be healthy before exiting.
-        #[arg(short, long, default_value_t = 300)]
-        timeout: u64,
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
 
         /// Do not print anything to the terminal
         #[arg(short, long, action = ArgAction::SetTrue)]
         quiet: bool,
 
-        /// After a successful start attach to MSDE container logs.
+        /// After a successful start attach to the container logs, MSDE by default (see `--attach-target`).
         #[arg(long, action = ArgAction::SetTrue)]
         attach: bool,
 
+        /// The container to attach to with `--attach`.
+        #[arg(long, value_enum, default_value_t = AttachTarget::Msde, requires = "attach")]
+        attach_target: AttachTarget,
+
         /// (Re)build the services (pass --build to docker compose).
         #[arg(long, action = ArgAction::SetTrue)]
         build: bool,
@@ -189,9 +403,32 @@ pub enum Commands {
         #[arg(long, action = ArgAction::SetTrue, conflicts_with = "quiet")]
         raw: bool,
 
-        /// The profile to use. This defines which features are enabled. If not given, the minimal profile is used.
+        /// The profile to use. This defines which features are enabled. If neither this nor `--features` is given, the
+        /// features of the last successful run are used (see `--reset-features`), or the minimal profile on the first run.
         #[arg(short, long, conflicts_with = "features")]
         profile: Option<String>,
+
+        /// Forget the features of the last successful run, so the minimal profile is used unless `--features` or
+        /// `--profile` is given.
+        #[arg(long, action = ArgAction::SetTrue)]
+        reset_features: bool,
+
+        /// The number of Web3 event consumers to run. Only valid when the Web3 feature is enabled.
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        web3_consumers: Option<u32>,
+
+        /// Set an additional environment variable for the docker compose commands, e.g. `--env STACK_VERSION=8.7.1`.
+        /// Can be repeated, and takes precedence over the inherited environment and the `.env` file.
+        #[arg(short, long, value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
+
+        /// Only boot the base services (Postgres, Redis, Consul, ..), and exit once they're ready. No features, MSDE or post-init hooks are started.
+        /// Useful for debugging the base stack alone, e.g. to run migrations.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["features", "profile", "attach", "web3_consumers"])]
+        only_base: bool,
     },
     /// Wipe out all config files related to this tool.
     Clean {
@@ -229,9 +466,11 @@ pub enum Commands {
     ///                "env_overrides": {
     ///                    "MY_KEY": "MY_VALUE"
     ///                },
+    ///                "env_file": "hooks/.env",
     ///                "working_directory": "/home/user/merigo",
-    ///                "continue_on_error": false,
-    ///                "hide_output": false
+    ///                "continue_on_failure": false,
+    ///                "hide_output": false,
+    ///                "timeout_secs": 60
     ///            }
     ///        ],
     ///        "post_run": []
@@ -242,12 +481,19 @@ pub enum Commands {
     ///
     /// `env_overrides`: Environment variables to set for the running command.
     ///
-    /// `working_directory`: The directory to execute the command in. Must be an absolute path.
+    /// `env_file`: A `.env` file to load environment variables from. Variables in `env_overrides` take precedence. Relative
+    /// paths are resolved against the project directory. If the file can't be read, the run stops, unless `continue_on_failure`
+    /// is set, in which case this command is skipped.
     ///
-    /// `continue_on_error`: Don't stop the run if this command failed (exited with non-zero code). [default: false]
+    /// `working_directory`: The directory to execute the command in. Relative paths are resolved against the project directory.
+    ///
+    /// `continue_on_failure`: Don't stop the run if this command failed (exited with non-zero code). [default: false]
     ///
     /// `hide_output`: Don't display the output of this command. [default: false]
     ///
+    /// `timeout_secs`: Kill the command if it's still running after this many seconds. This is treated as a failure, so
+    /// `continue_on_failure` applies. A missing or zero value means no timeout. [default: none]
+    ///
     /// Any script invoked by the MSDE-CLI tool sets the `MSDE_CLI_RUNNER` environment variable to `true`, so you may rely on that
     /// to distinguish executions.
     ///
@@ -257,22 +503,29 @@ pub enum Commands {
     ///
     /// - it starts with an appropriate shebang, for instance: `#!/usr/bin/env bash`
     Run {
-        /// The features to enable for this run.
+        /// The features to enable for this run. Use `all` to enable every feature, and prefix a feature with `-` to exclude it,
+        /// e.g. `--features all,-bot`. Exclusions on their own are taken from every feature, so `--features=-bot` means the
+        /// same. If the first value is an exclusion, pass it with `=` like that.
         #[arg(short, long, value_delimiter = ',', num_args = 1..)]
-        features: Vec<crate::env::Feature>,
+        features: Vec<crate::env::FeatureSelector>,
 
         /// The maximum duration in seconds to wait for services to be healthy before exiting.
-        #[arg(short, long, default_value_t = 300)]
-        timeout: u64,
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
 
         /// Do not print anything to the terminal
         #[arg(short, long, action = ArgAction::SetTrue)]
         quiet: bool,
 
-        /// After a successful start attach to MSDE container logs.
+        /// After a successful start attach to the container logs, MSDE by default (see `--attach-target`).
         #[arg(long, action = ArgAction::SetTrue)]
         attach: bool,
 
+        /// The container to attach to with `--attach`.
+        #[arg(long, value_enum, default_value_t = AttachTarget::Msde, requires = "attach")]
+        attach_target: AttachTarget,
+
         /// (Re)build the services (pass --build to docker compose).
         #[arg(long, action = ArgAction::SetTrue)]
         build: bool,
@@ -285,9 +538,45 @@ pub enum Commands {
         #[arg(long, action = ArgAction::SetTrue)]
         no_hooks: bool,
 
-        /// The profile to use. This defines which features are enabled. If not given, the minimal profile is used.
+        /// The stages file to import the games from, instead of the project's `games/stages.yml`. The paths inside are relative to the file.
+        #[arg(long)]
+        import_config_path: Option<PathBuf>,
+
+        /// Fail if any of the configured games couldn't be loaded or imported, instead of skipping them with a warning.
+        #[arg(long, action = ArgAction::SetTrue)]
+        strict: bool,
+
+        /// Import and sync the games, but don't start their stages.
+        #[arg(long, action = ArgAction::SetTrue)]
+        no_start: bool,
+
+        /// The maximum duration in seconds of importing the games, once MSDE is healthy. Independent of `--timeout`, which only
+        /// bounds booting the services. Without it, the import is unbounded, except for waiting for the games to sync, which
+        /// gives up after 30 seconds. Either way, sync jobs that don't finish in time fail the command.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        import_timeout: Option<u64>,
+
+        /// The profile to use. This defines which features are enabled. If neither this nor `--features` is given, the
+        /// features of the last successful run are used (see `--reset-features`), or the minimal profile on the first run.
         #[arg(short, long, conflicts_with = "features")]
         profile: Option<String>,
+
+        /// Forget the features of the last successful run, so the minimal profile is used unless `--features` or
+        /// `--profile` is given.
+        #[arg(long, action = ArgAction::SetTrue)]
+        reset_features: bool,
+
+        /// The number of Web3 event consumers to run. Only valid when the Web3 feature is enabled.
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        web3_consumers: Option<u32>,
+
+        /// Set an additional environment variable for the docker compose commands, e.g. `--env STACK_VERSION=8.7.1`.
+        /// Can be repeated, and takes precedence over the inherited environment and the `.env` file.
+        #[arg(short, long, value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
     },
     /// Run the defined hooks, if there are any. This command requires at least one of the --pre of --post flag to define which set of
     /// hooks to execute. This command will run hooks in the order they're defined in (and runs pre before post hooks, obviously).
@@ -302,21 +591,115 @@ pub enum Commands {
     },
     Stop {
         /// The maximum wait duration in seconds for the stop command to finish before exiting with an error.
-        #[arg(short, long, default_value_t = 300)]
-        timeout: u64,
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
+    },
+    /// Resume the previously stopped services. Unlike `up`, this does not create any containers.
+    Start {
+        /// The maximum wait duration in seconds for the start command to finish before exiting with an error.
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
     },
-    // TODO: This is almost the same as `Up`.
-    Start,
     /// Stop all running services and remove stored game data by cleaning associated Docker volumes.
     Down {
         /// The maximum wait duration in seconds for the down command to finish before exiting with an error.
-        #[arg(short, long, default_value_t = 300)]
-        timeout: u64,
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
     },
-    /// Attach the logs of the target service. This command will not display logs from the past.
+    /// Start from scratch: stop all services, remove their volumes with all game data, then start the services again.
+    /// It has the same effect as `msde-cli down && msde-cli up [args]`.
+    Reset {
+        /// The features to enable for the fresh run. Use `all` to enable every feature, and prefix a feature with `-` to
+        /// exclude it, e.g. `--features all,-bot`. Exclusions on their own are taken from every feature, so `--features=-bot`
+        /// means the same.
+        #[arg(short, long, value_delimiter = ',', num_args = 1..)]
+        features: Vec<crate::env::FeatureSelector>,
+
+        /// The profile to use. This defines which features are enabled. If neither this nor `--features` is given, the
+        /// features of the last successful run are used, or the minimal profile on the first run.
+        #[arg(short, long, conflicts_with = "features")]
+        profile: Option<String>,
+
+        /// The maximum duration in seconds to wait for each of the down and up steps before exiting with an error.
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
+
+        /// Continue without asking for further confirmation.
+        #[arg(short = 'y', long, action = ArgAction::SetTrue)]
+        always_yes: bool,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
+    },
+    /// Stop the services, and start them again with the same features as the last `up` or `run`, unless features or a
+    /// profile are given. Unlike `reset`, game data is preserved.
+    Restart {
+        /// The features to enable instead of the last used ones. Use `all` to enable every feature, and prefix a feature with `-`
+        /// to exclude it, e.g. `--features all,-bot`. Exclusions on their own are taken from every feature, so
+        /// `--features=-bot` means the same.
+        #[arg(short, long, value_delimiter = ',', num_args = 1..)]
+        features: Vec<crate::env::FeatureSelector>,
+
+        /// The profile to use instead of the last used features.
+        #[arg(short, long, conflicts_with = "features")]
+        profile: Option<String>,
+
+        /// The maximum duration in seconds to wait for each of the stop and up steps before exiting with an error.
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
+
+        /// Do not print anything to the terminal
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
+
+        /// (Re)build the services (pass --build to docker compose).
+        #[arg(long, action = ArgAction::SetTrue)]
+        build: bool,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
+    },
+    /// Print the logs of the target service. Without a target, the logs of every running service are interleaved, and each
+    /// line is prefixed with the name of its service.
+    ///
+    /// By default only new logs are printed as they arrive. Use `--since` or `--tail` to print logs from the past.
+    ///
+    /// Example:
+    ///
+    /// > msde-cli log --since 10m --no-follow msde
     Log {
+        /// Save the log history of the target to this file instead of printing it. The file starts with a header
+        /// describing the container, so it can be attached to bug reports as is.
+        #[arg(long)]
+        save: Option<PathBuf>,
+
+        /// Keep printing (or with `--save`, appending) new logs until interrupted. This is the default without `--save`.
+        #[arg(long, action = ArgAction::SetTrue)]
+        follow: bool,
+
+        /// Exit after printing the requested logs, instead of waiting for new ones.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["follow", "save"])]
+        no_follow: bool,
+
+        /// Only print logs newer than this. Either a duration like `30s`, `10m`, `2h` or `1d`, or an RFC 3339 timestamp.
+        #[arg(long, value_parser = parse_since)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Only print this many lines from the end of the existing logs.
+        #[arg(long)]
+        tail: Option<usize>,
+
+        /// Prefix every line with the timestamp Docker recorded for it.
+        #[arg(long, action = ArgAction::SetTrue)]
+        timestamps: bool,
+
         #[command(subcommand)]
-        target: Target,
+        target: Option<Target>,
     },
     /// Pull the latest docker image of the target service(s).
     Pull {
@@ -327,6 +710,29 @@ pub enum Commands {
         /// The specific version to pull.
         #[arg(short, long, required_unless_present = "version")]
         version: Option<String>,
+
+        /// Fail before pulling anything if a requested version is not in the local cache, instead of only warning.
+        #[arg(long, action = ArgAction::SetTrue)]
+        require_version: bool,
+
+        /// Hide the progress bars, and log a single line per image instead. Useful in CI logs.
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
+
+        /// Print a summary of the pulled images as JSON: the status (`pulled`, `up-to-date` or `failed`), the size in bytes
+        /// and the duration of each image.
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+
+        /// The maximum number of images to pull at the same time. Lower it on slow connections or if the registry rate
+        /// limits you.
+        #[arg(long, default_value_t = 4)]
+        max_concurrency: usize,
+
+        /// Fail on the first error of an image, instead of retrying network errors, registry server errors and rate limits
+        /// with an exponential backoff.
+        #[arg(long, action = ArgAction::SetTrue)]
+        no_retry: bool,
     },
     /// SSH into the running container.
     Ssh {
@@ -361,8 +767,26 @@ pub enum Commands {
         no_pull_images: bool,
 
         /// The target features to pull. If no features is required, just pass the empty value like so: `--features `.
+        /// Use `all` to pull every feature, and prefix a feature with `-` to exclude it, e.g. `--features all,-bot`. Exclusions on
+        /// their own are taken from every feature, so `--features=-bot` means the same.
         #[arg(short, long, value_delimiter = ',', num_args = 0..)]
-        features: Option<Vec<crate::env::Feature>>,
+        features: Option<Vec<crate::env::FeatureSelector>>,
+
+        /// The profile whose features to pull and boot, instead of `--features`.
+        #[arg(long, conflicts_with = "features")]
+        profile: Option<String>,
+
+        /// Boot the services after initializing (and pulling), and wait until they are healthy. The features are the
+        /// pulled ones, or the ones given with `--features` or `--profile`. Without either, the minimal profile is used.
+        #[arg(long, action = ArgAction::SetTrue)]
+        up: bool,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
+
+        /// Hide the progress bars while pulling the images, and log a single line per image instead.
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
     },
     /// Verify the integrity of BEAM files.
     VerifyBeamFiles {
@@ -370,9 +794,22 @@ pub enum Commands {
         #[arg(short, long)]
         version: Option<semver::Version>,
 
-        /// The path where the BEAM files are located. By default, this is the `project_folder/merigo_extension`.
+        /// The path where the BEAM files are located. By default, this is the `project_folder/merigo-extension`.
         #[arg(short, long)]
         path: Option<std::path::PathBuf>,
+
+        /// Verify every extension directory in the project (e.g. `merigo-extension-3.9.0`) against the version in its own
+        /// `checksum.txt`, and print a summary table.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["version", "path"])]
+        all: bool,
+
+        /// Don't display progress while hashing the BEAM files.
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
+
+        /// Hash the BEAM files on multiple threads. Faster on large extensions, the checksum is the same.
+        #[arg(long, action = ArgAction::SetTrue)]
+        parallel: bool,
     },
     /// Update the BEAM files. `version`, if not given, is determined by the active project `metadata.json`'s version. If that's not present
     /// either, then the upstream Merigo version is used (only updated when this tool is updated).
@@ -381,19 +818,52 @@ pub enum Commands {
         #[arg(short, long)]
         version: Option<semver::Version>,
 
-        /// The path where the BEAM files should be put. By default, this is the `project_folder/merigo_extension`.
+        /// The path where the BEAM files should be put. By default, this is the `project_folder/merigo-extension`.
         #[arg(short, long)]
         path: Option<std::path::PathBuf>,
 
-        /// Skip verifying the integrity of the BEAM files.
+        /// Skip verifying the integrity of the BEAM files and the downloaded archive.
         #[arg(long, action = ArgAction::SetTrue)]
         no_verify: bool,
+
+        /// The base URL to download the BEAM files from. Useful for serving them from an internal mirror.
+        /// The files are expected at `<base-url>/<version>/merigo-extension.zip`, alongside its `merigo-extension.zip.sha256` manifest.
+        #[arg(long, env = "MERIGO_BEAM_FILES_BASE_URL", default_value = crate::updater::DEFAULT_BEAM_FILES_BASE_URL)]
+        base_url: String,
+
+        /// Download the BEAM files even if the existing ones already match the requested version.
+        #[arg(long, action = ArgAction::SetTrue)]
+        force: bool,
+
+        /// Keep the downloaded zip and the temporary extraction directory. Useful for debugging a failed update.
+        #[arg(long, action = ArgAction::SetTrue)]
+        keep_tmp: bool,
+
+        /// Don't display progress while hashing the BEAM files.
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
+
+        /// Hash the BEAM files on multiple threads. Faster on large extensions, the checksum is the same.
+        #[arg(long, action = ArgAction::SetTrue)]
+        parallel: bool,
     },
     // TODO: This command doesn't really make sense. Maybe as an element of a project upgrade?
-    /// Checks and stops all running containers.
+    /// Checks and stops all running Merigo containers.
     Containers {
         #[arg(short = 'y', long, action = ArgAction::SetTrue)]
         always_yes: bool,
+
+        /// The duration in seconds to wait for a container to stop gracefully before it's killed.
+        #[arg(short, long, default_value_t = 10)]
+        timeout: u64,
+
+        /// The maximum number of containers to stop at the same time.
+        #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: u16,
+
+        /// Stop every running container, not just the Merigo ones.
+        #[arg(long, action = ArgAction::SetTrue)]
+        all: bool,
     },
     // TODO: This is broken if auth is not correct. Also it doesn't really make sense?
     /// Build a cache around all available Merigo Docker images in the remote registry.
@@ -402,10 +872,130 @@ pub enum Commands {
         #[arg(short, long)]
         duration: Option<i64>,
     },
-    /// Check the available versions of the target service.
+    /// Print the volume bindings of the games and samples directories, that are injected into the services on startup.
+    /// Useful to verify that the host paths resolve correctly.
+    Volumes {
+        /// The features to generate the bindings for. Use `all` to enable every feature, and prefix a feature with `-` to exclude it.
+        /// Exclusions on their own are taken from every feature, so `--features=-bot` is the same as `--features all,-bot`.
+        #[arg(short, long, value_delimiter = ',', num_args = 1..)]
+        features: Vec<crate::env::FeatureSelector>,
+
+        /// The profile to use. This defines which features are enabled. If not given, the minimal profile is used.
+        #[arg(short, long, conflicts_with = "features")]
+        profile: Option<String>,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
+    },
+    /// Collect diagnostics into a single zip file to attach to bug reports: the system and Docker information, the project
+    /// status, `metadata.json`, `config.json`, the recent logs of the Merigo containers (stopped ones too),
+    /// `log/output.log` and the generated volumes. Credentials (`credentials.json` and `auth.json`) are never included, and the stored keys and token
+    /// are masked wherever they appear in the collected files.
+    SupportBundle {
+        /// The path of the zip file. Defaults to `msde-support-<timestamp>.zip` in the current directory.
+        output: Option<PathBuf>,
+
+        /// The number of log lines to collect from the end of each container's logs.
+        #[arg(long, default_value_t = 1000)]
+        log_lines: usize,
+
+        #[command(flatten)]
+        bind_dirs: crate::compose::BindDirs,
+    },
+    /// Print the completion candidates of a dynamic value, one per line. Used by the generated shell completions.
+    #[command(name = "complete-value", hide = true)]
+    Complete {
+        kind: CompletionKind,
+
+        /// The value typed so far.
+        #[arg(allow_hyphen_values = true, last = true)]
+        current: Option<String>,
+    },
+    /// List the games and stages of the project, read from `games/stages.yml` and the referenced `local_config.yml` files.
+    Games {
+        /// Print the list as JSON. Same as `--format json`.
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+
+        /// Also show which stages are loaded in the running MSDE. MSDE must be running.
+        #[arg(long, action = ArgAction::SetTrue)]
+        running: bool,
+
+        /// The stages file to read instead of the project's `games/stages.yml`.
+        #[arg(long)]
+        stages_file: Option<PathBuf>,
+    },
+    /// Show the live state of every stage loaded in the running MSDE, e.g. whether it's running or only imported.
+    GamesStatus {
+        /// Print the list as JSON. Same as `--format json`.
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Stop the launched stages in the running MSDE, without stopping MSDE itself. Use `import-games` to start them again.
+    StopGames {
+        /// Only stop the stages of these games, given by name or guid. Separate multiple games with commas.
+        #[arg(long, value_delimiter = ',', num_args = 1..)]
+        only: Option<Vec<String>>,
+    },
+    /// Print the absolute path of a game's directory in the project, or of one of its stages.
+    ///
+    /// Example:
+    ///
+    /// > cd "$(msde-cli game-dir --game my-game --stage dev)"
+    GameDir {
+        /// The name of the game.
+        #[arg(short, long)]
+        game: String,
+
+        /// The stage name of the game.
+        #[arg(short, long)]
+        stage: Option<String>,
+
+        /// Also open the directory in the file manager.
+        #[arg(long, action = ArgAction::SetTrue)]
+        reveal: bool,
+    },
+    /// Wait until the given targets are healthy, e.g. after a detached `up` in CI. Exits with an error if any target is not
+    /// running, unhealthy, or doesn't become healthy in time. Targets without a health check are ready once they run.
+    ///
+    /// Example:
+    ///
+    /// > msde-cli wait --target msde --target web3 --timeout 120
+    Wait {
+        /// The targets to wait for. Defaults to MSDE.
+        #[arg(long = "target", value_enum)]
+        targets: Vec<crate::env::ExtendedFeature>,
+
+        /// The maximum duration in seconds to wait for the targets to be healthy.
+        /// Defaults to the `default_timeout` of the project's metadata.json, or 300 if that's not set either.
+        #[arg(short, long, env = "MERIGO_TIMEOUT")]
+        timeout: Option<u64>,
+
+        /// Print the status of the targets as JSON. Same as `--format json`.
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Validate the `stages.yml` and `local_config.yml` files of the project's games. Unknown keys, invalid values and
+    /// missing referenced paths are all reported, so mistakes are caught before importing the games.
+    Validate {
+        /// The directory of the games, if they are not in the project's `games` directory.
+        #[arg(long, env = "MERIGO_GAMES_DIR")]
+        games_dir: Option<PathBuf>,
+    },
+    /// Check the available versions of the target service. Lists every target grouped when no target is given.
+    ///
+    /// The versions are sorted from newest to oldest in every `--format`.
     Versions {
+        /// Only list versions greater than or equal to this one, e.g. `3.10.0`.
+        #[arg(long)]
+        min: Option<semver::Version>,
+
+        /// Only list versions less than or equal to this one, e.g. `3.99.99`.
+        #[arg(long)]
+        max: Option<semver::Version>,
+
         #[command(subcommand)]
-        target: Target,
+        target: Option<Target>,
     },
     LegacyLogin {
         // The key used for GHCR authentication.
@@ -420,6 +1010,26 @@ pub enum Commands {
     },
 }
 
+#[derive(Clone, PartialEq, Eq, Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List every profile with its features.
+    List,
+    /// Show the features of a single profile, in the order they're started.
+    Show {
+        /// The name of the profile.
+        name: String,
+    },
+    /// Remove a profile.
+    Remove {
+        /// The name of the profile.
+        name: String,
+
+        /// Allow removing the built-in `minimal`, `default` and `full` profiles.
+        #[arg(long, action = ArgAction::SetTrue)]
+        force: bool,
+    },
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Subcommand)]
 #[command(subcommand_negates_reqs = true)]
 pub enum Target {
@@ -435,6 +1045,8 @@ pub enum Target {
         #[arg(short, long)]
         version: Option<String>,
 
+        /// Which of the Web3 services to target: `producer` is the services image, `consumer` the consumer image.
+        /// Defaults to both.
         #[arg(short, long)]
         kind: Option<Web3Kind>,
     },
@@ -444,6 +1056,138 @@ pub enum Target {
     },
 }
 
+/// The output format of commands that print structured data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum OutputFormat {
+    #[value(alias = "table")]
+    Text,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Print `value` to stdout in the structured formats. Returns `false` for `Text`, where the caller prints its own
+    /// human-readable output instead.
+    pub fn print_structured<T: serde::Serialize>(self, value: &T) -> anyhow::Result<bool> {
+        match self {
+            OutputFormat::Text => return Ok(false),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        }
+        Ok(true)
+    }
+}
+
+/// The values that are completed dynamically by `complete-value`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum CompletionKind {
+    Features,
+    Profiles,
+    /// The running targets, see `generate-completions --dynamic`.
+    Targets,
+}
+
+/// The containers that can be followed with `--attach`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum AttachTarget {
+    Msde,
+    Compiler,
+    Web3,
+    Bot,
+}
+
+impl AttachTarget {
+    pub fn to_target(self) -> Target {
+        match self {
+            AttachTarget::Msde => Target::Msde { version: None },
+            AttachTarget::Compiler => Target::Compiler { version: None },
+            AttachTarget::Web3 => Target::Web3 {
+                version: None,
+                kind: None,
+            },
+            AttachTarget::Bot => Target::Bot { version: None },
+        }
+    }
+
+    /// Make sure the container will actually be running with the given features.
+    pub fn ensure_enabled(self, features: &[crate::env::Feature]) -> anyhow::Result<()> {
+        let required = match self {
+            AttachTarget::Msde | AttachTarget::Compiler => return Ok(()),
+            AttachTarget::Web3 => crate::env::Feature::Web3,
+            AttachTarget::Bot => crate::env::Feature::Bot,
+        };
+        anyhow::ensure!(
+            features.contains(&required),
+            "Cannot attach to {self:?}, because the {required} feature is not enabled."
+        );
+        Ok(())
+    }
+}
+
+/// Which logs of a target to read.
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub tail: Option<usize>,
+    pub follow: bool,
+    pub timestamps: bool,
+}
+
+impl LogOptions {
+    /// The options of the Docker logs request. When `save` is set, following without any limit still includes the history,
+    /// since that's what saving is for.
+    fn to_logs_opts(&self, save: bool) -> LogsOpts {
+        let mut builder = LogsOpts::builder()
+            .stdout(true)
+            .stderr(true)
+            .follow(self.follow)
+            .timestamps(self.timestamps);
+        builder = match (self.tail, self.since) {
+            (Some(n), _) => builder.n_lines(n),
+            // Following without any limit only shows the new logs, like attaching to the container.
+            (None, None) if self.follow && !save => builder.n_lines(0),
+            (None, _) => builder.all(),
+        };
+        if let Some(since) = &self.since {
+            builder = builder.since(since);
+        }
+        builder.build()
+    }
+}
+
+/// Parse the `--since` argument of `log`: a duration like `10m` before now, or an RFC 3339 timestamp.
+fn parse_since(value: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&chrono::Utc));
+    }
+    let (amount, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let amount: u64 = amount.parse().map_err(|_| {
+        format!("`{value}` is neither a duration like `10m` nor an RFC 3339 timestamp")
+    })?;
+    let seconds_per_unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown unit `{unit}`, use one of `s`, `m`, `h` or `d`"
+            ))
+        }
+    };
+    amount
+        .checked_mul(seconds_per_unit)
+        .and_then(|seconds| {
+            chrono::Duration::from_std(std::time::Duration::from_secs(seconds)).ok()
+        })
+        .and_then(|duration| chrono::Utc::now().checked_sub_signed(duration))
+        .ok_or_else(|| format!("`{value}` is too far in the past"))
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, ValueEnum)]
 pub enum Web3Kind {
     All,
@@ -452,6 +1196,25 @@ pub enum Web3Kind {
 }
 
 impl Target {
+    /// Every target with the same version, used when a command is invoked without a target.
+    pub fn all(version: Option<String>) -> Vec<Target> {
+        vec![
+            Target::Msde {
+                version: version.clone(),
+            },
+            Target::Compiler {
+                version: version.clone(),
+            },
+            Target::Bot {
+                version: version.clone(),
+            },
+            Target::Web3 {
+                version,
+                kind: Some(Web3Kind::All),
+            },
+        ]
+    }
+
     pub async fn attach(&self, docker: &Docker) -> anyhow::Result<()> {
         let id = self.get_id(docker).await?;
 
@@ -465,6 +1228,115 @@ impl Target {
         }
         Ok(())
     }
+    /// Print the logs of the target to stdout.
+    pub async fn print_logs(&self, docker: &Docker, opts: &LogOptions) -> anyhow::Result<()> {
+        let id = self.get_id(docker).await?;
+        let container = docker.containers().get(id);
+        let mut logs = container.logs(&opts.to_logs_opts(false));
+        let mut stdout = std::io::stdout();
+        while let Some(chunk) = logs.next().await {
+            if let TtyChunk::StdOut(chunk) | TtyChunk::StdErr(chunk) = chunk? {
+                stdout.write_all(&chunk)?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the interleaved logs of every running target, each line prefixed with the name of the target.
+    pub async fn print_all_logs(docker: &Docker, opts: &LogOptions) -> anyhow::Result<()> {
+        let targets = Target::running(docker).await?;
+        anyhow::ensure!(!targets.is_empty(), "No Merigo services are running");
+        let width = targets
+            .iter()
+            .map(|target| target.to_string().len())
+            .max()
+            .unwrap_or_default();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = Vec::with_capacity(targets.len());
+        for target in targets {
+            let (docker, opts, tx) = (docker.clone(), opts.to_logs_opts(false), tx.clone());
+            tasks.push(tokio::spawn(async move {
+                let id = target.get_id(&docker).await?;
+                let container = docker.containers().get(id);
+                let mut logs = container.logs(&opts);
+                // Chunks don't necessarily end at line boundaries, so the last partial line is kept until it's completed.
+                let mut pending = String::new();
+                while let Some(chunk) = logs.next().await {
+                    if let TtyChunk::StdOut(chunk) | TtyChunk::StdErr(chunk) = chunk? {
+                        pending.push_str(&String::from_utf8_lossy(&chunk));
+                        while let Some(end) = pending.find('\n') {
+                            let line: String = pending.drain(..=end).collect();
+                            let _ = tx.send((target.to_string(), line));
+                        }
+                    }
+                }
+                if !pending.is_empty() {
+                    pending.push('\n');
+                    let _ = tx.send((target.to_string(), pending));
+                }
+                anyhow::Ok(())
+            }));
+        }
+        drop(tx);
+
+        let mut stdout = std::io::stdout();
+        while let Some((name, line)) = rx.recv().await {
+            write!(stdout, "{name:<width$} | {line}")?;
+            stdout.flush()?;
+        }
+        for task in tasks {
+            if let Err(error) = task.await? {
+                tracing::warn!(%error, "failed to read the logs of a service");
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the logs of the target to `path`, after a header with the container name, image and the current time.
+    pub async fn save_logs(
+        &self,
+        docker: &Docker,
+        path: &Path,
+        opts: &LogOptions,
+    ) -> anyhow::Result<()> {
+        let id = self.get_id(docker).await?;
+        let container = docker.containers().get(id);
+        let details = container.inspect().await?;
+
+        let mut file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create `{}`", path.display()))?;
+        let saved_at = time::OffsetDateTime::now_utc();
+        writeln!(
+            file,
+            "# container: {}",
+            details
+                .name
+                .as_deref()
+                .unwrap_or_default()
+                .trim_start_matches('/')
+        )?;
+        writeln!(
+            file,
+            "# image: {}",
+            details
+                .config
+                .and_then(|config| config.image)
+                .unwrap_or_default()
+        )?;
+        writeln!(file, "# saved at: {saved_at}")?;
+        writeln!(file)?;
+
+        let mut logs = container.logs(&opts.to_logs_opts(true));
+        while let Some(chunk) = logs.next().await {
+            if let TtyChunk::StdOut(chunk) | TtyChunk::StdErr(chunk) = chunk? {
+                file.write_all(&chunk)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_version(&self) -> Option<&String> {
         match self {
             Target::Msde { version }
@@ -474,29 +1346,43 @@ impl Target {
         }
     }
 
-    pub async fn get_id(&self, docker: &Docker) -> anyhow::Result<String> {
-        let target = match self {
+    /// The name of the target's container, as reported by the Docker daemon.
+    fn daemon_container_name(&self) -> &'static str {
+        match self {
             Target::Msde { .. } => "/msde-vm-dev",
             Target::Bot { .. } => "/bot-vm-dev",
             Target::Web3 { .. } => "/web3-vm-dev",
             Target::Compiler { .. } => "/compiler-vm-dev",
-        };
+        }
+    }
+
+    pub async fn get_id(&self, docker: &Docker) -> anyhow::Result<String> {
         let containers = running_containers(docker).await?;
         let container_id = containers
-            .get(target)
+            .get(self.daemon_container_name())
             .context("Target container is not running")?;
         Ok(container_id.clone())
     }
 
+    /// The targets whose container is running.
+    pub async fn running(docker: &Docker) -> anyhow::Result<Vec<Target>> {
+        let containers = running_containers(docker).await?;
+        Ok(Target::all(None)
+            .into_iter()
+            .filter(|target| containers.contains_key(target.daemon_container_name()))
+            .collect())
+    }
+
     pub fn container_name(&self) -> Option<&str> {
         match self {
             Target::Msde { .. } => Some("msde-vm-dev"),
             Target::Bot { .. } => Some("bot-vm-dev"),
-            Target::Web3 { .. } => None,
+            Target::Web3 { .. } => Some("web3-vm-dev"),
             Target::Compiler { .. } => Some("compiler-vm-dev"),
         }
     }
 
+    /// The release script that starts a remote Elixir console. Web3 is not an Elixir service, so it has none.
     pub fn container_remote_console_path(&self) -> Option<&str> {
         match self {
             Target::Msde { .. } => Some("/usr/local/bin/merigo/msde/bin/msde"),
@@ -520,25 +1406,27 @@ impl Target {
                     tag,
                 )]
             }
-            Target::Web3 { version, .. } => {
+            Target::Web3 { version, kind } => {
                 let tag = match version {
                     Some(version) => version.to_string(),
                     None => LATEST.to_owned(),
                 };
                 tracing::trace!(%tag, "assembled tag is");
 
-                vec![
-                    (
-                        "docker.pkg.github.com/merigo-co/web3_services/web3_services_dev"
-                            .to_string(),
-                        tag.clone(),
-                    ),
-                    (
-                        "docker.pkg.github.com/merigo-co/web3_services/web3_consumer_dev"
-                            .to_string(),
-                        tag,
-                    ),
-                ]
+                let images: &[&str] = match kind.as_ref().unwrap_or(&Web3Kind::All) {
+                    Web3Kind::All => &["web3_services_dev", "web3_consumer_dev"],
+                    Web3Kind::Consumer => &["web3_consumer_dev"],
+                    Web3Kind::Producer => &["web3_services_dev"],
+                };
+                images
+                    .iter()
+                    .map(|image| {
+                        (
+                            format!("docker.pkg.github.com/merigo-co/web3_services/{image}"),
+                            tag.clone(),
+                        )
+                    })
+                    .collect()
             }
         }
     }
@@ -568,3 +1456,77 @@ impl AsRef<str> for Target {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn web3_images_follow_the_kind() {
+        let images = |kind| {
+            Target::Web3 {
+                version: Some(String::from("1.0.0")),
+                kind,
+            }
+            .images_and_tags()
+        };
+        assert_eq!(
+            images(Some(Web3Kind::Consumer)),
+            vec![(
+                String::from("docker.pkg.github.com/merigo-co/web3_services/web3_consumer_dev"),
+                String::from("1.0.0")
+            )]
+        );
+        assert_eq!(images(Some(Web3Kind::Producer)).len(), 1);
+        assert!(images(Some(Web3Kind::Producer))[0]
+            .0
+            .ends_with("web3_services_dev"));
+        assert_eq!(images(Some(Web3Kind::All)), images(None));
+        assert_eq!(images(None).len(), 2);
+    }
+
+    #[test]
+    fn format_has_short_and_alias() {
+        let format = |args: &[&str]| Command::try_parse_from(args).unwrap().format;
+        assert_eq!(
+            format(&["msde-cli", "-o", "json", "versions"]),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            format(&["msde-cli", "versions", "--output", "yaml"]),
+            OutputFormat::Yaml
+        );
+        assert_eq!(
+            format(&["msde-cli", "versions", "--format", "json"]),
+            OutputFormat::Json
+        );
+        assert_eq!(format(&["msde-cli", "status"]), OutputFormat::Text);
+        // The zip path of `support-bundle` used to be `-o`.
+        let cmd = Command::try_parse_from(["msde-cli", "support-bundle", "-o", "yaml", "out.zip"])
+            .unwrap();
+        assert_eq!(cmd.format, OutputFormat::Yaml);
+        assert!(matches!(
+            cmd.command,
+            Some(Commands::SupportBundle { output: Some(output), .. }) if output == Path::new("out.zip")
+        ));
+    }
+
+    #[test]
+    fn parses_since() {
+        let ten_minutes_ago = parse_since("10m").unwrap();
+        let elapsed = chrono::Utc::now() - ten_minutes_ago;
+        assert!(
+            elapsed >= chrono::Duration::minutes(10) && elapsed < chrono::Duration::minutes(11)
+        );
+        assert_eq!(
+            parse_since("2024-05-01T12:00:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-05-01T10:00:00+00:00"
+        );
+        assert!(parse_since("10 minutes").is_err());
+        assert!(parse_since("m").is_err());
+        assert!(parse_since("9999999999999d").is_err());
+        assert!(parse_since("99999999999999999999s").is_err());
+    }
+}
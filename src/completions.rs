@@ -0,0 +1,227 @@
+//! Dynamic shell completions. The generated completion scripts are static, so the values that depend on the user's setup
+//! (the profiles in `config.json`, or the running targets with `--dynamic`) are completed by calling back into the hidden
+//! `complete-value` command.
+
+use clap::ValueEnum;
+use clap_complete::Shell;
+
+use crate::{cli::CompletionKind, env::Feature};
+
+/// The commands whose target is completed with the running targets only, with `--dynamic`.
+const TARGET_COMMANDS: [&str; 3] = ["log", "shell", "ssh"];
+
+/// The completion candidates of `current` for the given kind of value. `known` holds the candidates that come from the
+/// user's setup: the profile names, or the running targets.
+///
+/// Features are completed as comma separated lists, so for `metrics,we` the candidate is `metrics,web3`. Exclusions like
+/// `-bot` are only suggested once the current item starts with a `-`.
+pub fn complete(kind: CompletionKind, current: &str, known: &[String]) -> Vec<String> {
+    match kind {
+        CompletionKind::Profiles | CompletionKind::Targets => known
+            .iter()
+            .filter(|name| name.starts_with(current))
+            .cloned()
+            .collect(),
+        CompletionKind::Features => {
+            let (done, item) = match current.rsplit_once(',') {
+                Some((done, item)) => (format!("{done},"), item),
+                None => (String::new(), current),
+            };
+            let names = Feature::value_variants()
+                .iter()
+                .filter_map(|feature| feature.to_possible_value())
+                .map(|value| value.get_name().to_owned());
+            let candidates: Vec<String> = if item.starts_with('-') {
+                names.map(|name| format!("-{name}")).collect()
+            } else {
+                std::iter::once(String::from("all")).chain(names).collect()
+            };
+            candidates
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(item))
+                .map(|candidate| format!("{done}{candidate}"))
+                .collect()
+        }
+    }
+}
+
+const BASH_FILES: &str = r#"COMPREPLY=($(compgen -f "${cur}"))"#;
+
+/// Patch the static completion script of clap to complete `--profile` and `--features` values dynamically, and with
+/// `targets`, the targets of [`TARGET_COMMANDS`]. Only bash and zsh are supported, the other shells keep the static
+/// completions.
+pub fn with_dynamic_values(shell: Shell, script: String, targets: bool) -> String {
+    match shell {
+        Shell::Bash => {
+            let profiles =
+                r#"COMPREPLY=($(msde-cli complete-value profiles -- "${cur}" 2>/dev/null))"#;
+            let features =
+                r#"COMPREPLY=($(msde-cli complete-value features -- "${cur}" 2>/dev/null))"#;
+            let script = patch_bash_option(&script, "--profile", "-p", profiles);
+            let script = patch_bash_option(&script, "--features", "-f", features);
+            if targets {
+                patch_bash_targets(&script)
+            } else {
+                script
+            }
+        }
+        Shell::Zsh => {
+            let script = script
+                .replace(
+                    "]:PROFILE: '",
+                    r#"]:PROFILE:{compadd -- ${(f)"$(msde-cli complete-value profiles 2>/dev/null)"}}'"#,
+                )
+                .replace(
+                    "]:FEATURES: '",
+                    r#"]:FEATURES:{_values -s , features ${(f)"$(msde-cli complete-value features 2>/dev/null)"}}'"#,
+                );
+            if targets {
+                patch_zsh_targets(&script)
+            } else {
+                script
+            }
+        }
+        _ => script,
+    }
+}
+
+/// The targets in the order clap lists them as the subcommands of a command that takes a [`crate::cli::Target`].
+fn static_targets() -> Vec<String> {
+    <crate::cli::Target as clap::Subcommand>::augment_subcommands(clap::Command::new("target"))
+        .get_subcommands()
+        .map(|command| command.get_name().to_owned())
+        .collect()
+}
+
+fn patch_bash_targets(script: &str) -> String {
+    let static_targets = format!(" {} help\"", static_targets().join(" "));
+    let dynamic_targets = r#" $(msde-cli complete-value targets 2>/dev/null) help""#;
+    let mut lines: Vec<String> = script.lines().map(ToOwned::to_owned).collect();
+    for i in 0..lines.len().saturating_sub(1) {
+        let is_target_command = TARGET_COMMANDS
+            .iter()
+            .any(|command| lines[i].trim() == format!("msde__cli__{command})"));
+        if is_target_command && lines[i + 1].trim_start().starts_with("opts=") {
+            lines[i + 1] = lines[i + 1].replace(&static_targets, dynamic_targets);
+        }
+    }
+    join_lines(script, lines)
+}
+
+fn patch_zsh_targets(script: &str) -> String {
+    let static_targets = static_targets()
+        .iter()
+        .map(|target| format!("'{target}:' \\"))
+        .collect::<Vec<_>>();
+    let mut lines: Vec<String> = script.lines().map(ToOwned::to_owned).collect();
+    let mut patched = Vec::with_capacity(lines.len());
+    let mut i = 0;
+    while i < lines.len() {
+        let is_target_command = TARGET_COMMANDS
+            .iter()
+            .any(|command| lines[i].trim() == format!("_msde-cli__{command}_commands() {{"));
+        patched.push(std::mem::take(&mut lines[i]));
+        i += 1;
+        // The function body starts with `local commands; commands=(`, followed by one line per subcommand.
+        if is_target_command && i + static_targets.len() < lines.len() {
+            let listed = &lines[i + 1..i + 1 + static_targets.len()];
+            if listed
+                .iter()
+                .map(|line| line.trim())
+                .eq(static_targets.iter().map(String::as_str))
+            {
+                patched.push(std::mem::take(&mut lines[i]));
+                patched.push(String::from(
+                    r#"${(f)"$(msde-cli complete-value targets 2>/dev/null)"} \"#,
+                ));
+                i += 1 + static_targets.len();
+            }
+        }
+    }
+    join_lines(script, patched)
+}
+
+fn join_lines(original: &str, lines: Vec<String>) -> String {
+    let mut joined = lines.join("\n");
+    if original.ends_with('\n') {
+        joined.push('\n');
+    }
+    joined
+}
+
+/// Replace the file completion of `long` (and of `short`, if it directly follows `long`) with `completion`. The short
+/// flag is only replaced in that position, because the same short flag may mean something else in other subcommands.
+fn patch_bash_option(script: &str, long: &str, short: &str, completion: &str) -> String {
+    let mut lines: Vec<String> = script.lines().map(ToOwned::to_owned).collect();
+    let mut i = 0;
+    while i + 1 < lines.len() {
+        if lines[i].trim() == format!("{long})") && lines[i + 1].trim() == BASH_FILES {
+            lines[i + 1] = lines[i + 1].replace(BASH_FILES, completion);
+            // The next case is `return 0` and `;;`, then possibly the short flag.
+            if lines
+                .get(i + 4)
+                .is_some_and(|line| line.trim() == format!("{short})"))
+                && lines
+                    .get(i + 5)
+                    .is_some_and(|line| line.trim() == BASH_FILES)
+            {
+                lines[i + 5] = lines[i + 5].replace(BASH_FILES, completion);
+            }
+        }
+        i += 1;
+    }
+    join_lines(script, lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_comma_separated_features() {
+        assert_eq!(
+            complete(CompletionKind::Features, "metrics,we", &[]),
+            vec!["metrics,web3"]
+        );
+        assert_eq!(
+            complete(CompletionKind::Features, "all,-b", &[]),
+            vec!["all,-bot"]
+        );
+        assert!(complete(CompletionKind::Features, "", &[]).contains(&String::from("all")));
+    }
+
+    #[test]
+    fn patches_only_the_short_flag_after_the_long_one() {
+        let script = [
+            "                --profile)",
+            r#"                    COMPREPLY=($(compgen -f "${cur}"))"#,
+            "                    return 0",
+            "                    ;;",
+            "                -p)",
+            r#"                    COMPREPLY=($(compgen -f "${cur}"))"#,
+            "                    return 0",
+            "                    ;;",
+            "                -p)",
+            r#"                    COMPREPLY=($(compgen -f "${cur}"))"#,
+        ]
+        .join("\n");
+        let patched = with_dynamic_values(Shell::Bash, script, false);
+        assert_eq!(patched.matches("complete-value profiles").count(), 2);
+        assert_eq!(patched.matches("compgen -f").count(), 1);
+    }
+
+    #[test]
+    fn patches_the_targets_only_with_dynamic() {
+        let script = [
+            "        msde__cli__log)",
+            r#"            opts="-h --save --help msde bot web3 compiler help""#,
+            "        msde__cli__pull)",
+            r#"            opts="-h --help msde bot web3 compiler help""#,
+        ]
+        .join("\n");
+        assert!(!with_dynamic_values(Shell::Bash, script.clone(), false).contains("complete-value"));
+        let patched = with_dynamic_values(Shell::Bash, script, true);
+        assert_eq!(patched.matches("complete-value targets").count(), 1);
+        assert!(patched.contains(r#"--help msde bot web3 compiler help""#));
+    }
+}
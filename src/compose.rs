@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     future::Future,
     io::Read,
     path::{Path, PathBuf},
@@ -7,8 +7,14 @@ use std::{
     time::Duration,
 };
 
-use crate::{env::Feature, game::rpc, MERIGO_UPSTREAM_VERSION};
+use crate::{
+    env::{ExtendedFeature, Feature},
+    game::rpc_typed,
+    sysconfig, MERIGO_UPSTREAM_VERSION,
+};
 use anyhow::Context as _;
+use backoff::backoff::Backoff;
+use clap::ValueEnum as _;
 use docker_api::{
     opts::{ContainerRemoveOpts, ExecCreateOpts},
     Docker, Exec,
@@ -37,6 +43,9 @@ pub static DOCKER_COMPOSE_OTEL: &str = "docker/docker-compose-otel.yml";
 #[allow(dead_code)]
 pub static DOCKER_COMPOSE_BOT: &str = "docker/docker-compose-bot.yml";
 
+/// The compose service that consumes Web3 events. Multiple instances of it may run.
+const WEB3_CONSUMER_SERVICE: &str = "consumer_events";
+
 const MERIGO_GAMES_DIR: &str = "/usr/local/bin/merigo/games";
 const MERIGO_SAMPLE_DIR: &str = "/usr/local/bin/merigo/samples";
 
@@ -46,19 +55,29 @@ pub struct ComposeOpts<'a> {
     pub target: Option<&'a str>,
     pub file_streamed_stdin: bool,
     pub build: bool,
+    /// Services to scale, and the number of their instances (passed as `--scale service=n`).
+    pub scale: Vec<(&'a str, u32)>,
+    /// Additional environment variables for the compose process, set over the inherited environment.
+    pub env: &'a [(String, String)],
+    /// The MSDE release to run, passed to compose as `VSN`. Defaults to the release this tool was built for.
+    pub vsn: Option<&'a str>,
 }
 
 impl<'a> ComposeOpts<'a> {
-    fn into_args(self) -> Vec<&'a str> {
+    fn into_args(self) -> Vec<String> {
         let mut args = vec![];
         if self.daemon {
-            args.push("-d");
+            args.push(String::from("-d"));
         }
         if self.build {
-            args.push("--build");
+            args.push(String::from("--build"));
+        }
+        for (service, n) in self.scale {
+            args.push(String::from("--scale"));
+            args.push(format!("{service}={n}"));
         }
         if let Some(target) = self.target {
-            args.push(target)
+            args.push(target.to_owned())
         }
 
         args
@@ -95,8 +114,9 @@ impl Compose {
             .arg("compose")
             .args(files)
             .arg("start")
+            .env("VSN", opts.vsn.unwrap_or(MERIGO_UPSTREAM_VERSION))
+            .envs(opts.env.iter().cloned())
             .args(opts.into_args())
-            .env("VSN", MERIGO_UPSTREAM_VERSION) // TODO: Use the same logic as for UpdateBeamFiles to determine the version.
             .spawn()
             .map_err(Into::into)
     }
@@ -130,8 +150,9 @@ impl Compose {
             .arg("compose")
             .args(files)
             .arg("up")
+            .env("VSN", opts.vsn.unwrap_or(MERIGO_UPSTREAM_VERSION))
+            .envs(opts.env.iter().cloned())
             .args(opts.into_args())
-            .env("VSN", MERIGO_UPSTREAM_VERSION) // TODO: Use the same logic as for UpdateBeamFiles to determine the version.
             .spawn()
             .map_err(Into::into)
     }
@@ -208,51 +229,20 @@ impl Pipeline {
         pb.set_style(spinner_style);
         pb.enable_steady_tick(std::time::Duration::from_millis(80));
         pb.set_message("Stopping all services..");
-        let mut child = Compose::down_all(&msde_dir)?;
-
-        tokio::select! {
-            exc = child.wait() => {
-                match exc {
-                    Ok(status) if status.success() => {
-                        clean_otel_volumes(docker).await?;
-                        web3_stop_consumers(docker).await?;
-                        pb.finish_with_message("✅ All services stopped.")
-                    },
-                    Ok(status) => {
-                        pb.finish_with_message(format!("❌ Failed to stop services, stopping process.. (exit status {:?})", status.code().unwrap_or(1)));
-                        let mut stdout = child.stdout.take().context("Failed to take child stdout")?;
-                        let mut stderr = child.stderr.take().context("Failed to take child stderr")?;
-                        let mut stdout_buf = vec![];
-                        let mut stderr_buf = vec![];
-                        stdout.read_to_end(&mut stdout_buf).await?;
-                        stderr.read_to_end(&mut stderr_buf).await?;
-                        drop(stdout);
-                        drop(stderr);
-
-                        let log_path = write_failed_start_log(&msde_dir, stdout_buf.as_slice(), stderr_buf.as_slice()).await?;
-                        println!("You may find the output of the failing command at:");
-                        println!("  {}  ", log_path.display());
-                        return Err(anyhow::Error::msg("Failed"));
+        let child = Compose::down_all(&msde_dir)?;
 
-                    },
-                    Err(e) => {
-                        // FIXME: Unclear from the documentation what happens here. Probably things go really wrong here, so we should just exit immediately.
-                        eprintln!("{e}");
-                        return Err(anyhow::Error::msg("Failed"));
-
-                    },
-                }
-            },
-            _ = tokio::time::sleep(std::time::Duration::from_secs(timeout)) => {
-                pb.finish_with_message("❌ Stopping services timed out, stopping process..");
-                child.start_kill()?;
-                let result  = child.wait_with_output().await?;
-                let log_path = write_failed_start_log(&msde_dir, &result.stdout, &result.stderr).await?;
-                println!("You may find the output of the failing command at:");
-                println!("  {}  ", log_path.display());
-                return Err(anyhow::Error::msg("Failed"));
-            },
-        }
+        wait_child(
+            child,
+            &pb,
+            timeout,
+            &msde_dir,
+            "Failed to stop services",
+            "Stopping services timed out",
+        )
+        .await?;
+        clean_otel_volumes(docker).await?;
+        web3_stop_consumers(docker).await?;
+        pb.finish_with_message("✅ All services stopped.");
         Ok(())
     }
 
@@ -272,54 +262,85 @@ impl Pipeline {
         pb.set_style(spinner_style);
         pb.enable_steady_tick(std::time::Duration::from_millis(80));
         pb.set_message("Stopping all services..");
-        let mut child = Compose::stop_all(&msde_dir)?;
-
-        tokio::select! {
-            exc = child.wait() => {
-                match exc {
-                    Ok(status) if status.success() => {
-                        web3_stop_consumers(docker).await?;
-                        pb.finish_with_message("✅ All services stopped.")
-                    },
-                    Ok(status) => {
-                        pb.finish_with_message(format!("❌ Failed to stop services, stopping process.. (exit status {:?})", status.code().unwrap_or(1)));
-                        let mut stdout = child.stdout.take().context("Failed to take child stdout")?;
-                        let mut stderr = child.stderr.take().context("Failed to take child stderr")?;
-                        let mut stdout_buf = vec![];
-                        let mut stderr_buf = vec![];
-                        stdout.read_to_end(&mut stdout_buf).await?;
-                        stderr.read_to_end(&mut stderr_buf).await?;
-                        drop(stdout);
-                        drop(stderr);
-
-                        let log_path = write_failed_start_log(&msde_dir, stdout_buf.as_slice(), stderr_buf.as_slice()).await?;
-                        println!("You may find the output of the failing command at:");
-                        println!("  {}  ", log_path.display());
-                        return Err(anyhow::Error::msg("Failed"));
+        let child = Compose::stop_all(&msde_dir)?;
 
-                    },
-                    Err(e) => {
-                        // FIXME: Unclear from the documentation what happens here. Probably things go really wrong here, so we should just exit immediately.
-                        eprintln!("{e}");
-                        return Err(anyhow::Error::msg("Failed"));
+        wait_child(
+            child,
+            &pb,
+            timeout,
+            &msde_dir,
+            "Failed to stop services",
+            "Stopping services timed out",
+        )
+        .await?;
+        web3_stop_consumers(docker).await?;
+        pb.finish_with_message("✅ All services stopped.");
+        Ok(())
+    }
 
-                    },
-                }
-            },
-            _ = tokio::time::sleep(std::time::Duration::from_secs(timeout)) => {
-                pb.finish_with_message("❌ Stopping services timed out, stopping process..");
-                child.start_kill()?;
-                let result  = child.wait_with_output().await?;
-                let log_path = write_failed_start_log(&msde_dir, &result.stdout, &result.stderr).await?;
-                println!("You may find the output of the failing command at:");
-                println!("  {}  ", log_path.display());
-                return Err(anyhow::Error::msg("Failed"));
-            },
+    /// Resume the previously stopped services. Unlike `up_from_features`, this never creates containers, so only services
+    /// that were started with `up` and not removed with `down` are resumed.
+    pub async fn start_all<P: AsRef<Path>>(
+        docker: &Docker,
+        msde_dir: P,
+        vsn: &str,
+        timeout: u64,
+    ) -> anyhow::Result<()> {
+        let pb = progress_spinner(false);
+        pb.set_message("Starting all stopped services..");
+        let child = Compose::start_custom(
+            &[
+                DOCKER_COMPOSE_BASE,
+                DOCKER_COMPOSE_MAIN,
+                DOCKER_COMPOSE_METRICS,
+                DOCKER_COMPOSE_OTEL,
+                DOCKER_COMPOSE_WEB3,
+            ],
+            Some(ComposeOpts {
+                vsn: Some(vsn),
+                ..Default::default()
+            }),
+            Stdio::piped(),
+            Stdio::piped(),
+            Stdio::null(),
+            &msde_dir,
+        )?;
+
+        wait_child(
+            child,
+            &pb,
+            timeout,
+            &msde_dir,
+            "Failed to start services",
+            "Starting services timed out",
+        )
+        .await?;
+        let running = running_containers(docker).await?;
+        let mut started = 0;
+        for feature in ExtendedFeature::value_variants()
+            .iter()
+            // The bot runs in the MSDE container.
+            .filter(|feature| !matches!(feature, ExtendedFeature::Bot))
+        {
+            if running.contains_key(feature.wait_target()) {
+                started += 1;
+                pb.println(format!("✅ {feature} is running."));
+            } else {
+                pb.println(format!("➖ {feature} is not running."));
+            }
         }
+        if started == 0 {
+            pb.finish_with_message(
+                "❌ No services were started. Use the `up` command to create them.",
+            );
+            anyhow::bail!("no stopped services found");
+        }
+        pb.finish_with_message("✅ Stopped services resumed.");
         Ok(())
     }
 
     // FIXME: Too many arguments
+    #[allow(clippy::too_many_arguments)]
     pub async fn up_from_features<
         P: AsRef<Path>,
         F: Future<Output = anyhow::Result<()>>,
@@ -335,11 +356,31 @@ impl Pipeline {
         attach_future: Option<F>,
         import_hook: Option<G>,
         raw: bool,
+        only_base: bool,
+        web3_consumers: Option<u32>,
+        env: &[(String, String)],
+        bind_dirs: &BindDirs,
+        rpc_timeout: Duration,
     ) -> anyhow::Result<()> {
-        features.sort();
+        features.sort_by_key(Feature::startup_order);
+        anyhow::ensure!(
+            web3_consumers.is_none() || features.contains(&Feature::Web3),
+            "The number of Web3 consumers can only be set when the Web3 feature is enabled."
+        );
+        if !only_base {
+            warn_on_running_features(docker, features).await;
+        }
+
+        // The environment the enabled features require, explicitly passed variables take precedence.
+        let env: Vec<_> = features
+            .iter()
+            .flat_map(Feature::compose_env)
+            .chain(env.iter().cloned())
+            .collect();
+        let env = env.as_slice();
 
-        let volumes =
-            generate_volumes(features, &msde_dir).context("Failed to generate volume bindings")?;
+        let volumes = generate_volumes(features, &msde_dir, bind_dirs, true)
+            .context("Failed to generate volume bindings")?;
         let pb = progress_spinner(quiet || raw);
         pb.set_message("Booting base services..");
         let child = Compose::up_custom(
@@ -349,6 +390,9 @@ impl Pipeline {
                 target: None,
                 file_streamed_stdin: false,
                 build,
+                env,
+                vsn: Some(vsn),
+                ..Default::default()
             }),
             if raw {
                 Stdio::inherit()
@@ -364,7 +408,12 @@ impl Pipeline {
             &msde_dir,
         )?;
         wait_child_with_timeout(child, &pb, timeout, &msde_dir, "Base services").await?;
+        if only_base {
+            pb.finish_with_message("✅ Base services are ready.");
+            return Ok(());
+        }
 
+        // The bot is always the last by its startup order, and it takes over booting MSDE.
         let last_feature_idx = features.len().saturating_sub(1);
         let bot_enabled = features.iter().any(|f| matches!(f, Feature::Bot));
 
@@ -384,6 +433,12 @@ impl Pipeline {
                     },
                     file_streamed_stdin: i == last_feature_idx && bot_enabled,
                     build,
+                    scale: match (feature, web3_consumers) {
+                        (Feature::Web3, Some(n)) => vec![(WEB3_CONSUMER_SERVICE, n)],
+                        _ => vec![],
+                    },
+                    env,
+                    vsn: Some(vsn),
                 }),
                 if raw {
                     Stdio::inherit()
@@ -418,6 +473,9 @@ impl Pipeline {
                     target: Some("msde-vm-dev"),
                     file_streamed_stdin: true,
                     build,
+                    env,
+                    vsn: Some(vsn),
+                    ..Default::default()
                 }),
                 if raw {
                     Stdio::inherit()
@@ -460,7 +518,7 @@ impl Pipeline {
             let docker = docker.clone();
             handle = Some(tokio::spawn(async move {
                 tokio::time::sleep(Duration::from_secs(8)).await;
-                if let Err(e) = disable_otel(docker).await {
+                if let Err(e) = disable_otel(docker, rpc_timeout).await {
                     eprintln!("Failed to disable OTEL in MSDE: {e}");
                 }
             }));
@@ -509,20 +567,41 @@ impl Pipeline {
 }
 
 async fn wait_child_with_timeout<P: AsRef<Path>>(
-    mut child: Child,
+    child: Child,
     pb: &ProgressBar,
     timeout: u64,
     msde_dir: P,
     target: &str,
+) -> anyhow::Result<()> {
+    wait_child(
+        child,
+        pb,
+        timeout,
+        msde_dir,
+        &format!("Failed to start {target}"),
+        &format!("{target} timed out"),
+    )
+    .await?;
+    pb.finish_with_message(format!("✅ {target} started."));
+    Ok(())
+}
+
+/// Wait for a docker compose process to exit successfully. If it fails or doesn't finish in time, the progress bar is
+/// finished with the given message, and the output of the process is written to `log/output.log` of the project.
+async fn wait_child<P: AsRef<Path>>(
+    mut child: Child,
+    pb: &ProgressBar,
+    timeout: u64,
+    msde_dir: P,
+    failed: &str,
+    timed_out: &str,
 ) -> anyhow::Result<()> {
     tokio::select! {
         exc = child.wait() => {
             match exc {
-                Ok(status) if status.success() => {
-                    pb.finish_with_message(format!("✅ {target} started."))
-                },
+                Ok(status) if status.success() => {},
                 Ok(status) => {
-                    pb.finish_with_message(format!("❌ Failed to start {target}, stopping process.. (exit status {:?})", status.code().unwrap_or(1)));
+                    pb.finish_with_message(format!("❌ {failed}, stopping process.. (exit status {:?})", status.code().unwrap_or(1)));
                     let mut stdout = child.stdout.take().context("Failed to take child stdout")?;
                     let mut stderr = child.stderr.take().context("Failed to take child stderr")?;
                     let mut stdout_buf = vec![];
@@ -539,13 +618,13 @@ async fn wait_child_with_timeout<P: AsRef<Path>>(
                 },
                 Err(e) => {
                     // FIXME: Unclear from the documentation what happens here. Probably things go really wrong here, so we should just exit immediately.
-                    println!("{e}");
+                    eprintln!("{e}");
                     return Err(anyhow::Error::msg("Failed"));
                 }
             }
         },
         _ = tokio::time::sleep(std::time::Duration::from_secs(timeout)) => {
-            pb.finish_with_message(format!("❌ {target} timed out, stopping process.."));
+            pb.finish_with_message(format!("❌ {timed_out}, stopping process.."));
             child.start_kill()?;
             let result  = child.wait_with_output().await?;
             let log_path = write_failed_start_log(&msde_dir, &result.stdout, &result.stderr).await?;
@@ -600,9 +679,70 @@ pub fn progress_spinner(quiet: bool) -> ProgressBar {
     pb
 }
 
-fn generate_volumes(features: &[Feature], msde_dir: impl AsRef<Path>) -> anyhow::Result<String> {
-    let games_dir = msde_dir.as_ref().join("games");
-    let samples_dir = msde_dir.as_ref().join("samples");
+/// Resolve the host side of a bind mount to an absolute path, because Docker would treat relative paths and unresolved symlinks
+/// differently from what we mean. On WSL, paths on the Windows filesystem are known to silently mount as empty directories
+/// in some Docker setups, so we warn about them.
+fn bind_host_path(path: &Path) -> anyhow::Result<PathBuf> {
+    anyhow::ensure!(
+        path.is_dir(),
+        "`{}` is not a directory, Docker would mount an empty directory in its place",
+        path.display()
+    );
+    let resolved = path.canonicalize()?;
+    if crate::utils::on_windows_filesystem(&resolved) {
+        tracing::warn!(
+            path = %resolved.display(),
+            "The bind mount is on the Windows filesystem. It may show up as an empty directory inside the containers, it's highly recommended to move the project to the WSL filesystem."
+        );
+    }
+    Ok(resolved)
+}
+
+/// Same as [`bind_host_path`], but for the default directories of the project, which are created if they don't exist yet and
+/// `create` is set (e.g. a freshly initialized project has no `samples` directory).
+fn bind_default_path(path: &Path, create: bool) -> anyhow::Result<PathBuf> {
+    if create {
+        std::fs::create_dir_all(path)
+            .with_context(|| format!("Failed to create `{}`", path.display()))?;
+    } else {
+        anyhow::ensure!(
+            path.exists(),
+            "`{}` doesn't exist yet, it's created when the services are started",
+            path.display()
+        );
+    }
+    bind_host_path(path)
+}
+
+/// The host directories of the games and samples, bound into the services. Defaults to the `games` and `samples` directories of the project.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct BindDirs {
+    /// The directory of the games to bind into the services, if they are not in the project's `games` directory.
+    #[arg(long, env = "MERIGO_GAMES_DIR")]
+    pub games_dir: Option<PathBuf>,
+
+    /// The directory of the samples to bind into the services, if they are not in the project's `samples` directory.
+    #[arg(long, env = "MERIGO_SAMPLES_DIR")]
+    pub samples_dir: Option<PathBuf>,
+}
+
+/// Generate the compose file that binds the games and samples directories of the project into the services that need them.
+/// The default directories are only created with `create`, so that merely printing the bindings leaves the project untouched.
+pub fn generate_volumes(
+    features: &[Feature],
+    msde_dir: impl AsRef<Path>,
+    bind_dirs: &BindDirs,
+    create: bool,
+) -> anyhow::Result<String> {
+    // Only the directories given by the user are required to exist.
+    let games_dir = match &bind_dirs.games_dir {
+        Some(dir) => bind_host_path(dir)?,
+        None => bind_default_path(&msde_dir.as_ref().join("games"), create)?,
+    };
+    let samples_dir = match &bind_dirs.samples_dir {
+        Some(dir) => bind_host_path(dir)?,
+        None => bind_default_path(&msde_dir.as_ref().join("samples"), create)?,
+    };
     let volumes = vec![
         format!("{}:{MERIGO_GAMES_DIR}", games_dir.display()),
         format!("{}:{MERIGO_SAMPLE_DIR}", samples_dir.display()),
@@ -610,7 +750,7 @@ fn generate_volumes(features: &[Feature], msde_dir: impl AsRef<Path>) -> anyhow:
     let service = Service { volumes };
 
     let mut mapping = Services {
-        services: HashMap::new(),
+        services: BTreeMap::new(),
     };
     mapping.services.insert("compiler-vm-dev", service.clone());
     mapping.services.insert("msde-vm-dev", service.clone());
@@ -623,7 +763,7 @@ fn generate_volumes(features: &[Feature], msde_dir: impl AsRef<Path>) -> anyhow:
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct Services<'a> {
     #[serde(borrow)]
-    services: HashMap<&'a str, Service>,
+    services: BTreeMap<&'a str, Service>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -649,7 +789,86 @@ pub async fn running_containers(
         .collect())
 }
 
-pub async fn wait_until_heathy(docker: &docker_api::Docker, target_id: &str) -> anyhow::Result<()> {
+/// The features of the currently running stack, detected by their containers. Returns `None` if MSDE is not running.
+///
+/// Note: the bot feature runs in the MSDE container itself, so it cannot be detected, and it's never part of the result.
+pub async fn running_features(docker: &docker_api::Docker) -> anyhow::Result<Option<Vec<Feature>>> {
+    let containers = running_containers(docker).await?;
+    if !containers.contains_key(ExtendedFeature::MSDE.wait_target()) {
+        return Ok(None);
+    }
+    Ok(Some(
+        [Feature::Metrics, Feature::OTEL, Feature::Web3]
+            .into_iter()
+            .filter(|feature| {
+                containers.contains_key(ExtendedFeature::from(feature.clone()).wait_target())
+            })
+            .collect(),
+    ))
+}
+
+/// Warn if MSDE is already running with a different set of features than the requested one, because booting on top of it
+/// leaves the stack in an inconsistent state.
+async fn warn_on_running_features(docker: &docker_api::Docker, requested: &[Feature]) {
+    let running = match running_features(docker).await {
+        Ok(Some(running)) => running,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::debug!(error = %e, "failed to detect the running features");
+            return;
+        }
+    };
+    let mut requested: Vec<_> = requested
+        .iter()
+        .filter(|feature| **feature != Feature::Bot)
+        .cloned()
+        .collect();
+    requested.sort();
+    if running != requested {
+        let describe = |features: &[Feature]| {
+            if features.is_empty() {
+                String::from("none")
+            } else {
+                features
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        };
+        tracing::warn!(
+            running = %describe(&running),
+            requested = %describe(&requested),
+            "MSDE is already running with different features. The result may be an inconsistent mix of services, consider running `msde-cli down` first."
+        );
+    }
+}
+
+/// The reasons waiting for a container to become healthy may fail.
+#[derive(Debug, thiserror::Error)]
+pub enum HealthError {
+    #[error("container is unhealthy")]
+    Unhealthy,
+    #[error("health check is not defined for container")]
+    NoHealthcheck,
+    #[error("container did not become healthy in {0:?}")]
+    Timeout(Duration),
+    #[error("failed to inspect container: {0}")]
+    Inspect(#[from] docker_api::Error),
+}
+
+/// Poll the health of the container with an exponential backoff, until it's healthy or `timeout` has elapsed.
+pub async fn wait_until_heathy(
+    docker: &docker_api::Docker,
+    target_id: &str,
+    timeout: Duration,
+) -> Result<(), HealthError> {
+    let mut backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(250))
+        .with_max_interval(Duration::from_secs(4))
+        .with_max_elapsed_time(Some(timeout))
+        .build();
+    let mut last_status = None;
     loop {
         let health = docker
             .containers()
@@ -657,24 +876,107 @@ pub async fn wait_until_heathy(docker: &docker_api::Docker, target_id: &str) ->
             .inspect()
             .await?
             .state
-            .context("Failed to get container state")?
-            .health
-            .context("Failed to get container health")?
-            .status
-            .context("Failed to get container health status")?;
-
-        if health.as_str() == "healthy" {
-            break Ok(());
-        } else if health.as_str() == "unhealthy" {
-            break Err(anyhow::Error::msg("container failed to start"));
-        } else if health.as_str() == "none" {
-            break Err(anyhow::Error::msg("health check not defined for container"));
+            .and_then(|state| state.health)
+            .ok_or(HealthError::NoHealthcheck)?;
+        let status = health.status.unwrap_or_default();
+
+        match status.as_str() {
+            "healthy" => return Ok(()),
+            "unhealthy" => return Err(HealthError::Unhealthy),
+            "none" => return Err(HealthError::NoHealthcheck),
+            // Only log the status when it changes.
+            _ if last_status.as_ref() == Some(&status) => {}
+            "starting" => {
+                tracing::debug!(container = %target_id, "waiting for the health check to pass")
+            }
+            _ => tracing::debug!(container = %target_id, %status, "unexpected health status"),
         }
+        last_status = Some(status);
+
+        let Some(delay) = backoff.next_backoff() else {
+            return Err(HealthError::Timeout(timeout));
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// The outcome of waiting for a target with [`wait_for_targets`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetHealth {
+    Healthy,
+    /// The container is running, but it has no health check to wait for.
+    NoHealthcheck,
+    Unhealthy,
+    Timeout,
+    NotRunning,
+    Error,
+}
 
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+impl std::fmt::Display for TargetHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TargetHealth::Healthy => "healthy",
+            TargetHealth::NoHealthcheck => "running (no health check)",
+            TargetHealth::Unhealthy => "unhealthy",
+            TargetHealth::Timeout => "timed out",
+            TargetHealth::NotRunning => "not running",
+            TargetHealth::Error => "error",
+        })
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct TargetStatus {
+    pub target: ExtendedFeature,
+    pub status: TargetHealth,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TargetStatus {
+    /// Whether the target is ready to use. Targets without a health check are ready once they run.
+    pub fn is_ready(&self) -> bool {
+        matches!(
+            self.status,
+            TargetHealth::Healthy | TargetHealth::NoHealthcheck
+        )
+    }
+}
+
+/// Wait until every target is healthy, or `timeout` has elapsed. Targets that aren't running are not waited for.
+pub async fn wait_for_targets(
+    docker: &Docker,
+    targets: &[ExtendedFeature],
+    timeout: Duration,
+) -> anyhow::Result<Vec<TargetStatus>> {
+    let containers = running_containers(docker).await?;
+    Ok(
+        futures::future::join_all(targets.iter().map(|target| async {
+            let Some(id) = containers.get(target.wait_target()) else {
+                return TargetStatus {
+                    target: target.clone(),
+                    status: TargetHealth::NotRunning,
+                    error: None,
+                };
+            };
+            let (status, error) = match wait_until_heathy(docker, id, timeout).await {
+                Ok(()) => (TargetHealth::Healthy, None),
+                Err(HealthError::NoHealthcheck) => (TargetHealth::NoHealthcheck, None),
+                Err(HealthError::Unhealthy) => (TargetHealth::Unhealthy, None),
+                Err(HealthError::Timeout(_)) => (TargetHealth::Timeout, None),
+                Err(e @ HealthError::Inspect(_)) => (TargetHealth::Error, Some(e.to_string())),
+            };
+            TargetStatus {
+                target: target.clone(),
+                status,
+                error,
+            }
+        }))
+        .await,
+    )
+}
+
 pub async fn wait_with_timeout(docker: &docker_api::Docker, quiet: bool) -> anyhow::Result<()> {
     let containers = running_containers(docker).await?;
     let msde_id = containers
@@ -682,23 +984,23 @@ pub async fn wait_with_timeout(docker: &docker_api::Docker, quiet: bool) -> anyh
         .context("MSDE is not running somehow?")?;
     let pb = progress_spinner(quiet);
     pb.set_message("Waiting for MSDE to be healthy..");
-    tokio::select! {
-        _ = tokio::time::sleep(std::time::Duration::from_secs(60)) => {
+    match wait_until_heathy(docker, msde_id, Duration::from_secs(60)).await {
+        Ok(_) => pb.finish_with_message("✅ MSDE is healthy."),
+        Err(e @ HealthError::Timeout(_)) => {
             pb.finish_with_message("❌ MSDE health check timed out.");
-            return Err(anyhow::Error::msg("Failed"));
+            return Err(e.into());
         }
-        r = wait_until_heathy(docker, msde_id) => {
-            match r {
-                Ok(_) => pb.finish_with_message("✅ MSDE is healthy."),
-                Err(e) => { pb.finish_with_message("❌ MSDE health check failed."); tracing::error!(%e); return Err(anyhow::Error::msg(e)); }
-            }
+        Err(e) => {
+            pb.finish_with_message("❌ MSDE health check failed.");
+            tracing::error!(%e);
+            return Err(e.into());
         }
     }
     Ok(())
 }
 
 pub async fn web3_stop_consumers(docker: &Docker) -> anyhow::Result<()> {
-    let consumer_events = String::from("consumer_events");
+    let consumer_events = String::from(WEB3_CONSUMER_SERVICE);
     let containers = docker
         .containers()
         .list(&Default::default())
@@ -846,28 +1148,26 @@ pub async fn rewrite_sysconfig(
     let mut buffer = String::new();
     let _bytes_read = sys_config.read_to_string(&mut buffer)?;
 
-    if !features.contains(&Feature::OTEL) {
-        buffer = buffer.replace("{traces_exporter,otlp}", "{traces_exporter,none}");
+    let traces_exporter = if features.contains(&Feature::OTEL) {
+        "otlp"
     } else {
-        buffer = buffer.replace("{traces_exporter,none}", "{traces_exporter,otlp}");
-    }
-
-    if !features.contains(&Feature::Metrics) && !features.contains(&Feature::OTEL) {
-        buffer = buffer.replace("{stats,[{enable,true}]}", "{stats,[{enable,false}]}");
-    } else {
-        buffer = buffer.replace("{stats,[{enable,false}]}", "{stats,[{enable,true}]}");
-    }
-
-    if !features.contains(&Feature::Web3) {
-        buffer = buffer.replace(
-            "{evmlistener,[{enable,true}]}",
-            "{evmlistener,[{enable,false}]}",
-        );
-    } else {
-        buffer = buffer.replace(
-            "{evmlistener,[{enable,false}]}",
-            "{evmlistener,[{enable,true}]}",
-        );
+        "none"
+    };
+    let stats = features.contains(&Feature::Metrics) || features.contains(&Feature::OTEL);
+    let evmlistener = features.contains(&Feature::Web3);
+    for (path, value) in [
+        (&["traces_exporter"][..], traces_exporter),
+        (
+            &["stats", "enable"][..],
+            if stats { "true" } else { "false" },
+        ),
+        (
+            &["evmlistener", "enable"][..],
+            if evmlistener { "true" } else { "false" },
+        ),
+    ] {
+        buffer = sysconfig::set_value(&buffer, path, value)
+            .with_context(|| format!("Failed to update the sys.config of MSDE {vsn}"))?;
     }
 
     if let Err(e) = docker
@@ -889,8 +1189,8 @@ pub async fn rewrite_sysconfig(
     Ok(())
 }
 
-async fn disable_otel(docker: Docker) -> anyhow::Result<()> {
-    rpc(
+async fn disable_otel(docker: Docker, rpc_timeout: Duration) -> anyhow::Result<()> {
+    let output = rpc_typed(
         docker,
         r#"require Logger;
              Logger.warn("[OTEL] OpenTelemetry is disabled, killing related applications.") ;
@@ -898,6 +1198,8 @@ async fn disable_otel(docker: Docker) -> anyhow::Result<()> {
              :rpc.multicall(Sys.Cluster.msdeNodes(), KO, :kill_otel, []) ;
              Logger.warn("[OTEL] Done. If you need OpenTelemetry, rerun with the otel feature enabled.")
           "#,
+        rpc_timeout,
     ).await?;
+    tracing::debug!(output = %output, "disabled OpenTelemetry");
     Ok(())
 }
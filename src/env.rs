@@ -22,6 +22,7 @@ use crate::{
         DOCKER_COMPOSE_BOT, DOCKER_COMPOSE_METRICS, DOCKER_COMPOSE_OTEL, DOCKER_COMPOSE_WEB3,
     },
     hooks::Hooks,
+    utils::write_atomically,
     CONFIG_JSON, MERIGO_UPSTREAM_VERSION, METADATA_JSON,
 };
 use flate2::bufread::GzDecoder;
@@ -49,6 +50,24 @@ pub struct Config {
     #[serde(rename = "MERIGO_DEV_PACKAGE_DIR")]
     pub merigo_dev_package_dir: Option<PathBuf>,
     pub profiles: Profiles,
+    /// The most recently used project paths, the latest first.
+    #[serde(default)]
+    pub recent_projects: Vec<PathBuf>,
+    /// The project that was active before the current one, used by `set-project -`.
+    #[serde(default)]
+    pub previous_project: Option<PathBuf>,
+}
+
+/// The number of project paths remembered in [`Config::recent_projects`].
+pub const MAX_RECENT_PROJECTS: usize = 10;
+
+impl Config {
+    /// Move `project_path` to the front of the recent projects, forgetting the oldest ones over [`MAX_RECENT_PROJECTS`].
+    pub fn push_recent_project(&mut self, project_path: PathBuf) {
+        self.recent_projects.retain(|path| path != &project_path);
+        self.recent_projects.insert(0, project_path);
+        self.recent_projects.truncate(MAX_RECENT_PROJECTS);
+    }
 }
 
 // This is a helper that preserves *important* config values that are essential to deserialize, even if other things fail..
@@ -67,6 +86,9 @@ impl From<ConfigStatic> for Config {
     }
 }
 
+/// The profiles that are always present in a fresh config file.
+pub const BUILTIN_PROFILES: [&str; 3] = ["minimal", "default", "full"];
+
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 #[serde(transparent)]
 pub struct Profiles(pub HashMap<String, Vec<Feature>>);
@@ -78,12 +100,7 @@ impl Default for Profiles {
         hm.insert("default".into(), vec![Feature::Metrics, Feature::Web3]);
         hm.insert(
             "full".into(),
-            vec![
-                Feature::Metrics,
-                Feature::Web3,
-                Feature::OTEL,
-                Feature::Metrics,
-            ],
+            vec![Feature::Metrics, Feature::OTEL, Feature::Web3, Feature::Bot],
         );
 
         Self(hm)
@@ -96,6 +113,16 @@ pub struct ProfileSpec {
     features: Vec<Feature>,
 }
 
+/// The default of the `--timeout` flag in seconds, if neither the flag nor the project's `default_timeout` is set.
+pub const DEFAULT_TIMEOUT: u64 = 300;
+
+/// The Elastic stack version used by the OTEL feature, unless overridden by the `STACK_VERSION` environment variable.
+const DEFAULT_STACK_VERSION: &str = "8.7.1";
+
+fn stack_version() -> String {
+    std::env::var("STACK_VERSION").unwrap_or_else(|_| String::from(DEFAULT_STACK_VERSION))
+}
+
 #[derive(
     serde::Deserialize,
     serde::Serialize,
@@ -127,14 +154,21 @@ impl Feature {
         }
     }
 
+    /// The environment variables the compose files of this feature need.
+    pub fn compose_env(&self) -> Vec<(String, String)> {
+        match self {
+            Feature::OTEL => vec![(String::from("STACK_VERSION"), stack_version())],
+            Feature::Metrics | Feature::Web3 | Feature::Bot => vec![],
+        }
+    }
+
     pub fn required_images_and_tags(&self) -> Vec<(String, String)> {
         match self {
             Feature::Metrics => {
                 vec![(String::from("prom/prometheus"), String::from("v2.45.0"))]
             }
             Feature::OTEL => {
-                let stack_version =
-                    std::env::var("STACK_VERSION").unwrap_or_else(|_| String::from("8.7.1"));
+                let stack_version = stack_version();
                 vec![
                     (
                         String::from("otel/opentelemetry-collector-contrib"),
@@ -219,6 +253,17 @@ impl From<Feature> for ExtendedFeature {
 }
 
 impl Feature {
+    /// The position of the feature in the startup sequence, lower values are booted first. The bot must always be started
+    /// last, because its compose file also boots MSDE.
+    pub fn startup_order(&self) -> u8 {
+        match self {
+            Feature::Metrics => 0,
+            Feature::OTEL => 1,
+            Feature::Web3 => 2,
+            Feature::Bot => u8::MAX,
+        }
+    }
+
     pub fn to_target(&self) -> &'static str {
         match self {
             Feature::OTEL => DOCKER_COMPOSE_OTEL,
@@ -229,6 +274,64 @@ impl Feature {
     }
 }
 
+/// A single element of the `--features` argument: `all`, a feature name, or a feature name prefixed with `-` to exclude it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeatureSelector {
+    All,
+    Include(Feature),
+    Exclude(Feature),
+}
+
+impl std::str::FromStr for FeatureSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_feature = |name: &str| {
+            Feature::from_str(name, true).map_err(|_| {
+                let possible = Feature::value_variants()
+                    .iter()
+                    .map(|feature| feature.to_string().to_lowercase())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                anyhow::anyhow!("unknown feature `{name}`, possible values are: all, {possible}")
+            })
+        };
+        match s.trim() {
+            "all" => Ok(Self::All),
+            s => match s.strip_prefix('-') {
+                Some(name) => parse_feature(name).map(Self::Exclude),
+                None => parse_feature(s).map(Self::Include),
+            },
+        }
+    }
+}
+
+impl FeatureSelector {
+    /// Resolve the selectors to the list of features. Exclusions always win, regardless of their position, and if only
+    /// exclusions are given, they are subtracted from all features. For example `all,-bot` and `-bot` both mean every feature but bot.
+    pub fn resolve(selectors: &[FeatureSelector]) -> Vec<Feature> {
+        let only_exclusions = !selectors.is_empty()
+            && selectors
+                .iter()
+                .all(|selector| matches!(selector, Self::Exclude(_)));
+        let mut features: Vec<Feature> = selectors
+            .iter()
+            .flat_map(|selector| match selector {
+                Self::All => Feature::value_variants().to_vec(),
+                Self::Include(feature) => vec![feature.clone()],
+                Self::Exclude(_) => vec![],
+            })
+            .collect();
+        if only_exclusions {
+            features = Feature::value_variants().to_vec();
+        }
+        features.sort();
+        features.dedup();
+        features.retain(|feature| !selectors.contains(&Self::Exclude(feature.clone())));
+        features
+    }
+}
+
 #[derive(Debug)]
 pub struct Context {
     pub home: PathBuf,
@@ -245,13 +348,43 @@ pub struct PackageLocalConfig {
     pub self_version: String,
     pub timestamp: i64,
     pub hooks: Option<Hooks>,
+    /// The project-wide default of the `--timeout` flag, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_timeout: Option<u64>,
+    /// The features of the last successful `up` or `run`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_features: Option<Vec<Feature>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl PackageLocalConfig {
+    /// Resolve the `--timeout` of the commands that wait for docker compose. An explicitly given value (from the flag or the
+    /// `MERIGO_TIMEOUT` environment variable) wins, then the project's `default_timeout`, then [`DEFAULT_TIMEOUT`].
+    pub fn resolve_timeout(&self, explicit: Option<u64>) -> u64 {
+        explicit.or(self.default_timeout).unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    /// The MSDE release the project targets. Projects without one recorded target the release this tool was built for.
+    pub fn target_version(&self) -> &str {
+        self.target_msde_version
+            .as_deref()
+            .unwrap_or(MERIGO_UPSTREAM_VERSION)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct Authorization {
     pub token: String,
 }
 
+// The context is traced on startup, so never print the token itself.
+impl std::fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Authorization")
+            .field("token", &crate::redact::mask(&self.token))
+            .finish()
+    }
+}
+
 impl Context {
     pub fn from_env() -> anyhow::Result<Self> {
         let home = home()?;
@@ -310,8 +443,31 @@ impl Context {
         std::fs::remove_dir_all(&self.config_dir).unwrap();
     }
 
+    pub fn write_profiles(&self, name: String, mut features: Vec<Feature>) -> anyhow::Result<()> {
+        let requested = features.len();
+        features.sort();
+        features.dedup();
+        if features.len() < requested {
+            tracing::warn!(profile = %name, "Duplicate features were removed from the profile.");
+        }
+        self.update_profiles(|profiles| {
+            profiles
+                .0
+                .entry(name)
+                .and_modify(|f| f.clone_from(&features))
+                .or_insert(features);
+        })
+    }
+
+    /// Remove a profile from the config file. Returns whether the profile existed.
+    pub fn remove_profile(&self, name: &str) -> anyhow::Result<bool> {
+        let mut removed = false;
+        self.update_profiles(|profiles| removed = profiles.0.remove(name).is_some())?;
+        Ok(removed)
+    }
+
     // If the file is broken (maybe it uses the older scheme) this function handles that migration part too.
-    pub fn write_profiles(&self, name: String, features: Vec<Feature>) -> anyhow::Result<()> {
+    fn update_profiles(&self, update: impl FnOnce(&mut Profiles)) -> anyhow::Result<()> {
         let config_file = self.config_dir.join(CONFIG_JSON);
         let mut f = std::fs::OpenOptions::new()
             .write(true)
@@ -321,29 +477,17 @@ impl Context {
         let mut buf = String::new();
         let _bytes_read = f.read_to_string(&mut buf)?;
 
-        let cfg = match serde_json::from_str::<Config>(&buf) {
-            Ok(mut cfg) => {
-                cfg.profiles
-                    .0
-                    .entry(name)
-                    .and_modify(|f| f.clone_from(&features))
-                    .or_insert(features);
-                cfg
-            }
+        let mut cfg = match serde_json::from_str::<Config>(&buf) {
+            Ok(cfg) => cfg,
             Err(_) => match serde_json::from_str::<ConfigStatic>(&buf) {
-                Ok(cfg_static) => {
-                    let mut cfg = Config::from(cfg_static);
-                    cfg.profiles.0.insert(name, features);
-                    cfg
-                }
+                Ok(cfg_static) => Config::from(cfg_static),
                 Err(e) => {
                     tracing::warn!(error = %e, "Invalid config file format, failed to preserve project path.");
-                    let mut cfg = Config::default();
-                    cfg.profiles.0.insert(name, features);
-                    cfg
+                    Config::default()
                 }
             },
         };
+        update(&mut cfg.profiles);
 
         let f = std::fs::OpenOptions::new()
             .write(true)
@@ -356,6 +500,27 @@ impl Context {
         Ok(())
     }
 
+    /// The profiles from the config file, or the built-in ones if there's no valid config file.
+    pub fn profiles(&self) -> Profiles {
+        self.config
+            .as_ref()
+            .map(|cfg| cfg.profiles.clone())
+            .unwrap_or_default()
+    }
+
+    /// The most recently used project paths, the latest first.
+    pub fn recent_projects(&self) -> &[PathBuf] {
+        self.config
+            .as_ref()
+            .map(|cfg| cfg.recent_projects.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The project that was active before the current one.
+    pub fn previous_project(&self) -> Option<&PathBuf> {
+        self.config.as_ref()?.previous_project.as_ref()
+    }
+
     pub fn write_config(&self, project_path: PathBuf) -> anyhow::Result<()> {
         std::fs::create_dir_all(&self.config_dir)?;
         let config_file = self.config_dir.join(CONFIG_JSON);
@@ -367,11 +532,18 @@ impl Context {
 
         let mut writer = std::io::BufWriter::new(f);
 
+        let mut config = self.config.clone().unwrap_or_default();
+        config.push_recent_project(project_path.clone());
+        if let Some(current) = config.merigo_dev_package_dir.take() {
+            if current != project_path {
+                config.previous_project = Some(current);
+            }
+        }
         serde_json::to_writer(
             &mut writer,
             &Config {
                 merigo_dev_package_dir: Some(project_path),
-                ..self.config.clone().unwrap_or_default()
+                ..config
             },
         )?;
         writer.flush()?;
@@ -403,6 +575,8 @@ impl Context {
                     pre_run: vec![],
                     post_run: vec![],
                 }),
+                default_timeout: None,
+                last_features: None,
             },
         )?;
         writer.flush()?;
@@ -413,40 +587,47 @@ impl Context {
         &self,
         self_version: semver::Version,
     ) -> anyhow::Result<()> {
-        let msde_dir = self
-            .msde_dir
-            .as_ref()
-            .context("Package location is unknown")?;
-        let config_file = msde_dir.join(METADATA_JSON);
-        let mut f = std::fs::OpenOptions::new().read(true).open(&config_file)?;
+        self.update_package_local_config(|config| {
+            config.self_version = self_version.to_string();
+            config.timestamp = time::OffsetDateTime::now_utc().unix_timestamp();
+        })
+    }
 
-        let mut buf = String::new();
-        f.read_to_string(&mut buf)?;
-        let current: PackageLocalConfig = serde_json::from_str(&buf)?;
+    /// Remember the features of the last successful `up` or `run`, so `restart` can reuse them.
+    pub fn write_last_features(&self, features: &[Feature]) -> anyhow::Result<()> {
+        self.update_package_local_config(|config| config.last_features = Some(features.to_vec()))
+    }
 
-        let f = std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&config_file)?;
-        let mut writer = std::io::BufWriter::new(f);
+    pub fn clear_last_features(&self) -> anyhow::Result<()> {
+        self.update_package_local_config(|config| config.last_features = None)
+    }
 
-        serde_json::to_writer(
-            &mut writer,
-            &PackageLocalConfig {
-                self_version: self_version.to_string(),
-                ..current
-            },
-        )?;
-        writer.flush()?;
-        Ok(())
+    fn update_package_local_config(
+        &self,
+        update: impl FnOnce(&mut PackageLocalConfig),
+    ) -> anyhow::Result<()> {
+        let Some(msde_dir) = self.msde_dir.as_ref() else {
+            anyhow::bail!("cannot update {METADATA_JSON}: no project is set, run `msde-cli init` or `msde-cli set-project` first")
+        };
+        let config_file = msde_dir.join(METADATA_JSON);
+        let buf = fs::read_to_string(&config_file)
+            .with_context(|| format!("failed to open `{}`", config_file.display()))?;
+        let mut config: PackageLocalConfig = serde_json::from_str(&buf)
+            .with_context(|| format!("`{}` is invalid", config_file.display()))?;
+        update(&mut config);
+
+        // This runs after every `up` and `run`, an interrupted write must not leave a broken project behind.
+        write_atomically(&config_file, &serde_json::to_vec(&config)?)
     }
 
-    fn save_stages_yml(msde_dir: impl AsRef<Path>) -> anyhow::Result<String> {
+    fn save_stages_yml(msde_dir: impl AsRef<Path>) -> anyhow::Result<Option<String>> {
         let stages_file = msde_dir.as_ref().join("games/stages.yml");
-        let mut buf = String::new();
-        let mut f = std::fs::File::open(stages_file)?;
-        f.read_to_string(&mut buf)?;
-        Ok(buf)
+        match fs::read_to_string(stages_file) {
+            Ok(buf) => Ok(Some(buf)),
+            // Nothing to preserve, the packaged one is used.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
     fn restore_stages_yml(msde_dir: impl AsRef<Path>, content: String) -> anyhow::Result<()> {
@@ -461,27 +642,55 @@ impl Context {
         Ok(())
     }
 
+    /// Extracts the packaged project files over the active project. The archive only contains `docker/`, `README.md`
+    /// and `games/stages.yml`, and the latter is restored afterwards, so the user's games are left untouched.
     pub fn unpack_project_files(&self) -> anyhow::Result<()> {
         let Some(msde_dir) = self.msde_dir.as_ref() else {
-            anyhow::bail!("project must be set")
+            anyhow::bail!("cannot unpack the project files: no project is set, run `msde-cli init` or `msde-cli set-project` first")
         };
         // Note: A drop guard may be safer, but that's an overkill I think.
         let stages_yml = Self::save_stages_yml(msde_dir)
             .context("failed to save games/stages.yml file content")?;
         let mut archive = tar::Archive::new(GzDecoder::new(crate::PACKAGE));
 
-        archive.unpack(&msde_dir).with_context(|| {
+        archive.unpack(msde_dir).with_context(|| {
             format!(
                 "Failed to upgrade project at directory `{}`",
                 msde_dir.display()
             )
         })?;
 
-        Self::restore_stages_yml(msde_dir, stages_yml)?;
+        if let Some(stages_yml) = stages_yml {
+            Self::restore_stages_yml(msde_dir, stages_yml)?;
+        }
 
         Ok(())
     }
 
+    /// Same as [`PackageLocalConfig::resolve_timeout`], for commands that don't load the project otherwise. The project's
+    /// `metadata.json` is only read if no timeout is given explicitly.
+    pub fn resolve_timeout(&self, explicit: Option<u64>) -> anyhow::Result<u64> {
+        if let Some(timeout) = explicit {
+            return Ok(timeout);
+        }
+        Ok(self
+            .package_local_config()?
+            .map_or(DEFAULT_TIMEOUT, |metadata| metadata.resolve_timeout(None)))
+    }
+
+    /// Parse the project's `metadata.json`, without the checks of [`Context::run_project_checks`]. `None` if no project is set.
+    pub fn package_local_config(&self) -> anyhow::Result<Option<PackageLocalConfig>> {
+        let Some(msde_dir) = self.msde_dir.as_ref() else {
+            return Ok(None);
+        };
+        let metadata_file = msde_dir.join(METADATA_JSON);
+        let f = fs::read_to_string(&metadata_file)
+            .with_context(|| format!("failed to open `{}`", metadata_file.display()))?;
+        serde_json::from_str(&f)
+            .map(Some)
+            .with_context(|| format!("`{}` is invalid", metadata_file.display()))
+    }
+
     pub fn set_project_path(&mut self, project_path: impl AsRef<Path>) {
         self.msde_dir = Some(project_path.as_ref().to_path_buf())
     }
@@ -524,3 +733,100 @@ pub enum ProjectCheckErrors {
     #[error("Invalid project version in metadata.json")]
     InvalidVersion(#[from] semver::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_profiles_are_well_formed() {
+        let profiles = Profiles::default();
+        let mut names: Vec<_> = profiles.0.keys().map(String::as_str).collect();
+        names.sort();
+        let mut expected = BUILTIN_PROFILES.to_vec();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        for (name, features) in &profiles.0 {
+            let mut normalized = features.clone();
+            normalized.sort();
+            normalized.dedup();
+            assert_eq!(
+                features, &normalized,
+                "profile `{name}` is not sorted or has duplicates"
+            );
+        }
+        assert_eq!(profiles.0["minimal"], vec![]);
+        assert_eq!(profiles.0["full"], Feature::value_variants().to_vec());
+    }
+
+    #[test]
+    fn parses_feature_selectors() {
+        let parse = |s: &str| s.parse::<FeatureSelector>();
+        assert_eq!(parse("all").unwrap(), FeatureSelector::All);
+        assert_eq!(
+            parse(" web3 ").unwrap(),
+            FeatureSelector::Include(Feature::Web3)
+        );
+        assert_eq!(
+            parse("OTEL").unwrap(),
+            FeatureSelector::Include(Feature::OTEL)
+        );
+        assert_eq!(
+            parse("-bot").unwrap(),
+            FeatureSelector::Exclude(Feature::Bot)
+        );
+        let error = parse("grafana").unwrap_err().to_string();
+        assert!(error.contains("unknown feature `grafana`"), "{error}");
+        assert!(parse("-grafana").is_err());
+        assert!(parse("-all").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn resolves_feature_selectors() {
+        let resolve = |s: &str| {
+            let selectors = s
+                .split(',')
+                .map(|s| s.parse().unwrap())
+                .collect::<Vec<FeatureSelector>>();
+            FeatureSelector::resolve(&selectors)
+        };
+        let all_but_bot = vec![Feature::Metrics, Feature::OTEL, Feature::Web3];
+        assert_eq!(resolve("all,-bot"), all_but_bot);
+        // Exclusions win regardless of their position.
+        assert_eq!(resolve("-bot,all"), all_but_bot);
+        // Exclusions on their own are taken from every feature.
+        assert_eq!(resolve("-bot"), all_but_bot);
+        assert_eq!(resolve("-bot,-web3"), vec![Feature::Metrics, Feature::OTEL]);
+        // Any inclusion means only the included features are enabled.
+        assert_eq!(resolve("web3,-bot"), vec![Feature::Web3]);
+        assert_eq!(resolve("bot,web3,bot"), vec![Feature::Web3, Feature::Bot]);
+        assert_eq!(resolve("all,metrics"), Feature::value_variants().to_vec());
+        assert_eq!(resolve("bot,-bot"), vec![]);
+        assert_eq!(FeatureSelector::resolve(&[]), vec![]);
+    }
+
+    #[test]
+    fn recent_projects_are_deduplicated_and_bounded() {
+        let mut config = Config::default();
+        for i in 0..MAX_RECENT_PROJECTS + 2 {
+            config.push_recent_project(PathBuf::from(format!("/projects/{i}")));
+        }
+        config.push_recent_project(PathBuf::from("/projects/5"));
+        assert_eq!(config.recent_projects.len(), MAX_RECENT_PROJECTS);
+        assert_eq!(config.recent_projects[0], PathBuf::from("/projects/5"));
+        assert_eq!(config.recent_projects[1], PathBuf::from("/projects/11"));
+        assert_eq!(
+            config
+                .recent_projects
+                .iter()
+                .filter(|path| path.ends_with("5"))
+                .count(),
+            1
+        );
+        assert!(!config
+            .recent_projects
+            .contains(&PathBuf::from("/projects/0")));
+    }
+}
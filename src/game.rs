@@ -2,7 +2,7 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
@@ -14,13 +14,16 @@ use docker_api::{
     Docker, Exec,
 };
 use futures::{stream, StreamExt};
+use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
 use uuid::Uuid;
 
 use crate::{
     compose::{progress_spinner, running_containers},
     env::Context,
-    parsing::{parse_simple_tuple, ElixirTuple, OkVariant},
+    parsing::{parse_simple_tuple, parse_tuple_lenient, strip_ansi, ElixirTuple, OkVariant},
+    utils::write_atomically,
 };
 
 pub const RPC_START_SEQUENCE: &str = "\u{1}\0\0\0\0\0\0\u{8}";
@@ -85,30 +88,406 @@ pub struct Data {}
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Analytics {}
 
-pub fn create_game() -> anyhow::Result<()> {
-    // Plan:
-    // 1. Compile the template game into the CLI tool.
-    // 2. Check whether the path is free (or --force) and copy over stuff.. probably generate new UUIDs.
-    // 3. Check whether the stages.yml exists, and update or create it with the new game.
-    // 4. Client code? Probably just skip now. Longer term we may need to initialize that too and swap IDs.
-    // 5. Trigger a fresh load if MSDE is running. (load_games function..)
-    // 6. Create the game config as string, and trigger an import in MSDE.
+/// How the ids of a new game stage are chosen in [`create_game`].
+#[derive(Debug, Default, Clone)]
+pub struct CreateGameOpts {
+    /// The guid of the game. Takes precedence over `new_guid`.
+    pub guid: Option<Uuid>,
+    /// The suid of the stage.
+    pub suid: Option<Uuid>,
+    /// Derive the ids that aren't given from the game and stage names, instead of generating random ones.
+    pub deterministic: bool,
+    /// Don't reuse the guid of the game's existing stages.
+    pub new_guid: bool,
+}
+
+/// Create a new game stage from `template` (a gzipped tarball) at `games_dir/<game>/<stage>`, and register it in
+/// `games_dir/stages.yml`. If the game already has a stage, the new stage reuses its guid unless `guid` or `new_guid` is
+/// given.
+///
+/// Either everything is created or nothing: the config files are replaced atomically, and the unpacked stage directory is
+/// removed again on any error.
+// TODO: Trigger a fresh load and an import of the new game if MSDE is running.
+pub fn create_game(
+    games_dir: &Path,
+    template: &[u8],
+    game: &str,
+    stage: &str,
+    opts: &CreateGameOpts,
+) -> anyhow::Result<()> {
+    validate_game_name("game", game)?;
+    validate_game_name("stage", stage)?;
+    let target = games_dir.join(game).join(stage);
+    if target.exists() {
+        anyhow::bail!("A game with name combination '{game}/{stage}' already exists.")
+    }
+
+    let mut archive = tar::Archive::new(flate2::bufread::GzDecoder::new(template));
+    let unpacked = archive.unpack(&target).with_context(|| {
+        format!(
+            "Failed to initialize a new game at directory `{}`",
+            target.display()
+        )
+    });
+    let result = unpacked.and_then(|_| register_game(games_dir, &target, game, stage, opts));
+    if result.is_err() {
+        if let Err(error) = fs::remove_dir_all(&target) {
+            tracing::warn!(%error, path = %target.display(), "failed to clean up the partially created game");
+        }
+        // Only succeeds if the game has no other stages.
+        let _ = fs::remove_dir(games_dir.join(game));
+    }
+    result
+}
+
+/// The namespace of the UUIDv5 ids of games created with `--deterministic`. The guid is derived from the game name, and the
+/// suid from `game/stage`.
+const GAME_ID_NAMESPACE: Uuid = Uuid::from_u128(0x3f6c_1a5e_8b2d_4c71_9e04_d2a7_5b18_c6e9);
+
+fn register_game(
+    games_dir: &Path,
+    target: &Path,
+    game: &str,
+    stage: &str,
+    opts: &CreateGameOpts,
+) -> anyhow::Result<()> {
+    let new_id = |name: &str| {
+        if opts.deterministic {
+            Uuid::new_v5(&GAME_ID_NAMESPACE, name.as_bytes())
+        } else {
+            Uuid::new_v4()
+        }
+    };
+    let stages_path = games_dir.join("stages.yml");
+    let stages = fs::read_to_string(&stages_path)
+        .context("games/stages.yml file doesn't exist, but it should..")?;
+    let mut stages_cfg = serde_yaml::from_str::<PackageStagesConfig>(&stages)
+        .context("Failed to deserialize stages.yml")?;
+    let existing_game = if opts.new_guid {
+        None
+    } else {
+        stages_cfg.try_find_guid_in(game)
+    };
+    let guid = match opts.guid {
+        Some(guid) => guid,
+        None => match existing_game {
+            Some(existing_local_cfg) => {
+                match fs::read_to_string(games_dir.join(existing_local_cfg)) {
+                    Ok(local_config) => {
+                        serde_yaml::from_str::<PackageLocalConfig>(&local_config)
+                            .with_context(|| {
+                                format!("`{}` is invalid", existing_local_cfg.display())
+                            })?
+                            .guid
+                    }
+                    Err(_) => new_id(game),
+                }
+            }
+            None => new_id(game),
+        },
+    };
+    let new_stages = append_stage_entry(
+        &stages,
+        &mut stages_cfg,
+        PackageConfigEntry {
+            config: PathBuf::from(format!("{game}/{stage}/local_config.yml")),
+            scripts: PathBuf::from(format!("{game}/{stage}/scripts")),
+            tuning: PathBuf::from(format!("{game}/{stage}/tuning")),
+            disabled: Some(false),
+        },
+    )?;
+
+    let local_config_path = target.join("local_config.yml");
+    let local_config = fs::read_to_string(&local_config_path)?;
+    let mut local_cfg = serde_yaml::from_str::<PackageLocalConfig>(&local_config)?;
+    local_cfg.game = game.to_owned();
+    local_cfg.stage = stage.to_owned();
+    local_cfg.guid = guid;
+    local_cfg.suid = opts
+        .suid
+        .unwrap_or_else(|| new_id(&format!("{game}/{stage}")));
+
+    // The new stage's own config first: until stages.yml references it, it's not part of the project anyway.
+    write_atomically(
+        &local_config_path,
+        serde_yaml::to_string(&local_cfg)?.as_bytes(),
+    )?;
+    write_atomically(&stages_path, new_stages.as_bytes())?;
+
+    if let Err(e) = verify_new_stage(games_dir, game, stage, &local_cfg) {
+        write_atomically(&stages_path, stages.as_bytes())?;
+        return Err(
+            e.context("The new stage is inconsistent with the project, the changes are reverted")
+        );
+    }
+    Ok(())
+}
+
+/// Read back the written files, and check that the new stage has the expected ids, which are consistent with the rest of the
+/// project. Problems of the other stages are not reported here, `validate` is there for that.
+fn verify_new_stage(
+    games_dir: &Path,
+    game: &str,
+    stage: &str,
+    expected: &PackageLocalConfig,
+) -> anyhow::Result<()> {
+    let config = PathBuf::from(format!("{game}/{stage}/local_config.yml"));
+    let stages = serde_yaml::from_str::<PackageStagesConfig>(&fs::read_to_string(
+        games_dir.join("stages.yml"),
+    )?)?;
+    let ids = stages
+        .0
+        .into_iter()
+        .filter_map(|entry| {
+            let local_config = fs::read_to_string(games_dir.join(&entry.config)).ok()?;
+            let local_config = serde_yaml::from_str::<PackageLocalConfig>(&local_config).ok()?;
+            Some(StageIds {
+                config: entry.config,
+                game: local_config.game,
+                stage: local_config.stage,
+                guid: local_config.guid,
+                suid: local_config.suid,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let Some(new) = ids.iter().position(|ids| ids.config == config) else {
+        anyhow::bail!("`{}` is not referenced in stages.yml", config.display())
+    };
+    anyhow::ensure!(
+        ids[new].guid == expected.guid && ids[new].suid == expected.suid,
+        "`{}` has guid {} and suid {}, but {} and {} were written",
+        config.display(),
+        ids[new].guid,
+        ids[new].suid,
+        expected.guid,
+        expected.suid
+    );
+    let problems = check_stage_ids(&ids)
+        .into_iter()
+        .filter(|(i, _)| *i == new)
+        .map(|(_, problem)| problem)
+        .collect::<Vec<_>>();
+    anyhow::ensure!(problems.is_empty(), "{}", problems.join(", "));
     Ok(())
 }
 
+/// Append `entry` to the `stages.yml` content `stages`, parsed as `stages_cfg`. The existing content is kept verbatim, so
+/// the comments and the formatting of the file are preserved. Files that can't be appended to textually (like flow style
+/// lists) are serialized again as a whole.
+fn append_stage_entry(
+    stages: &str,
+    stages_cfg: &mut PackageStagesConfig,
+    entry: PackageConfigEntry,
+) -> anyhow::Result<String> {
+    let block = serde_yaml::to_string(&[&entry])?;
+    stages_cfg.0.push(entry);
+
+    let is_content = |line: &&str| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#') && line != "---"
+    };
+    let first = stages.lines().find(is_content).unwrap_or_default();
+    let appended = match first.trim() {
+        // An empty file, or an explicit empty list.
+        "" | "[]" => {
+            let mut lines = stages
+                .lines()
+                .filter(|line| line.trim() != "[]")
+                .map(|line| format!("{line}\n"))
+                .collect::<String>();
+            lines.push_str(&block);
+            Some(lines)
+        }
+        trimmed if trimmed.starts_with("- ") => {
+            let indent = &first[..first.len() - first.trim_start().len()];
+            let mut appended = stages.to_owned();
+            if !appended.is_empty() && !appended.ends_with('\n') {
+                appended.push('\n');
+            }
+            for line in block.lines() {
+                appended.push_str(indent);
+                appended.push_str(line);
+                appended.push('\n');
+            }
+            Some(appended)
+        }
+        _ => None,
+    };
+    // Only keep the textual edit if it means the same as the updated config.
+    match appended {
+        Some(appended)
+            if serde_yaml::from_str::<PackageStagesConfig>(&appended)
+                .is_ok_and(|parsed| parsed == *stages_cfg) =>
+        {
+            Ok(appended)
+        }
+        _ => {
+            tracing::debug!("stages.yml can't be appended to, it's rewritten as a whole");
+            Ok(serde_yaml::to_string(stages_cfg)?)
+        }
+    }
+}
+
+/// Remove the entries matching `remove` from the `stages.yml` content `stages`, parsed as `stages_cfg`. Like
+/// [`append_stage_entry`], the rest of the content is kept verbatim: only the lines of the removed entries are dropped, the
+/// comments between the entries stay. Files that can't be edited textually are serialized again as a whole.
+pub fn remove_stage_entries(
+    stages: &str,
+    stages_cfg: &mut PackageStagesConfig,
+    remove: impl Fn(&PackageConfigEntry) -> bool,
+) -> anyhow::Result<String> {
+    let removed = stages_cfg.0.iter().map(&remove).collect::<Vec<_>>();
+    stages_cfg.0.retain(|entry| !remove(entry));
+
+    let is_content = |line: &&str| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#') && line != "---"
+    };
+    let first = stages.lines().find(is_content).unwrap_or_default();
+    let edited = if first.trim_start().starts_with("- ") {
+        let indent = first.len() - first.trim_start().len();
+        let mut edited = String::with_capacity(stages.len());
+        // The index of the entry the current line belongs to, if it's part of an entry at all.
+        let mut current = None;
+        let mut entries = 0;
+        for line in stages.lines() {
+            let line_indent = line.len() - line.trim_start().len();
+            if line_indent == indent && line.trim_start().starts_with("- ") {
+                current = Some(entries);
+                entries += 1;
+            } else if line.trim().is_empty() || line_indent <= indent {
+                // Blank lines and comments outside of the entries are kept.
+                current = None;
+            }
+            if !current.is_some_and(|index| removed.get(index).copied().unwrap_or_default()) {
+                edited.push_str(line);
+                edited.push('\n');
+            }
+        }
+        if stages_cfg.0.is_empty() {
+            edited.push_str("[]\n");
+        }
+        (entries == removed.len()).then_some(edited)
+    } else {
+        None
+    };
+    // Only keep the textual edit if it means the same as the updated config.
+    match edited {
+        Some(edited)
+            if serde_yaml::from_str::<PackageStagesConfig>(&edited)
+                .is_ok_and(|parsed| parsed == *stages_cfg) =>
+        {
+            Ok(edited)
+        }
+        _ => {
+            tracing::debug!("stages.yml can't be edited in place, it's rewritten as a whole");
+            Ok(serde_yaml::to_string(stages_cfg)?)
+        }
+    }
+}
+
+/// The default value of the `--rpc-timeout` flag.
+pub const DEFAULT_RPC_TIMEOUT_SECS: u64 = 120;
+
+/// The container of the MSDE node that RPC calls go to by default.
+pub const MSDE_CONTAINER: &str = "msde-vm-dev";
+
+/// Run the RPC on the default MSDE node. The call fails if it doesn't finish within `timeout`.
 pub async fn rpc(
     docker: docker_api::Docker,
     cmd: impl Into<Cow<'_, str>>,
+    timeout: Duration,
+) -> anyhow::Result<String> {
+    rpc_on(docker, MSDE_CONTAINER, cmd, timeout).await
+}
+
+/// The names of the running containers of the MSDE nodes, e.g. `msde-vm-dev` and its clustered replicas.
+pub async fn msde_node_containers(docker: &docker_api::Docker) -> anyhow::Result<Vec<String>> {
+    let mut nodes: Vec<_> = running_containers(docker)
+        .await?
+        .into_keys()
+        .map(|name| name.trim_start_matches('/').to_owned())
+        .filter(|name| name.starts_with(MSDE_CONTAINER))
+        .collect();
+    nodes.sort();
+    Ok(nodes)
+}
+
+/// Run the RPC on the node in the given container, instead of the default MSDE node.
+pub async fn rpc_on(
+    docker: docker_api::Docker,
+    container: &str,
+    cmd: impl Into<Cow<'_, str>>,
+    timeout: Duration,
+) -> anyhow::Result<String> {
+    tokio::time::timeout(timeout, rpc_inner(docker, container, cmd.into()))
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "RPC timed out after {}s, the MSDE node may be stuck. Use `--rpc-timeout` to wait longer.",
+                timeout.as_secs()
+            )
+        })?
+}
+
+/// The longest command `msde rpc` accepts, in bytes.
+pub const RPC_MAX_BYTES: usize = 4096;
+
+/// Run an Elixir script of any length on the node in the given container. Scripts that don't fit in a single RPC command
+/// are copied into the container and evaluated from there.
+pub async fn rpc_script_on(
+    docker: docker_api::Docker,
+    container: &str,
+    script: &str,
+    timeout: Duration,
+) -> anyhow::Result<String> {
+    if script.len() <= RPC_MAX_BYTES {
+        return rpc_on(docker, container, script, timeout).await;
+    }
+    let path = format!("/tmp/msde-cli-rpc-{}.exs", Uuid::new_v4());
+    tracing::debug!(bytes = script.len(), %path, "script is too long for a single RPC, evaluating it from a file");
+    docker
+        .containers()
+        .get(container.trim_start_matches('/'))
+        .copy_file_into(&path, script.as_bytes())
+        .await
+        .with_context(|| format!("Failed to copy the script into `{container}`"))?;
+    rpc_on(
+        docker,
+        container,
+        format!("try do elem(Code.eval_file(\"{path}\"), 0) after File.rm(\"{path}\") end"),
+        timeout,
+    )
+    .await
+}
+
+async fn rpc_inner(
+    docker: docker_api::Docker,
+    container: &str,
+    cmd: Cow<'_, str>,
 ) -> anyhow::Result<String> {
     let containers = running_containers(&docker).await?;
-    let msde_id = containers
-        .get("/msde-vm-dev")
-        .context("MSDE is not running")?;
+    let container = container.trim_start_matches('/');
+    let Some(container_id) = containers.get(&format!("/{container}")) else {
+        if container == MSDE_CONTAINER {
+            anyhow::bail!("MSDE is not running");
+        }
+        let mut running: Vec<_> = containers
+            .keys()
+            .map(|name| name.trim_start_matches('/'))
+            .collect();
+        running.sort();
+        anyhow::bail!(
+            "Container `{container}` is not running. Running containers are: {}",
+            running.join(", ")
+        );
+    };
     let opts = ExecCreateOpts::builder()
         .command(vec![
             "/usr/local/bin/merigo/msde/bin/msde",
             "rpc",
-            cmd.into().as_ref(),
+            cmd.as_ref(),
         ])
         .attach_stdout(true)
         .tty(false)
@@ -118,7 +497,7 @@ pub async fn rpc(
         })
         .build();
 
-    let exec = Exec::create(docker, msde_id, &opts).await?;
+    let exec = Exec::create(docker, container_id, &opts).await?;
 
     let mut stream = exec.start(&Default::default()).await?;
     let mut output: Vec<u8> = vec![];
@@ -135,20 +514,62 @@ pub async fn rpc(
     Ok(String::from_utf8_lossy(&output).into_owned())
 }
 
+/// The output of an RPC call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcOutput {
+    /// The output as it was received.
+    pub raw: String,
+    /// The output without the stream header, ANSI escape sequences and surrounding whitespace.
+    pub cleaned: String,
+}
+
+impl RpcOutput {
+    pub fn new(raw: String) -> Self {
+        let cleaned = strip_ansi(raw.trim_start_matches(RPC_START_SEQUENCE))
+            .trim_start_matches(|c: char| c.is_whitespace() || c.is_control())
+            .trim_end()
+            .to_owned();
+        Self { raw, cleaned }
+    }
+
+    /// The `{:ok, ..}` or `{:error, ..}` tuple the output ends with, if any.
+    pub fn parsed(&self) -> Option<ElixirTuple<'_>> {
+        parse_tuple_lenient(&self.cleaned).ok()
+    }
+
+    /// Whether the output ends with a bare `:ok`, that many functions return on success.
+    pub fn is_ok_atom(&self) -> bool {
+        self.cleaned.ends_with(":ok")
+    }
+}
+
+impl std::fmt::Display for RpcOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.cleaned)
+    }
+}
+
+/// Same as [`rpc`], but the output is cleaned up and parsed.
+pub async fn rpc_typed(
+    docker: docker_api::Docker,
+    cmd: impl Into<Cow<'_, str>>,
+    timeout: Duration,
+) -> anyhow::Result<RpcOutput> {
+    rpc(docker, cmd, timeout).await.map(RpcOutput::new)
+}
+
 pub fn process_rpc_output(output: &str) -> String {
-    output
-        .trim_start_matches(RPC_START_SEQUENCE)
-        .trim()
-        .chars()
-        // JSON should only contain alphanumeric + punctuation, so this solution may be just fine.
-        .skip_while(|c| !c.is_ascii_alphanumeric() && !c.is_ascii_punctuation())
-        .collect::<String>()
+    RpcOutput::new(output.to_owned()).cleaned
 }
 
-pub async fn get_msde_config(docker: docker_api::Docker) -> anyhow::Result<Vec<Stages>> {
+pub async fn get_msde_config(
+    docker: docker_api::Docker,
+    rpc_timeout: Duration,
+) -> anyhow::Result<Vec<Stages>> {
     let op = rpc(
         docker.clone(),
         "Game.configs |> Tuple.to_list |> Enum.at(1) |> Utils.Data.encodeJson!",
+        rpc_timeout,
     )
     .await?;
     // These transforms are not very pretty and inefficient too, but it works.. sigh
@@ -164,63 +585,76 @@ pub async fn get_msde_config(docker: docker_api::Docker) -> anyhow::Result<Vec<S
         let stages: Vec<Stages> = serde_json::from_str(&op)?;
         return Ok(stages);
     }
-    get_msde_config_chunked(docker).await
+    get_msde_config_chunked(docker, rpc_timeout).await
 }
 
-async fn get_msde_config_chunked(docker: docker_api::Docker) -> anyhow::Result<Vec<Stages>> {
-    // The JSON is too big, we ask for it in 3500 character-long chunks (so hopefully it's less than 4096 bytes, since rpc command is limited to that)
-    // Arguably I should be using byte size here, but it's too annoying to do behind rpc calls like this one.
-    // If we want to be very safe, we should use 1024 as CHUNK_SIZE, since any unicode character is at most 4 bytes, so 4 * 1024 is exactly 4096 and we
-    // still get full output - this is very unlikely, because all "frequent" alphanumeric characters and punctuation are usually in the 1 (maybe 2) byte range.
-    let mut chunk = 0;
-    const CHUNK_SIZE: usize = 3500;
-    let mut final_json = String::new();
-    loop {
-        // A safety measure.. if there're more than 50 chunks, we're just empty-looping and something is inevitably broken.
-        if chunk > 50 {
+/// The number of bytes of the config requested in one chunk. The bytes are hex encoded, so the output stays under the 4096
+/// byte limit of what the RPC prints.
+const CONFIG_CHUNK_BYTES: usize = 2000;
+
+async fn get_msde_config_chunked(
+    docker: docker_api::Docker,
+    rpc_timeout: Duration,
+) -> anyhow::Result<Vec<Stages>> {
+    // The JSON is too big, we ask for it in chunks. These are sliced by bytes rather than characters, and hex encoded, so
+    // neither multibyte characters nor escaping can push a chunk over the limit, or cut it in the middle of a character.
+    let mut final_json = Vec::new();
+    for chunk in 0.. {
+        // A safety measure.. if there're more than 100 chunks, we're just empty-looping and something is inevitably broken.
+        if chunk > 100 {
             anyhow::bail!("Failed to get MSDE config.");
         }
-        let slice_start = chunk * CHUNK_SIZE;
-        let slice_end = (chunk + 1) * CHUNK_SIZE;
-        let cmd = format!("Game.configs |> Tuple.to_list |> Enum.at(1) |> Utils.Data.encodeJson! |> String.slice({slice_start}..{slice_end})");
-        let next_chunk = rpc(docker.clone(), cmd).await?;
-        let next_chunk = process_rpc_output(&next_chunk)
-            .replace("\\\"", "\"")
-            .replace("\\\\", "\\");
-
-        // The literal empty string means we've reached the end.
-        if next_chunk.trim() == "\"\"" {
-            tracing::trace!(%final_json, "MSDE config concat");
-            let stages = serde_json::from_str(&final_json)?;
-            return Ok(stages);
+        let output = rpc_typed(docker.clone(), config_chunk_cmd(chunk), rpc_timeout).await?;
+        let bytes = decode_config_chunk(&output.cleaned)
+            .with_context(|| format!("Failed to get chunk {chunk} of the MSDE config"))?;
+        final_json.extend_from_slice(&bytes);
+        if bytes.len() < CONFIG_CHUNK_BYTES {
+            break;
         }
-        final_json.push_str(strip_once_chunked(&next_chunk, '"', chunk));
-        chunk += 1
     }
+    let final_json = String::from_utf8(final_json).context("MSDE config is not valid UTF-8")?;
+    tracing::trace!(%final_json, "MSDE config concat");
+    Ok(serde_json::from_str(&final_json)?)
 }
 
-fn strip_once_chunked(s: &str, chr: char, chunk: usize) -> &str {
-    let mut lower = if s.starts_with(chr) { 1 } else { 0 };
-    let upper = if s.ends_with(chr) { s.len() - 1 } else { 0 };
-    if chunk == 0 {
-        return &s[lower..upper];
-    }
-    // Any other non-first chunk will have an extra overlapping character, since Elixir ranges are *inclusive*.
-    // Strip that off.
-    lower = if s.starts_with(chr) { lower + 1 } else { lower };
-    &s[lower..upper]
+fn config_chunk_cmd(chunk: usize) -> String {
+    let start = chunk * CONFIG_CHUNK_BYTES;
+    format!(
+        "json = Game.configs |> Tuple.to_list |> Enum.at(1) |> Utils.Data.encodeJson! ; \
+         start = min({start}, byte_size(json)) ; \
+         Base.encode16(binary_part(json, start, min({CONFIG_CHUNK_BYTES}, byte_size(json) - start)), case: :lower)"
+    )
+}
+
+/// Decode the output of [`config_chunk_cmd`], a quoted hex string, possibly preceded by log lines.
+fn decode_config_chunk(output: &str) -> anyhow::Result<Vec<u8>> {
+    let last_line = output.lines().last().unwrap_or_default().trim();
+    let Some(hex) = last_line
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    else {
+        anyhow::bail!("unexpected output: {last_line}")
+    };
+    anyhow::ensure!(
+        hex.is_ascii() && hex.len() % 2 == 0,
+        "unexpected output: {last_line}"
+    );
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .with_context(|| format!("invalid hex in output: {}", &hex[i..i + 2]))
+        })
+        .collect()
 }
 
 pub async fn sync_stage_with_ids<'a>(
     docker: docker_api::Docker,
     guid: &'a Uuid,
     suid: &'a Uuid,
-) -> anyhow::Result<(String, &'a Uuid, &'a Uuid)> {
-    let op = rpc(
-        docker,
-        format!("Game.sync(\"{guid}\", \"{suid}\", :all) ; "),
-    )
-    .await?;
+    rpc_timeout: Duration,
+) -> anyhow::Result<(RpcOutput, &'a Uuid, &'a Uuid)> {
+    let op = rpc_typed(docker, sync_cmd(guid, suid), rpc_timeout).await?;
     Ok((op, guid, suid))
 }
 
@@ -228,11 +662,84 @@ pub async fn start_stage_with_ids<'a>(
     docker: docker_api::Docker,
     guid: &'a Uuid,
     suid: &'a Uuid,
-) -> anyhow::Result<(String, &'a Uuid, &'a Uuid)> {
-    let op = rpc(docker, format!("Game.start(\"{guid}\", \"{suid}\") ; ")).await?;
+    rpc_timeout: Duration,
+) -> anyhow::Result<(RpcOutput, &'a Uuid, &'a Uuid)> {
+    let op = rpc_typed(docker, start_cmd(guid, suid), rpc_timeout).await?;
+    Ok((op, guid, suid))
+}
+
+pub async fn stop_stage_with_ids<'a>(
+    docker: docker_api::Docker,
+    guid: &'a Uuid,
+    suid: &'a Uuid,
+    rpc_timeout: Duration,
+) -> anyhow::Result<(RpcOutput, &'a Uuid, &'a Uuid)> {
+    let op = rpc_typed(docker, stop_cmd(guid, suid), rpc_timeout).await?;
     Ok((op, guid, suid))
 }
 
+/// Keep the games that are named in `only`, either by their name or guid. It's an error if something in `only` doesn't
+/// match any game, so a typo doesn't go unnoticed.
+pub fn filter_games(stages: Vec<Stages>, only: &[String]) -> anyhow::Result<Vec<Stages>> {
+    let matches =
+        |game: &Stages, filter: &str| game.name == filter || game.guid.to_string() == filter;
+    if let Some(unknown) = only
+        .iter()
+        .find(|filter| !stages.iter().any(|game| matches(game, filter)))
+    {
+        let mut names: Vec<_> = stages.iter().map(|game| game.name.as_str()).collect();
+        names.sort();
+        anyhow::bail!(
+            "No game named `{unknown}` is loaded. Loaded games are: {}",
+            names.join(", ")
+        );
+    }
+    Ok(stages
+        .into_iter()
+        .filter(|game| only.iter().any(|filter| matches(game, filter)))
+        .collect())
+}
+
+/// Stop the launched stages in the running MSDE, optionally only those of the games in `only` (see [`filter_games`]).
+pub async fn stop_games(
+    docker: Docker,
+    only: Option<&[String]>,
+    rpc_timeout: Duration,
+) -> anyhow::Result<()> {
+    let stages = get_msde_config(docker.clone(), rpc_timeout)
+        .await
+        .context("Failed to query the running games, is MSDE running?")?;
+    let stages = match only {
+        Some(only) => filter_games(stages, only)?,
+        None => stages,
+    };
+    let mapping = start_stages_mapping(stages)?;
+    let id_pairs = flatten_stage_mapping(&mapping)?;
+    if id_pairs.is_empty() {
+        tracing::info!("No launched stages found.");
+        return Ok(());
+    }
+    // One at a time, for the same reason as in `import_stages`.
+    let mut failed = 0;
+    for (guid, suid) in &id_pairs {
+        let (op, guid, suid) = stop_stage_with_ids(docker.clone(), guid, suid, rpc_timeout).await?;
+        if op.is_ok_atom() || matches!(op.parsed(), Some(ElixirTuple::ErrorEx("game_not_running")))
+        {
+            tracing::info!(%guid, %suid, "stage stopped");
+        } else {
+            failed += 1;
+            tracing::warn!(output = ?op.cleaned, %guid, %suid, "stopping stage failed");
+        }
+    }
+    anyhow::ensure!(
+        failed == 0,
+        "Failed to stop {failed} of {} stage(s)",
+        id_pairs.len()
+    );
+    tracing::info!("Stopped {} stage(s).", id_pairs.len());
+    Ok(())
+}
+
 pub fn start_stages_mapping(
     stage_configs: Vec<Stages>,
 ) -> anyhow::Result<HashMap<Uuid, Vec<Uuid>>> {
@@ -269,29 +776,174 @@ pub fn flatten_stage_mapping(
     Ok(pairs)
 }
 
-pub async fn import_stages(docker: Docker, stages: &[Stages]) -> anyhow::Result<()> {
+/// The number of stages imported in one RPC call by [`import_stages`].
+const IMPORT_BATCH_SIZE: usize = 10;
+
+/// The prefix of the lines that the command of [`import_batch_cmd`] prints for every failed import.
+const IMPORT_FAILED_MARKER: &str = "msde-cli:import-failed:";
+
+/// Import the stages, and return the number of stages that failed to import. The failures are logged by guid and suid.
+pub async fn import_stages(
+    docker: Docker,
+    stages: &[Stages],
+    rpc_timeout: Duration,
+) -> anyhow::Result<usize> {
     // Can't really do it concurrently, since it will overwhelm RPC calls like so:
     // "res was: 10:30:33.852 notice Protocol 'inet_tcp': the name msde_maint_@172.99.0.5 seems to be in use by another Erlang node"
-    for stage in stages {
-        import_stage(docker.clone(), stage).await?;
+    // Every RPC call starts a new maintenance node with the same name, so to start fewer of them, the stages are imported
+    // in batches, one RPC call each.
+    let start = std::time::Instant::now();
+    let mut failed = 0;
+    for batch in stages.chunks(IMPORT_BATCH_SIZE) {
+        failed += import_batch(docker.clone(), batch, rpc_timeout).await?;
     }
+    tracing::debug!(stages = stages.len(), failed, elapsed = ?start.elapsed(), "Imported stages");
 
-    Ok(())
+    Ok(failed)
 }
 
-async fn import_stage(docker: Docker, stage: &Stages) -> anyhow::Result<()> {
-    let json = serde_json::to_string(&stage)?
-        .replace("\\", "\\\\")
-        .replace("\"", "\\\"");
-    let res = rpc(docker.clone(), format!("\"{json}\" |> Game.import()")).await?;
-    if process_rpc_output(&res) != ":ok" {
+async fn import_batch(
+    docker: Docker,
+    stages: &[Stages],
+    rpc_timeout: Duration,
+) -> anyhow::Result<usize> {
+    let warn_failed = |stage: &Stages, msg: &str| {
         let suids = stage.stages.iter().map(|s| s.suid).collect::<Vec<_>>();
-        tracing::warn!(guid = %stage.guid, suid = ?suids, msg = ?process_rpc_output(&res), "Stage import failed")
+        tracing::warn!(guid = %stage.guid, suid = ?suids, msg = ?msg, "Stage import failed")
+    };
+    // Game configs easily exceed the size of a single RPC command. Each stage gets the time a call of its own would get.
+    let timeout = rpc_timeout * u32::try_from(stages.len()).unwrap_or(u32::MAX);
+    let res = match rpc_script_on(docker, MSDE_CONTAINER, &import_batch_cmd(stages)?, timeout).await
+    {
+        Ok(output) => RpcOutput::new(output),
+        Err(error) => {
+            // A stuck or failed call only fails the stages of this batch, the rest are still imported.
+            for stage in stages {
+                warn_failed(stage, &error.to_string());
+            }
+            return Ok(stages.len());
+        }
+    };
+    if !res.is_ok_atom() {
+        // Nothing tells which of the stages got imported before the call failed, so all of them are reported.
+        for stage in stages {
+            warn_failed(stage, &res.cleaned);
+        }
+        return Ok(stages.len());
     }
-    Ok(())
+    let mut failed = 0;
+    for (index, msg) in failed_imports(&res.cleaned) {
+        let Some(stage) = stages.get(index) else {
+            continue;
+        };
+        warn_failed(stage, msg);
+        failed += 1;
+    }
+    Ok(failed)
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// The index and error of every failed import in the output of [`import_batch_cmd`].
+fn failed_imports(output: &str) -> impl Iterator<Item = (usize, &str)> {
+    output.lines().filter_map(|line| {
+        let (index, msg) = line.strip_prefix(IMPORT_FAILED_MARKER)?.split_once(':')?;
+        Some((index.parse().ok()?, msg))
+    })
+}
+
+// The Elixir expressions sent over RPC are only built by the functions below. Every interpolated value is quoted with
+// `elixir_string`, so no game data can break out of the intended expression.
+
+fn import_batch_cmd(stages: &[Stages]) -> anyhow::Result<String> {
+    let configs = stages
+        .iter()
+        .map(|stage| Ok(elixir_string(&serde_json::to_string(stage)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    // Every failure is printed on its own line, and `inspect` escapes newlines, so they're easy to find in the output.
+    // Imports that raise are reported the same way, so one broken stage doesn't take the rest of the batch down with it.
+    Ok(format!(
+        "for {{json, i}} <- Enum.with_index([{}]) do \
+         try do \
+         case Game.import(json) do :ok -> :ok ; error -> IO.puts(\"{IMPORT_FAILED_MARKER}#{{i}}:#{{inspect(error)}}\") end \
+         rescue e -> IO.puts(\"{IMPORT_FAILED_MARKER}#{{i}}:#{{inspect(e)}}\") \
+         end \
+         end ; :ok",
+        configs.join(", ")
+    ))
+}
+
+fn sync_cmd(guid: &Uuid, suid: &Uuid) -> String {
+    format!(
+        "Game.sync({}, {}, :all) ; ",
+        elixir_string(&guid.to_string()),
+        elixir_string(&suid.to_string())
+    )
+}
+
+fn start_cmd(guid: &Uuid, suid: &Uuid) -> String {
+    format!(
+        "Game.start({}, {}) ; ",
+        elixir_string(&guid.to_string()),
+        elixir_string(&suid.to_string())
+    )
+}
+
+fn stop_cmd(guid: &Uuid, suid: &Uuid) -> String {
+    format!(
+        "Game.stop({}, {}) ; ",
+        elixir_string(&guid.to_string()),
+        elixir_string(&suid.to_string())
+    )
+}
+
+fn stage_status_cmd(ids: &[(Uuid, Uuid)]) -> String {
+    let ids = ids
+        .iter()
+        .map(|(guid, suid)| {
+            format!(
+                "{{{}, {}}}",
+                elixir_string(&guid.to_string()),
+                elixir_string(&suid.to_string())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "for {{guid, suid}} <- [{ids}] do \
+         IO.puts(\"{STAGE_STATUS_MARKER}#{{guid}}:#{{suid}}:#{{inspect(Game.status(guid, suid))}}\") \
+         end ; :ok"
+    )
+}
+
+fn sync_job_status_cmd(id: &Uuid) -> String {
+    format!(
+        "Codify.getSyncJobStatus({})",
+        elixir_string(&id.to_string())
+    )
+}
+
+/// Quote `s` as an Elixir string literal. Interpolation is escaped, and control characters are written as escape
+/// sequences, so the literal evaluates to exactly `s`.
+fn elixir_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '#' if chars.peek() == Some(&'{') => quoted.push_str("\\#"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:X}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct PackageConfigEntry {
     pub config: PathBuf,
     pub scripts: PathBuf,
@@ -299,18 +951,19 @@ pub struct PackageConfigEntry {
     pub disabled: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct PackageStagesConfig(pub Vec<PackageConfigEntry>);
 
 impl PackageStagesConfig {
     /// If the game name is in Self, return the path of the local_config.yml we can fetch the guid from.
+    /// The game is the first directory of the config path, so `foo` doesn't match `foobar/dev/local_config.yml`.
     pub fn try_find_guid_in(&self, game_name: &str) -> Option<&PathBuf> {
         self.0.iter().find_map(|cfg_entry| {
-            if cfg_entry.config.starts_with(game_name) {
-                Some(&cfg_entry.config)
-            } else {
-                None
-            }
+            let game_dir = cfg_entry
+                .config
+                .components()
+                .find(|component| !matches!(component, std::path::Component::CurDir))?;
+            (game_dir.as_os_str() == game_name).then_some(&cfg_entry.config)
         })
     }
 }
@@ -325,12 +978,193 @@ pub struct PackageLocalConfig {
     pub launch: bool,
 }
 
-// Probably handle these errors gracefully, except the when the project dir is missing (as warnings maybe?)
-pub fn parse_package_local_stages_file(ctx: &Context) -> anyhow::Result<Vec<Stages>> {
+// Strict counterparts of `PackageConfigEntry` and `PackageLocalConfig` for validation, so mistyped keys are reported instead of
+// silently ignored. These must be kept in sync with the originals.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictPackageConfigEntry {
+    config: PathBuf,
+    scripts: PathBuf,
+    tuning: PathBuf,
+    #[allow(dead_code)]
+    disabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictPackageLocalConfig {
+    game: String,
+    stage: String,
+    guid: Uuid,
+    suid: Uuid,
+    #[allow(dead_code)]
+    launch: bool,
+}
+
+/// A problem found in the game files of a project.
+#[derive(Debug)]
+pub struct ValidationError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+/// Check the `stages.yml` file and every `local_config.yml` it references in the games directory, and collect all problems:
+/// unknown or missing keys, invalid values (with their line and column) and referenced paths that don't exist.
+pub fn validate_game_files(games_dir: &Path) -> Vec<ValidationError> {
+    let stages_file = games_dir.join("stages.yml");
+    let error = |file: &Path, message: String| ValidationError {
+        file: file.to_path_buf(),
+        message,
+    };
+    let stages = match fs::read_to_string(&stages_file) {
+        Ok(stages) => stages,
+        Err(e) => return vec![error(&stages_file, format!("failed to read file: {e}"))],
+    };
+    let entries = match serde_yaml::from_str::<Vec<StrictPackageConfigEntry>>(&stages) {
+        Ok(entries) => entries,
+        Err(e) => return vec![error(&stages_file, e.to_string())],
+    };
+
+    let mut errors = vec![];
+    let mut ids = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        for (key, path) in [
+            ("config", &entry.config),
+            ("scripts", &entry.scripts),
+            ("tuning", &entry.tuning),
+        ] {
+            if !games_dir.join(path).exists() {
+                errors.push(error(
+                    &stages_file,
+                    format!(
+                        "entry #{}: `{key}` points to `{}`, which does not exist",
+                        i + 1,
+                        path.display()
+                    ),
+                ));
+            }
+        }
+
+        let local_config_file = games_dir.join(&entry.config);
+        let Ok(local_config) = fs::read_to_string(&local_config_file) else {
+            // Already reported above.
+            continue;
+        };
+        match serde_yaml::from_str::<StrictPackageLocalConfig>(&local_config) {
+            Ok(local_config) => ids.push(StageIds {
+                config: entry.config.clone(),
+                game: local_config.game,
+                stage: local_config.stage,
+                guid: local_config.guid,
+                suid: local_config.suid,
+            }),
+            Err(e) => errors.push(error(&local_config_file, e.to_string())),
+        }
+    }
+    for (i, message) in check_stage_ids(&ids) {
+        errors.push(error(&games_dir.join(&ids[i].config), message));
+    }
+    errors
+}
+
+/// The ids of a stage, and the path its `local_config.yml` is referenced with in `stages.yml`.
+#[derive(Debug)]
+struct StageIds {
+    config: PathBuf,
+    game: String,
+    stage: String,
+    guid: Uuid,
+    suid: Uuid,
+}
+
+/// Check that the ids of the stages are consistent with each other and with how they are referenced. Returns the index of
+/// the offending stage and the problem:
+/// - the `game` and `stage` of a config referenced as `<game>/<stage>/local_config.yml` must match the path,
+/// - every suid must be unique,
+/// - a guid must not be shared by different games.
+///
+/// The stages of one game may have different guids, see `create-game --new-guid`.
+fn check_stage_ids(stages: &[StageIds]) -> Vec<(usize, String)> {
+    let mut problems = vec![];
+    let mut suids: HashMap<Uuid, &Path> = HashMap::new();
+    let mut guids: HashMap<Uuid, &str> = HashMap::new();
+    for (i, ids) in stages.iter().enumerate() {
+        let components = ids
+            .config
+            .components()
+            .filter(|component| !matches!(component, std::path::Component::CurDir))
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>();
+        if let [game, stage, file] = components.as_slice() {
+            if file == "local_config.yml" && (game != &ids.game || stage != &ids.stage) {
+                problems.push((
+                    i,
+                    format!(
+                        "the stage is `{}/{}`, but it's referenced as `{game}/{stage}`",
+                        ids.game, ids.stage
+                    ),
+                ));
+            }
+        }
+        if let Some(other) = suids.insert(ids.suid, &ids.config) {
+            problems.push((
+                i,
+                format!("suid {} is also used by `{}`", ids.suid, other.display()),
+            ));
+        }
+        match guids.get(&ids.guid) {
+            Some(game) if *game != ids.game => problems.push((
+                i,
+                format!("guid {} is also used by game `{game}`", ids.guid),
+            )),
+            Some(_) => {}
+            None => {
+                guids.insert(ids.guid, &ids.game);
+            }
+        }
+    }
+    problems
+}
+
+/// A game that was skipped while parsing the stages file, because its `local_config.yml` couldn't be loaded.
+#[derive(Debug)]
+pub struct ParseWarning {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+/// Parse the stages file, `stages.yml` in the games directory unless `stages_file` is given, and the local configs it
+/// references. The games directory is `games_dir`, or the project's `games` directory. The paths in the stages file are
+/// relative to its own directory.
+///
+/// Games whose local config is missing or invalid are skipped, and returned as warnings alongside the valid stages.
+pub fn parse_package_local_stages_file(
+    ctx: &Context,
+    stages_file: Option<&Path>,
+    games_dir: Option<&Path>,
+) -> anyhow::Result<(Vec<Stages>, Vec<ParseWarning>)> {
     let Some(msde_dir) = ctx.msde_dir.as_ref() else {
         anyhow::bail!("Project dir must be set");
     };
-    let stages_file = msde_dir.join("games/stages.yml");
+    let games_dir = games_dir
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| msde_dir.join("games"));
+    let stages_file = stages_file
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| games_dir.join("stages.yml"));
+    let stages_dir = stages_file.parent().unwrap_or(Path::new("."));
     // The volume is mounted to /usr/local/bin/merigo/games, so we the way the compiler node works we need to step back to the games folder.
     let base_segment = PathBuf::from("../games");
     let stages = fs::read_to_string(&stages_file)
@@ -338,8 +1172,9 @@ pub fn parse_package_local_stages_file(ctx: &Context) -> anyhow::Result<Vec<Stag
 
     let stages: PackageStagesConfig = serde_yaml::from_str(&stages)?;
     let mut stage_configs: Vec<Stages> = vec![];
+    let mut warnings = vec![];
     for stage in stages.0 {
-        let local_cfg = msde_dir.join("games").join(stage.config);
+        let local_cfg = stages_dir.join(stage.config);
         match fs::read_to_string(&local_cfg) {
             Ok(local) => match serde_yaml::from_str::<PackageLocalConfig>(&local) {
                 Ok(package_local_config) => {
@@ -409,16 +1244,24 @@ pub fn parse_package_local_stages_file(ctx: &Context) -> anyhow::Result<Vec<Stag
                     }
                 }
                 Err(error) => {
-                    tracing::warn!(search_path = %local_cfg.display(), %error, "local_config.yml is invalid")
+                    tracing::warn!(search_path = %local_cfg.display(), %error, "local_config.yml is invalid");
+                    warnings.push(ParseWarning {
+                        path: local_cfg,
+                        message: format!("local_config.yml is invalid: {error}"),
+                    });
                 }
             },
             Err(error) => {
-                tracing::warn!(search_path = %local_cfg.display(), %error, "local_config.yml not found")
+                tracing::warn!(search_path = %local_cfg.display(), %error, "local_config.yml not found");
+                warnings.push(ParseWarning {
+                    path: local_cfg,
+                    message: format!("local_config.yml not found: {error}"),
+                });
             }
         }
     }
 
-    Ok(stage_configs)
+    Ok((stage_configs, warnings))
 }
 
 // The idea there is to first merge based on guid, then deduplicate based on the suid part.
@@ -446,17 +1289,327 @@ pub fn merge_stages(this: Vec<Stages>, other: Vec<Stages>) -> Vec<Stages> {
         .collect()
 }
 
+/// Ensure that a game or stage name is safe to use as a directory name inside the games directory: only ASCII letters,
+/// digits, `_` and `-` are allowed, and it can't start with a dot. `arg` is the name of the offending argument in the error.
+pub fn validate_game_name(arg: &str, name: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(!name.is_empty(), "the {arg} name must not be empty");
+    anyhow::ensure!(
+        !name.starts_with('.'),
+        "invalid {arg} name `{name}`: it must not start with a dot"
+    );
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+        anyhow::bail!(
+            "invalid {arg} name `{name}`: `{c}` is not allowed, only letters, digits, `_` and `-` are"
+        );
+    }
+    Ok(())
+}
+
+/// A single stage of a game in the project, as listed by the `games` command.
+#[derive(Debug, Serialize)]
+pub struct GameListing {
+    pub game: String,
+    pub stage: Option<String>,
+    pub guid: Uuid,
+    pub suid: Uuid,
+    pub launch: bool,
+    pub disabled: bool,
+    /// Whether the stage is loaded in the running MSDE. Only known if the live config was queried.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running: Option<bool>,
+}
+
+/// Flatten the stages of the project into one entry per stage, sorted by game and stage name. If `live` is given (usually
+/// from [`get_msde_config`]), every entry is marked whether it's loaded there.
+pub fn list_games(stages: &[Stages], live: Option<&[Stages]>) -> Vec<GameListing> {
+    let live_ids = live.map(|live| {
+        live.iter()
+            .flat_map(|game| game.stages.iter().map(|stage| (game.guid, stage.suid)))
+            .collect::<HashSet<_>>()
+    });
+    let mut listing = stages
+        .iter()
+        .flat_map(|game| {
+            let live_ids = &live_ids;
+            game.stages.iter().map(move |stage| GameListing {
+                game: game.name.clone(),
+                stage: stage.name.clone(),
+                guid: game.guid,
+                suid: stage.suid,
+                launch: stage.launch,
+                disabled: stage.disabled_in_stages.unwrap_or(false),
+                running: live_ids
+                    .as_ref()
+                    .map(|ids| ids.contains(&(game.guid, stage.suid))),
+            })
+        })
+        .collect::<Vec<_>>();
+    listing.sort_by(|a, b| (&a.game, &a.stage).cmp(&(&b.game, &b.stage)));
+    listing
+}
+
+/// The live state of a stage loaded in the running MSDE, as listed by the `games-status` command.
+#[derive(Debug, Serialize)]
+pub struct StageStatus {
+    pub game: String,
+    pub stage: Option<String>,
+    pub guid: Uuid,
+    pub suid: Uuid,
+    pub launch: bool,
+    /// The state reported by MSDE, e.g. `running` or `stopped`, or `unknown` if it couldn't be determined.
+    pub state: String,
+}
+
+/// The prefix of the lines that the command of [`stage_status_cmd`] prints for every stage.
+const STAGE_STATUS_MARKER: &str = "msde-cli:stage-status:";
+
+/// Query the live state of every stage loaded in the running MSDE, sorted by game and stage name. All stages are queried
+/// in a single RPC call.
+pub async fn games_status(
+    docker: Docker,
+    rpc_timeout: Duration,
+) -> anyhow::Result<Vec<StageStatus>> {
+    let stages = get_msde_config(docker.clone(), rpc_timeout)
+        .await
+        .context("Failed to query the running games, is MSDE running?")?;
+    let ids: Vec<_> = stages
+        .iter()
+        .flat_map(|game| game.stages.iter().map(|stage| (game.guid, stage.suid)))
+        .collect();
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let output = RpcOutput::new(
+        rpc_script_on(docker, MSDE_CONTAINER, &stage_status_cmd(&ids), rpc_timeout).await?,
+    );
+    let states = stage_states(&output.cleaned);
+    let mut statuses: Vec<_> = stages
+        .iter()
+        .flat_map(|game| {
+            let states = &states;
+            game.stages.iter().map(move |stage| StageStatus {
+                game: game.name.clone(),
+                stage: stage.name.clone(),
+                guid: game.guid,
+                suid: stage.suid,
+                launch: stage.launch,
+                state: states
+                    .get(&(game.guid, stage.suid))
+                    .cloned()
+                    .unwrap_or_else(|| String::from("unknown")),
+            })
+        })
+        .collect();
+    statuses.sort_by(|a, b| (&a.game, &a.stage).cmp(&(&b.game, &b.stage)));
+    Ok(statuses)
+}
+
+/// The state of every stage in the output of [`stage_status_cmd`]. The state is the value of an ok tuple, or the reason of
+/// an error tuple.
+fn stage_states(output: &str) -> HashMap<(Uuid, Uuid), String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.strip_prefix(STAGE_STATUS_MARKER)?.splitn(3, ':');
+            let guid = parts.next()?.parse().ok()?;
+            let suid = parts.next()?.parse().ok()?;
+            // The value is exactly what `inspect` printed, so there's no noise to skip.
+            let state = match parse_simple_tuple(&mut parts.next()?.trim()).ok()? {
+                ElixirTuple::OkEx(OkVariant::String(state) | OkVariant::Atom(state)) => state,
+                ElixirTuple::ErrorEx(reason) | ElixirTuple::ErrorDetailed { atom: reason, .. } => {
+                    reason
+                }
+                ElixirTuple::OkEx(_) => return None,
+            };
+            Some(((guid, suid), state.to_lowercase()))
+        })
+        .collect()
+}
+
+/// How long [`import_games`] waits for the games to sync, if the import has no timeout.
+pub const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the progress of [`import_games`] is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportProgress {
+    /// An interactive spinner.
+    Spinner,
+    /// Plain log lines, that don't interfere with other output on the terminal, e.g. attached container logs.
+    Plain,
+    /// Nothing, except for errors and warnings.
+    Quiet,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOpts<'a> {
+    pub progress: ImportProgress,
+    /// The stages file to use instead of the project's `games/stages.yml`.
+    pub stages_file: Option<&'a Path>,
+    /// The games directory to use instead of the project's `games` directory.
+    pub games_dir: Option<&'a Path>,
+    /// Fail if any of the configured games couldn't be loaded or imported, instead of skipping them.
+    pub strict: bool,
+    /// The maximum duration of the whole import. The deadline starts when the import starts, not when the future is created.
+    /// Without it, waiting for the games to sync is still bounded by [`DEFAULT_SYNC_TIMEOUT`].
+    pub timeout: Option<Duration>,
+    /// Import and sync the stages, but don't launch them.
+    pub no_start: bool,
+    /// The maximum duration of a single RPC call.
+    pub rpc_timeout: Duration,
+}
+
+/// Turn the games skipped by [`parse_package_local_stages_file`] into an error.
+pub fn ensure_no_skipped_games(warnings: &[ParseWarning]) -> anyhow::Result<()> {
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    let details = warnings
+        .iter()
+        .map(|warning| format!("  - {warning}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    anyhow::bail!(
+        "{} game stage(s) could not be loaded:\n{details}",
+        warnings.len()
+    )
+}
+
+/// A spinner that prints its messages as log lines instead in [`ImportProgress::Plain`] mode.
+struct ImportReporter {
+    pb: ProgressBar,
+    plain: bool,
+}
+
+impl ImportReporter {
+    fn new(progress: ImportProgress) -> Self {
+        Self {
+            pb: progress_spinner(progress != ImportProgress::Spinner),
+            plain: progress == ImportProgress::Plain,
+        }
+    }
+
+    fn set_message(&self, msg: impl Into<Cow<'static, str>>) {
+        if self.plain {
+            tracing::info!("{}", msg.into());
+        } else {
+            self.pb.set_message(msg);
+        }
+    }
+
+    fn finish_with_message(&self, msg: impl Into<Cow<'static, str>>) {
+        if self.plain {
+            tracing::info!("{}", msg.into());
+        } else {
+            self.pb.finish_with_message(msg);
+        }
+    }
+
+    fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        self.pb.suspend(f)
+    }
+}
+
+/// Query the status of the sync jobs, and return the ones that are still in progress with their status. Finished jobs are
+/// left out, and so are failed ones, after they're logged. `in_backoff` is set for every poll after the first one.
+async fn poll_sync_jobs<'a>(
+    docker: &Docker,
+    pb: &ImportReporter,
+    jobs: Vec<(Uuid, &'a Uuid, &'a Uuid)>,
+    in_backoff: bool,
+    rpc_timeout: Duration,
+) -> Vec<((Uuid, &'a Uuid, &'a Uuid), String)> {
+    let mut pending = vec![];
+    for job in jobs {
+        let (id, guid, suid) = job;
+        let output = match rpc_typed(docker.clone(), sync_job_status_cmd(&id), rpc_timeout).await {
+            Ok(output) => output,
+            Err(e) => {
+                pending.push((job, format!("status unknown: {e}")));
+                continue;
+            }
+        };
+        match output.parsed() {
+            Some(ElixirTuple::OkEx(OkVariant::String(status))) => match status {
+                "Finished" => {}
+                "Verify Error" | "Tuning Error" | "Scripts Error" => {
+                    pb.suspend(|| {
+                        tracing::error!(status = ?status, %guid, %suid, "sync failed");
+                    });
+                }
+                // In a backoff situation, if "Setting Up script File System" is still in progress, that means it's stuck cause
+                // the folder doesn't exist or something.
+                // Arguably we should handle this better in MSDE, but let's handle this here for now..
+                "Setting Up script File System" if in_backoff => {
+                    pb.suspend(|| {
+                        tracing::error!(status = ?status, %guid, %suid, "sync failed");
+                    });
+                }
+                // These are not completed yet.
+                _ => pending.push((job, status.to_owned())),
+            },
+            e => {
+                pb.suspend(|| {
+                    tracing::warn!(e = ?e, output = ?output.cleaned, "rpc output was unexpected");
+                });
+            }
+        }
+    }
+    pending
+}
+
+pub async fn import_games(
+    ctx: &Context,
+    docker: Docker,
+    opts: ImportOpts<'_>,
+) -> anyhow::Result<()> {
+    let Some(timeout) = opts.timeout else {
+        return import_games_unbounded(ctx, docker, opts, None).await;
+    };
+    let deadline = Instant::now() + timeout;
+    tokio::time::timeout_at(
+        deadline,
+        import_games_unbounded(ctx, docker, opts, Some(deadline)),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("importing the games timed out after {}s", timeout.as_secs()))?
+}
+
 // This function is using streams rather than try_join_all, since it may overwhelm erlang rpc
 // calls and we'd get errors about the node being used elsewhere.
 // TODO: refactor to use well-defined functions
-pub async fn import_games(ctx: &Context, docker: Docker, quiet: bool) -> anyhow::Result<()> {
-    let pb = progress_spinner(quiet);
+async fn import_games_unbounded(
+    ctx: &Context,
+    docker: Docker,
+    opts: ImportOpts<'_>,
+    deadline: Option<Instant>,
+) -> anyhow::Result<()> {
+    let pb = ImportReporter::new(opts.progress);
     pb.set_message("🔍 Discovering stages..");
-    let local = parse_package_local_stages_file(ctx)?;
-    let remote = get_msde_config(docker.clone()).await?;
+    let (local, warnings) = parse_package_local_stages_file(ctx, opts.stages_file, opts.games_dir)?;
+    if opts.strict {
+        ensure_no_skipped_games(&warnings)?;
+    }
+    if !warnings.is_empty() {
+        let loaded: usize = local.iter().map(|stages| stages.stages.len()).sum();
+        pb.suspend(|| {
+            tracing::warn!(
+                "{loaded} game stage(s) loaded, {} skipped (see warnings above).",
+                warnings.len()
+            );
+        });
+    }
+    let remote = get_msde_config(docker.clone(), opts.rpc_timeout).await?;
     let merged_config = merge_stages(local, remote);
     pb.set_message("📥 Importing stages..");
-    import_stages(docker.clone(), &merged_config).await?;
+    let failed = import_stages(docker.clone(), &merged_config, opts.rpc_timeout).await?;
+    anyhow::ensure!(
+        !opts.strict || failed == 0,
+        "Failed to import {failed} of {} game(s) (see warnings above)",
+        merged_config.len()
+    );
     let mapping = start_stages_mapping(merged_config)?;
     let id_pairs = flatten_stage_mapping(&mapping)?;
     if id_pairs.is_empty() {
@@ -467,131 +1620,68 @@ pub async fn import_games(ctx: &Context, docker: Docker, quiet: bool) -> anyhow:
     let mut progress_count = 0;
     let num_of_jobs = id_pairs.len();
     let mut sync_tasks = stream::iter(id_pairs.clone())
-        .map(|(guid, suid)| sync_stage_with_ids(docker.clone(), guid, suid));
+        .map(|(guid, suid)| sync_stage_with_ids(docker.clone(), guid, suid, opts.rpc_timeout));
     let mut sync_job_ids = vec![];
     while let Some(sync_task) = sync_tasks.next().await {
         let (op, guid, suid) = sync_task.await?;
-        let op = process_rpc_output(&op);
         pb.set_message(format!(
             "🔁 Starting sync.. {progress_count}/{}",
             num_of_jobs
         ));
         progress_count += 1;
-        match parse_simple_tuple(&mut op.as_str()) {
-            Ok(ElixirTuple::OkEx(OkVariant::Uuid(uuid))) => sync_job_ids.push((uuid, guid, suid)),
+        match op.parsed() {
+            Some(ElixirTuple::OkEx(OkVariant::Uuid(uuid))) => sync_job_ids.push((uuid, guid, suid)),
             e => {
                 pb.suspend(|| {
-                    tracing::warn!(e = ?e, output = ?op, "rpc output was unexpected");
+                    tracing::warn!(e = ?e, output = ?op.cleaned, "rpc output was unexpected");
                 });
             }
         }
     }
 
-    let mut sync_status = futures::stream::iter(sync_job_ids.clone()).map(|(id, guid, suid)| {
-        (
-            rpc(docker.clone(), format!("Codify.getSyncJobStatus(\"{id}\")")),
-            async move { guid },
-            async move { suid },
-        )
-    });
-    let mut results = vec![];
-    while let Some((status, guid, suid)) = sync_status.next().await {
-        if let Ok(r) = status.await {
-            results.push((process_rpc_output(&r), guid.await, suid.await));
-        }
-    }
-
-    let mut remaining_sync_ids: Vec<_> = results
-                .iter()
-                .zip(sync_job_ids.iter())
-                .filter_map(
-                    |((r, guid, suid), job_id)| match parse_simple_tuple(&mut r.as_str()) {
-                        Ok(ElixirTuple::OkEx(OkVariant::String(status))) => match status {
-                            "Finished" => None,
-                            "Verify Error" | "Tuning Error" | "Scripts Error" => {
-                                pb.suspend(|| {
-                                    tracing::error!(status = ?status, guid = %guid, suid = %suid, "sync failed");
-                                });
-                                None
-                            }
-                            // These are not completed yet.
-                            _ => Some(job_id),
-                        },
-                        e => {
-                            pb.suspend(|| {
-                                tracing::warn!(e = ?e, output = ?r, "rpc output was unexpected");
-                            });
-
-                            None
-                        }
-                    },
-                )
-                .collect();
-
+    let sync_deadline = deadline.unwrap_or_else(|| Instant::now() + DEFAULT_SYNC_TIMEOUT);
+    let mut pending = poll_sync_jobs(&docker, &pb, sync_job_ids, false, opts.rpc_timeout).await;
     let mut backoff = backoff::ExponentialBackoffBuilder::new()
-        .with_max_elapsed_time(Some(Duration::from_secs(30)))
+        .with_max_elapsed_time(None)
         .build();
-
-    while !remaining_sync_ids.is_empty() {
-        let Some(backoff_duration) = backoff.next_backoff() else {
-            tracing::error!(ids = ?remaining_sync_ids, "No backoff left, some sync jobs failed to complete in time.");
-            break;
-        };
-
-        tokio::time::sleep(backoff_duration).await;
-
-        let mut sync_status =
-            futures::stream::iter(remaining_sync_ids.clone()).map(|(id, guid, suid)| {
-                (
-                    rpc(docker.clone(), format!("Codify.getSyncJobStatus(\"{id}\")")),
-                    async move { guid },
-                    async move { suid },
-                )
-            });
-        let mut new_sync_results = vec![];
-        while let Some((status, guid, suid)) = sync_status.next().await {
-            if let Ok(r) = status.await {
-                new_sync_results.push((process_rpc_output(&r), guid.await, suid.await));
-            }
+    while !pending.is_empty() {
+        pb.set_message(format!(
+            "🔁 Syncing.. {}/{num_of_jobs} job(s) pending",
+            pending.len()
+        ));
+        let remaining = sync_deadline.saturating_duration_since(Instant::now());
+        let wait = backoff.next_backoff().unwrap_or(remaining).min(remaining);
+        tokio::time::sleep(wait).await;
+        // Give up before polling again, so the deadline of the whole import doesn't cut this report short.
+        if Instant::now() >= sync_deadline {
+            pb.finish_with_message("❌ Syncing timed out.");
+            let jobs = pending
+                .iter()
+                .map(|((id, guid, suid), status)| {
+                    format!("  - job {id} of {guid}/{suid}: {status}")
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            anyhow::bail!(
+                "{} sync job(s) didn't finish in time, use `--import-timeout` to wait longer:\n{jobs}",
+                pending.len()
+            );
         }
+        let jobs = pending.into_iter().map(|(job, _)| job).collect();
+        pending = poll_sync_jobs(&docker, &pb, jobs, true, opts.rpc_timeout).await;
+    }
 
-        remaining_sync_ids = new_sync_results
-            .iter()
-            .zip(remaining_sync_ids.into_iter())
-            .filter_map(|((r, guid, suid), job_id)| {
-                match parse_simple_tuple(&mut r.as_str()) {
-                    Ok(ElixirTuple::OkEx(OkVariant::String(status))) => match status {
-                        "Finished" => None,
-                        // In a backoff situation, if "Setting Up script File System" is still in progress, that means it's stuck cause
-                        // the folder doesn't exist or something.
-                        // Arguably we should handle this better in MSDE, but let's handle this here for now..
-                        "Verify Error"
-                        | "Tuning Error"
-                        | "Scripts Error"
-                        | "Setting Up script File System" => {
-                            pb.suspend(|| {
-                                tracing::error!(status = ?status, %guid, %suid, "sync failed");
-                            });
-                            None
-                        }
-                        // These are not completed yet.
-                        _ => Some(job_id),
-                    },
-                    e => {
-                        pb.suspend(|| {
-                            tracing::warn!(e = ?e, output = ?r, "rpc output was unexpected");
-                        });
-                        None
-                    }
-                }
-            })
-            .collect();
+    if opts.no_start {
+        pb.finish_with_message(format!(
+            "Done. {num_of_jobs} stage(s) imported and synced, but not started."
+        ));
+        return Ok(());
     }
 
     pb.set_message("🚀 Launching stages..");
     let mut progress_count = 0;
-    let mut start_tasks =
-        stream::iter(id_pairs).map(|(guid, suid)| start_stage_with_ids(docker.clone(), guid, suid));
+    let mut start_tasks = stream::iter(id_pairs)
+        .map(|(guid, suid)| start_stage_with_ids(docker.clone(), guid, suid, opts.rpc_timeout));
     let mut success = true;
     while let Some(sync_task) = start_tasks.next().await {
         pb.set_message(format!(
@@ -600,17 +1690,12 @@ pub async fn import_games(ctx: &Context, docker: Docker, quiet: bool) -> anyhow:
         ));
         progress_count += 1;
         let (op, guid, suid) = sync_task.await?;
-        let op = process_rpc_output(&op);
-        // FIXME: Parsing this properly is a pain, because we may get output from the Job script like this:
+        // The lenient parser skips the log noise, but success is a bare `:ok` atom (not a tuple) after output like this:
         // "[36m09:12:13.597 debug [Job.Script] Crashed reading types(), or no types defined %ArgumentError{message: \"argument error\"}\n\u{1b}[0m:ok"
-        if !matches!(
-            parse_simple_tuple(&mut op.as_str()),
-            Ok(ElixirTuple::ErrorEx("game_running"))
-        ) && !op.ends_with(":ok")
-        {
+        if !matches!(op.parsed(), Some(ElixirTuple::ErrorEx("game_running"))) && !op.is_ok_atom() {
             success = false;
             pb.suspend(|| {
-                tracing::warn!(output = ?op, %guid, %suid, "starting stage failed");
+                tracing::warn!(output = ?op.cleaned, %guid, %suid, "starting stage failed");
             });
         }
     }
@@ -620,3 +1705,447 @@ pub async fn import_games(ctx: &Context, docker: Docker, quiet: bool) -> anyhow:
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh games directory with an empty stages.yml.
+    fn games_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("msde-cli-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("stages.yml"), "[]\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn creates_a_game() {
+        let dir = games_dir();
+        create_game(&dir, crate::TEMPLATE, "game", "dev", &Default::default()).unwrap();
+        create_game(&dir, crate::TEMPLATE, "game", "prod", &Default::default()).unwrap();
+
+        let stages: PackageStagesConfig =
+            serde_yaml::from_str(&fs::read_to_string(dir.join("stages.yml")).unwrap()).unwrap();
+        assert_eq!(stages.0.len(), 2);
+        let guid_of = |stage: &str| {
+            let cfg = fs::read_to_string(dir.join("game").join(stage).join("local_config.yml"));
+            serde_yaml::from_str::<PackageLocalConfig>(&cfg.unwrap())
+                .unwrap()
+                .guid
+        };
+        assert_eq!(guid_of("dev"), guid_of("prod"));
+
+        let new_guid = CreateGameOpts {
+            new_guid: true,
+            ..Default::default()
+        };
+        create_game(&dir, crate::TEMPLATE, "game", "test", &new_guid).unwrap();
+        assert_ne!(guid_of("dev"), guid_of("test"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn deterministic_ids_are_reproducible() {
+        let ids_of = |guid: Option<Uuid>| {
+            let dir = games_dir();
+            let opts = CreateGameOpts {
+                guid,
+                deterministic: true,
+                ..Default::default()
+            };
+            create_game(&dir, crate::TEMPLATE, "game", "dev", &opts).unwrap();
+            let cfg = fs::read_to_string(dir.join("game/dev/local_config.yml")).unwrap();
+            let cfg = serde_yaml::from_str::<PackageLocalConfig>(&cfg).unwrap();
+            fs::remove_dir_all(dir).unwrap();
+            (cfg.guid, cfg.suid)
+        };
+        let (guid, suid) = ids_of(None);
+        assert_eq!(ids_of(None), (guid, suid));
+        assert_ne!(guid, suid);
+
+        let explicit = Uuid::new_v4();
+        assert_eq!(ids_of(Some(explicit)), (explicit, suid));
+    }
+
+    #[test]
+    fn finds_the_guid_of_the_exact_game() {
+        let entry = |config: &str| PackageConfigEntry {
+            config: PathBuf::from(config),
+            scripts: PathBuf::new(),
+            tuning: PathBuf::new(),
+            disabled: None,
+        };
+        let stages = PackageStagesConfig(vec![
+            entry("foobar/dev/local_config.yml"),
+            entry("./foo/dev/local_config.yml"),
+        ]);
+        assert_eq!(
+            stages.try_find_guid_in("foo"),
+            Some(&PathBuf::from("./foo/dev/local_config.yml"))
+        );
+        assert_eq!(
+            stages.try_find_guid_in("foobar"),
+            Some(&PathBuf::from("foobar/dev/local_config.yml"))
+        );
+        assert_eq!(stages.try_find_guid_in("fo"), None);
+    }
+
+    #[test]
+    fn appending_a_stage_keeps_comments() {
+        let entry = |game: &str| PackageConfigEntry {
+            config: PathBuf::from(format!("{game}/dev/local_config.yml")),
+            scripts: PathBuf::from(format!("{game}/dev/scripts")),
+            tuning: PathBuf::from(format!("{game}/dev/tuning")),
+            disabled: Some(false),
+        };
+        let append = |stages: &str| {
+            let mut cfg = serde_yaml::from_str::<PackageStagesConfig>(stages).unwrap();
+            let appended = append_stage_entry(stages, &mut cfg, entry("new")).unwrap();
+            assert_eq!(
+                serde_yaml::from_str::<PackageStagesConfig>(&appended).unwrap(),
+                cfg
+            );
+            appended
+        };
+
+        let stages = "# The games of the project\n- config: old/dev/local_config.yml # the first one\n  scripts: old/dev/scripts\n  tuning: old/dev/tuning\n";
+        let appended = append(stages);
+        assert!(appended.starts_with(stages));
+        assert!(appended.ends_with("- config: new/dev/local_config.yml\n  scripts: new/dev/scripts\n  tuning: new/dev/tuning\n  disabled: false\n"));
+
+        let appended = append("# No games yet\n[]\n");
+        assert!(appended.starts_with("# No games yet\n- config: new/dev/local_config.yml\n"));
+
+        // Flow style lists are rewritten.
+        let appended = append("[{config: old/dev/local_config.yml, scripts: s, tuning: t}]\n");
+        assert!(appended.starts_with("- config: old/dev/local_config.yml\n"));
+    }
+
+    #[test]
+    fn removing_a_stage_keeps_comments() {
+        let remove = |stages: &str, game: &str| {
+            let mut cfg = serde_yaml::from_str::<PackageStagesConfig>(stages).unwrap();
+            let edited =
+                remove_stage_entries(stages, &mut cfg, |entry| entry.config.starts_with(game))
+                    .unwrap();
+            assert_eq!(
+                serde_yaml::from_str::<PackageStagesConfig>(&edited).unwrap(),
+                cfg
+            );
+            edited
+        };
+
+        let stages = "# The games of the project\n- config: old/dev/local_config.yml # the first one\n  scripts: old/dev/scripts\n  # not yet\n  tuning: old/dev/tuning\n\n# The second one\n- config: new/dev/local_config.yml\n  scripts: new/dev/scripts\n  tuning: new/dev/tuning\n";
+        assert_eq!(
+            remove(stages, "old"),
+            "# The games of the project\n\n# The second one\n- config: new/dev/local_config.yml\n  scripts: new/dev/scripts\n  tuning: new/dev/tuning\n"
+        );
+        assert_eq!(
+            remove(stages, "new"),
+            "# The games of the project\n- config: old/dev/local_config.yml # the first one\n  scripts: old/dev/scripts\n  # not yet\n  tuning: old/dev/tuning\n\n# The second one\n"
+        );
+
+        let edited = remove(&remove(stages, "new"), "old");
+        assert_eq!(
+            edited,
+            "# The games of the project\n\n# The second one\n[]\n"
+        );
+
+        // Flow style lists are rewritten.
+        let edited = remove(
+            "[{config: old/dev/local_config.yml, scripts: s, tuning: t}, {config: new/dev/local_config.yml, scripts: s, tuning: t}]\n",
+            "old",
+        );
+        assert!(edited.starts_with("- config: new/dev/local_config.yml\n"));
+    }
+
+    #[test]
+    fn detects_inconsistent_ids() {
+        let ids = |config: &str, game: &str, stage: &str, guid: u128, suid: u128| StageIds {
+            config: PathBuf::from(config),
+            game: game.to_owned(),
+            stage: stage.to_owned(),
+            guid: Uuid::from_u128(guid),
+            suid: Uuid::from_u128(suid),
+        };
+        let stages = [
+            ids("foo/dev/local_config.yml", "foo", "dev", 1, 10),
+            ids("foo/prod/local_config.yml", "foo", "prod", 2, 11),
+            ids("bar/dev/local_config.yml", "bar", "dev", 1, 12),
+            ids("./baz/dev/local_config.yml", "baz", "prod", 3, 10),
+        ];
+        let problems = check_stage_ids(&stages);
+        assert_eq!(
+            problems.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![2, 3, 3]
+        );
+        assert!(problems[0].1.contains("also used by game `foo`"));
+        assert!(problems[1].1.contains("referenced as `baz/dev`"));
+        assert!(problems[2]
+            .1
+            .contains("also used by `foo/dev/local_config.yml`"));
+    }
+
+    #[test]
+    fn rejects_a_create_with_a_used_suid() {
+        let dir = games_dir();
+        let suid = Uuid::new_v4();
+        let opts = CreateGameOpts {
+            suid: Some(suid),
+            ..Default::default()
+        };
+        create_game(&dir, crate::TEMPLATE, "game", "dev", &opts).unwrap();
+        let stages = fs::read_to_string(dir.join("stages.yml")).unwrap();
+
+        let error = create_game(&dir, crate::TEMPLATE, "other", "dev", &opts).unwrap_err();
+        assert!(format!("{error:#}").contains(&format!("suid {suid} is also used")));
+        assert!(!dir.join("other").exists());
+        assert_eq!(fs::read_to_string(dir.join("stages.yml")).unwrap(), stages);
+        assert!(validate_game_files(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_a_failed_create() {
+        let dir = games_dir();
+        // Writing the new stages.yml fails, because its temporary file can't be created.
+        fs::create_dir(dir.join("stages.yml.tmp")).unwrap();
+
+        assert!(create_game(&dir, crate::TEMPLATE, "game", "dev", &Default::default()).is_err());
+        assert!(!dir.join("game").exists());
+        assert_eq!(fs::read_to_string(dir.join("stages.yml")).unwrap(), "[]\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn accepts_plain_game_names() {
+        for name in ["my-game", "Stage_1", "dev"] {
+            assert!(validate_game_name("game", name).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_game_names_that_escape_the_games_dir() {
+        for name in [
+            "",
+            "..",
+            "../../etc",
+            ".hidden",
+            "a/b",
+            "a\\b",
+            "/abs",
+            "game name",
+            "ünicode",
+        ] {
+            let error = validate_game_name("stage", name).unwrap_err().to_string();
+            assert!(error.contains("stage name"), "{name}: {error}");
+        }
+    }
+
+    #[test]
+    fn elixir_strings_are_escaped() {
+        assert_eq!(elixir_string("plain ünicode"), "\"plain ünicode\"");
+        assert_eq!(
+            elixir_string(r##"{"a":"b\"c","d":"#{e}"}"##),
+            r##""{\"a\":\"b\\\"c\",\"d\":\"\#{e}\"}""##
+        );
+        assert_eq!(
+            elixir_string("line\nbreak\ttab\r\u{7}#"),
+            r#""line\nbreak\ttab\r\u{7}#""#
+        );
+    }
+
+    /// Read the Elixir string literal at the start of `src`, returning its value and the rest of the source.
+    fn read_elixir_string(src: &str) -> (String, &str) {
+        let mut chars = src.char_indices();
+        assert_eq!(chars.next(), Some((0, '"')), "not a string literal: {src}");
+        let mut value = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return (value, &src[i + 1..]),
+                '#' => {
+                    assert!(!src[i + 1..].starts_with('{'), "interpolation in {src}");
+                    value.push(c);
+                }
+                '\\' => match chars.next().unwrap().1 {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    'u' => {
+                        let rest: String = chars
+                            .by_ref()
+                            .map(|(_, c)| c)
+                            .take_while(|&c| c != '}')
+                            .collect();
+                        let code = u32::from_str_radix(rest.trim_start_matches('{'), 16).unwrap();
+                        value.push(char::from_u32(code).unwrap());
+                    }
+                    escaped => value.push(escaped),
+                },
+                c => value.push(c),
+            }
+        }
+        panic!("unterminated string literal: {src}")
+    }
+
+    #[test]
+    fn rpc_commands_cannot_be_escaped() {
+        for name in [
+            r#"game"); System.halt(); ("#,
+            r#"\"); System.halt(); (\""#,
+            "#{System.halt()}",
+            "\\#{System.halt()}",
+            "line\nbreak\u{0}\u{1b}[31m",
+        ] {
+            let stage = Stages {
+                name: name.to_owned(),
+                ..Default::default()
+            };
+            let cmd = import_batch_cmd(&[stage.clone(), stage.clone()]).unwrap();
+            let list = cmd
+                .strip_prefix("for {json, i} <- Enum.with_index([")
+                .unwrap();
+            let (first, rest) = read_elixir_string(list);
+            let (second, rest) = read_elixir_string(rest.strip_prefix(", ").unwrap());
+            assert_eq!(first, serde_json::to_string(&stage).unwrap());
+            assert_eq!(first, second);
+            assert!(rest.starts_with("]) do "), "{rest}");
+            assert!(rest.contains("rescue e -> "), "{rest}");
+        }
+
+        let (guid, suid) = (Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(
+            sync_cmd(&guid, &suid),
+            format!("Game.sync(\"{guid}\", \"{suid}\", :all) ; ")
+        );
+        assert_eq!(
+            start_cmd(&guid, &suid),
+            format!("Game.start(\"{guid}\", \"{suid}\") ; ")
+        );
+        assert_eq!(
+            stop_cmd(&guid, &suid),
+            format!("Game.stop(\"{guid}\", \"{suid}\") ; ")
+        );
+        assert_eq!(
+            sync_job_status_cmd(&guid),
+            format!("Codify.getSyncJobStatus(\"{guid}\")")
+        );
+    }
+
+    #[test]
+    fn rpc_output_is_cleaned_and_parsed() {
+        let output = RpcOutput::new(format!(
+            "{RPC_START_SEQUENCE}\u{1b}[36m09:12:13.597 debug [Game] syncing\u{1b}[0m\n{{:ok, \"Finished\"}}\n"
+        ));
+        assert_eq!(
+            output.cleaned,
+            "09:12:13.597 debug [Game] syncing\n{:ok, \"Finished\"}"
+        );
+        assert_eq!(
+            output.parsed(),
+            Some(ElixirTuple::OkEx(OkVariant::String("Finished")))
+        );
+        assert!(!output.is_ok_atom());
+
+        let output = RpcOutput::new(format!("{RPC_START_SEQUENCE}\u{1b}[0m:ok\n"));
+        assert_eq!(output.cleaned, ":ok");
+        assert!(output.is_ok_atom());
+        assert_eq!(output.parsed(), None);
+    }
+
+    #[test]
+    fn config_chunks_keep_multibyte_characters() {
+        let stages = vec![Stages {
+            name: "🎮 Játék — ゲーム ".repeat(300),
+            stages: vec![StageConfig {
+                name: Some("ステージ 🚀".repeat(200)),
+                tags: Some(vec!["ünïcödé".repeat(100), "emoji 😀🙃".repeat(100)]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+        let json = serde_json::to_string(&stages).unwrap();
+        assert!(json.len() > 3 * CONFIG_CHUNK_BYTES);
+
+        // What the node answers to `config_chunk_cmd`, with log noise before the value.
+        let mut received = Vec::new();
+        for chunk in json.as_bytes().chunks(CONFIG_CHUNK_BYTES) {
+            let hex: String = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            let output = format!("09:12:13.597 debug [Game] configs\n\"{hex}\"");
+            assert!(output.len() < RPC_MAX_BYTES);
+            received.extend(decode_config_chunk(&output).unwrap());
+        }
+        let decoded: Vec<Stages> = serde_json::from_slice(&received).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+
+        assert!(decode_config_chunk("\"0\"").is_err());
+        assert!(decode_config_chunk("\"zz\"").is_err());
+        assert!(decode_config_chunk("\"éa\"").is_err());
+        assert!(decode_config_chunk("{:error, :badarg}").is_err());
+        assert_eq!(decode_config_chunk("\"\"").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn filters_games_by_name_or_guid() {
+        let game = |name: &str| Stages {
+            name: name.to_owned(),
+            guid: Uuid::new_v4(),
+            ..Default::default()
+        };
+        let stages = vec![game("foo"), game("bar"), game("baz")];
+        let baz = stages[2].guid.to_string();
+
+        let filtered = filter_games(stages.clone(), &["foo".to_owned(), baz]).unwrap();
+        let names: Vec<_> = filtered.iter().map(|game| game.name.as_str()).collect();
+        assert_eq!(names, ["foo", "baz"]);
+
+        let error = filter_games(stages, &["foo".to_owned(), "qux".to_owned()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No game named `qux` is loaded. Loaded games are: bar, baz, foo"
+        );
+    }
+
+    #[test]
+    fn finds_failed_imports_in_batch_output() {
+        let output = format!(
+            "09:12:13.597 notice [Game] importing\n{IMPORT_FAILED_MARKER}1:{{:error, :invalid}}\n\
+             {IMPORT_FAILED_MARKER}3:{{:error, \"a: b\\nc\"}}\n{IMPORT_FAILED_MARKER}x:oops\n:ok"
+        );
+        assert_eq!(
+            failed_imports(&output).collect::<Vec<_>>(),
+            [(1, "{:error, :invalid}"), (3, "{:error, \"a: b\\nc\"}")]
+        );
+        assert_eq!(failed_imports(":ok").count(), 0);
+    }
+
+    #[test]
+    fn reads_stage_states() {
+        let (guid, suid) = (Uuid::new_v4(), Uuid::new_v4());
+        let cmd = stage_status_cmd(&[(guid, suid)]);
+        assert!(cmd.starts_with(&format!(
+            "for {{guid, suid}} <- [{{\"{guid}\", \"{suid}\"}}] do "
+        )));
+
+        let ids: Vec<_> = (0..5).map(|_| (Uuid::new_v4(), Uuid::new_v4())).collect();
+        let line = |(guid, suid): (Uuid, Uuid), value: &str| {
+            format!("{STAGE_STATUS_MARKER}{guid}:{suid}:{value}\n")
+        };
+        let output = [
+            String::from("\u{1b}[36m09:12:13.597 debug [Game] status\u{1b}[0m\n"),
+            line(ids[0], "{:ok, :running}"),
+            line(ids[1], "{:ok, \"Stopped\"}"),
+            line(ids[2], "{:error, :not_loaded}"),
+            line(ids[3], "{:error, {:syncing, \"scripts\"}}"),
+            line(ids[4], "{:ok, 42}"),
+            String::from(":ok"),
+        ]
+        .concat();
+        let states = stage_states(&RpcOutput::new(output).cleaned);
+        assert_eq!(states.len(), 4);
+        assert_eq!(states[&ids[0]], "running");
+        assert_eq!(states[&ids[1]], "stopped");
+        assert_eq!(states[&ids[2]], "not_loaded");
+        assert_eq!(states[&ids[3]], "syncing");
+    }
+}
@@ -2,7 +2,12 @@
 //!
 //! Hooks are custom scripts that can be automatically integrated into the developer package's lifecycle.
 
-use std::{collections::HashMap, path::PathBuf, process::Stdio};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
@@ -13,9 +18,10 @@ pub struct Hooks {
     pub post_run: Vec<ScriptHook>,
 }
 
-pub fn execute_all(hooks: Vec<ScriptHook>) -> anyhow::Result<()> {
+/// Execute the hooks in order. Relative paths in the hooks are resolved against `msde_dir`.
+pub fn execute_all(hooks: Vec<ScriptHook>, msde_dir: &Path) -> anyhow::Result<()> {
     for script in hooks {
-        script.execute()?;
+        script.execute(msde_dir)?;
     }
     Ok(())
 }
@@ -26,18 +32,41 @@ pub struct ScriptHook {
     pub args: Option<Vec<String>>,
     pub working_directory: Option<PathBuf>,
     pub env_overrides: Option<HashMap<String, String>>,
+    /// A `.env` file to load environment variables from. The `env_overrides` take precedence over it.
+    #[serde(default)]
+    pub env_file: Option<PathBuf>,
     #[serde(default)]
     pub hide_output: bool,
     #[serde(default)]
     pub continue_on_failure: bool,
+    /// Kill the script if it's still running after this many seconds. A missing or zero value means no timeout.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
 }
 
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 impl ScriptHook {
-    pub fn execute(self) -> anyhow::Result<()> {
+    pub fn execute(self, msde_dir: &Path) -> anyhow::Result<()> {
+        let mut envs = HashMap::new();
+        if let Some(env_file) = &self.env_file {
+            // Joining an absolute path replaces `msde_dir`, so absolute paths are kept as they are.
+            let env_file = msde_dir.join(env_file);
+            match read_env_file(&env_file) {
+                Ok(vars) => envs.extend(vars),
+                Err(e) if self.continue_on_failure => {
+                    tracing::warn!(cmd = %self.cmd, error = %e, "skipping custom hook script, because its env file can't be read");
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        envs.extend(self.env_overrides.unwrap_or_default());
+
         let mut cmd = std::process::Command::new(self.cmd.clone());
         let mut cmd = cmd
             .args(self.args.unwrap_or_default())
-            .envs(self.env_overrides.unwrap_or_default())
+            .envs(envs)
             .env("MSDE_CLI_RUNNER", "true")
             .stdin(Stdio::null())
             .stdout(if self.hide_output {
@@ -51,14 +80,37 @@ impl ScriptHook {
                 Stdio::inherit()
             });
         if let Some(wd) = self.working_directory {
-            cmd = cmd.current_dir(wd);
+            cmd = cmd.current_dir(msde_dir.join(wd));
         }
 
         let mut child = cmd.spawn().with_context(|| {
             format!("failed to spawn custom script (command was `{}`)", self.cmd)
         })?;
 
-        let success = child.wait()?.success();
+        let success = match self.timeout_secs.filter(|secs| *secs > 0) {
+            None => child.wait()?.success(),
+            Some(secs) => {
+                let deadline = Instant::now() + Duration::from_secs(secs);
+                loop {
+                    if let Some(status) = child.try_wait()? {
+                        break status.success();
+                    }
+                    if Instant::now() >= deadline {
+                        child.kill()?;
+                        child.wait()?;
+                        if self.continue_on_failure {
+                            tracing::warn!(cmd = %self.cmd, "custom hook script timed out after {secs}s, continuing");
+                            return Ok(());
+                        }
+                        anyhow::bail!(
+                            "Custom hook script timed out after {secs}s (command was `{}`).",
+                            self.cmd
+                        );
+                    }
+                    std::thread::sleep(HOOK_POLL_INTERVAL);
+                }
+            }
+        };
         if success || self.continue_on_failure {
             Ok(())
         } else {
@@ -68,3 +120,51 @@ impl ScriptHook {
         }
     }
 }
+
+fn read_env_file(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    dotenvy::from_path_iter(path)
+        .and_then(|vars| vars.collect::<Result<_, _>>())
+        .with_context(|| format!("failed to read the hook env file `{}`", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_hooks_that_time_out() {
+        let dir = std::env::temp_dir().join(format!("msde-cli-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pid_file = dir.join("pid");
+        let hook = ScriptHook {
+            cmd: String::from("sh"),
+            // `exec` keeps the pid, so the written one is the pid of `sleep` itself.
+            args: Some(vec![
+                String::from("-c"),
+                format!("echo $$ > {} && exec sleep 30", pid_file.display()),
+            ]),
+            working_directory: None,
+            env_overrides: None,
+            env_file: None,
+            hide_output: true,
+            continue_on_failure: false,
+            timeout_secs: Some(1),
+        };
+
+        let start = Instant::now();
+        let error = hook.execute(&dir).unwrap_err().to_string();
+        assert!(error.contains("timed out after 1s"), "{error}");
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let alive = std::process::Command::new("kill")
+            .args(["-0", pid.trim()])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success();
+        assert!(!alive, "the hook process {} is still running", pid.trim());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
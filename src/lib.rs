@@ -1,5 +1,6 @@
 pub mod central_service;
 pub mod cli;
+pub mod completions;
 pub mod compose;
 pub mod env;
 pub mod game;
@@ -8,6 +9,8 @@ pub mod init;
 #[cfg(all(feature = "local_auth", debug_assertions))]
 pub mod local_auth;
 pub mod parsing;
+pub mod redact;
+pub mod sysconfig;
 pub mod updater;
 pub mod utils;
 
@@ -15,6 +18,7 @@ pub const LATEST: &str = "latest";
 pub const USER: &str = "merigo-client";
 pub const METADATA_JSON: &str = "metadata.json";
 pub const CONFIG_JSON: &str = "config.json";
+/// The name of the Merigo extension directory inside the project folder. The downloaded BEAM file archive is named after it too.
 pub const MERIGO_EXTENSION: &str = "merigo-extension";
 
 pub const DEFAULT_DURATION: i64 = 12;
@@ -1,46 +1,55 @@
 use std::{
-    collections::HashMap,
-    fs::{File, OpenOptions},
+    collections::{BTreeMap, HashMap},
+    fs::File,
     io::{BufReader, BufWriter, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Stdio,
     time::Duration,
 };
 
 use anyhow::Context as _;
-use clap::Parser;
+use backoff::backoff::Backoff;
+use clap::{Parser, ValueEnum};
 use clap_complete::{generate, shells::Shell};
 use dialoguer::{Confirm, Input, Password};
 use docker_api::{
+    conn::TtyChunk,
     opts::{ContainerListOpts, ContainerStopOpts},
     Docker,
 };
 use flate2::bufread::GzDecoder;
-use futures::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
+use futures::{StreamExt, TryStreamExt};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 #[cfg(all(feature = "local_auth", debug_assertions))]
 use msde_cli::local_auth;
 use msde_cli::{
     central_service::MerigoApiClient,
-    cli::{Command, Commands, Target, Web3Kind},
-    compose::Pipeline,
-    env::{Authorization, Context, Feature},
+    cli::{
+        Command, Commands, CompletionKind, LogFormat, LogOptions, OutputFormat, ProfileCommand,
+        Target,
+    },
+    completions,
+    compose::{self, Pipeline},
+    env::{Authorization, Context, ExtendedFeature, Feature, FeatureSelector, BUILTIN_PROFILES},
     game::{
-        import_games, PackageConfigEntry, PackageLocalConfig as GamePackageLocalConfig,
-        PackageStagesConfig,
+        create_game, ensure_no_skipped_games, games_status, get_msde_config, import_games,
+        list_games, parse_package_local_stages_file, remove_stage_entries, stop_games,
+        validate_game_files, validate_game_name, CreateGameOpts, GameListing, ImportOpts,
+        ImportProgress, PackageLocalConfig as GamePackageLocalConfig, PackageStagesConfig,
+        StageStatus,
     },
     hooks::{execute_all, Hooks},
     init::ensure_valid_project_path,
-    updater,
+    redact::Redactor,
+    updater::{self, HashingOpts, UpdateBeamFilesOpts},
     utils::{self, resolve_features},
-    DEFAULT_DURATION, LATEST, MERIGO_EXTENSION, MERIGO_UPSTREAM_VERSION, METADATA_JSON,
-    REPOS_AND_IMAGES, USER,
+    CONFIG_JSON, DEFAULT_DURATION, LATEST, MERIGO_EXTENSION, MERIGO_UPSTREAM_VERSION,
+    METADATA_JSON, REPOS_AND_IMAGES, USER,
 };
 
 use secrecy::{ExposeSecret, Secret};
 use sysinfo::System;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use uuid::Uuid;
 
 #[cfg(debug_assertions)]
 static LOGLEVEL: &str = "msde_cli=trace";
@@ -48,21 +57,46 @@ static LOGLEVEL: &str = "msde_cli=trace";
 #[cfg(not(debug_assertions))]
 static LOGLEVEL: &str = "msde_cli=info";
 
+/// The log level of the `-v` and `-q` flags, or the default one without any.
+fn log_level(verbose: u8, quiet: bool) -> &'static str {
+    match verbose {
+        0 if quiet => "msde_cli=warn",
+        0 => LOGLEVEL,
+        1 => "msde_cli=info",
+        2 => "msde_cli=debug",
+        _ => "msde_cli=trace",
+    }
+}
+
 type BoxedFuture = std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<()>>>>;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // The project's .env must be loaded before parsing the arguments, since many of them can be set by environment variables.
+    let mut ctx = msde_cli::env::Context::from_env()?;
+    if let Some(msde_dir) = ctx.msde_dir.as_ref() {
+        let docker_compose_env = msde_dir.join("./docker/.env");
+        dotenvy::from_path(docker_compose_env).ok();
+    }
+    let cmd = Command::parse();
+    let json_logs = cmd.log_format == LogFormat::Json;
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| LOGLEVEL.into()),
+                .unwrap_or_else(|_| log_level(cmd.verbose, cmd.quiet).into()),
         )
-        .with(
+        .with((!json_logs).then(|| {
             tracing_subscriber::fmt::layer()
                 .with_writer(std::io::stderr)
                 .without_time()
-                .with_target(false),
-        )
+                .with_target(false)
+        }))
+        .with(json_logs.then(|| {
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(std::io::stderr)
+                .with_target(false)
+        }))
         .init();
     let theme = dialoguer::theme::ColorfulTheme {
         checked_item_prefix: console::style("  [x]".to_string()).for_stderr().green(),
@@ -74,15 +108,25 @@ async fn main() -> anyhow::Result<()> {
     let upstream_version = semver::Version::parse(MERIGO_UPSTREAM_VERSION).unwrap();
 
     let current_shell = Shell::from_env().unwrap_or(Shell::Bash);
-    let mut ctx = msde_cli::env::Context::from_env()?;
     tracing::trace!(?ctx, "context");
 
-    if let Some(msde_dir) = ctx.msde_dir.as_ref() {
-        let docker_compose_env = msde_dir.join("./docker/.env");
-        dotenvy::from_path(docker_compose_env).ok();
+    // Completions run on every <TAB>, so they must be fast and quiet: no project checks and no Docker.
+    if let Some(Commands::Complete { kind, current }) = &cmd.command {
+        let known = match kind {
+            CompletionKind::Features => vec![],
+            CompletionKind::Profiles => ctx.profiles().0.into_keys().collect(),
+            CompletionKind::Targets => running_targets(cmd.docker_host.as_deref())
+                .await
+                .unwrap_or_default(),
+        };
+        let mut candidates = completions::complete(*kind, current.as_deref().unwrap_or(""), &known);
+        candidates.sort();
+        for candidate in candidates {
+            println!("{candidate}");
+        }
+        return Ok(());
     }
 
-    let cmd = Command::parse();
     let self_version = <Command as clap::CommandFactory>::command()
         .get_version()
         .map(|s| semver::Version::parse(s).unwrap())
@@ -99,7 +143,11 @@ async fn main() -> anyhow::Result<()> {
     ) {
         match (ctx.msde_dir.as_ref(), std::env::var("MERIGO_NOWARN_INIT")) {
             (Some(msde_dir), _) => {
-                tracing::info!(path = %msde_dir.display(), "Active project is at");
+                if cmd.should_announce_project() {
+                    tracing::info!(path = %msde_dir.display(), "Active project is at");
+                } else {
+                    tracing::debug!(path = %msde_dir.display(), "Active project is at");
+                }
                 if let Err(e) = &ctx.run_project_checks(self_version.clone()) {
                     tracing::warn!(error = %e, "project is invalid");
                 }
@@ -108,14 +156,19 @@ async fn main() -> anyhow::Result<()> {
             (None, _) => {
                 tracing::warn!("The developer package is not yet configured.");
                 tracing::warn!("To configure, you may use the `init` or `set-project` command, or set the project path to the `MERIGO_DEV_PACKAGE_DIR` environment variable.");
-                tracing::warn!("You may also install auto-completions by running:");
-                if let Some(completion_path) = completions_path(current_shell) {
+                // The completions hint is only shown once, and only if we know where to put them.
+                let hint_marker = ctx.config_dir.join(COMPLETIONS_HINT_MARKER);
+                if let (Some(completion_path), false) =
+                    (completions_path(current_shell), hint_marker.exists())
+                {
+                    tracing::warn!("You may also install auto-completions by running:");
                     tracing::warn!(
                         "`msde-cli generate-completions | sudo tee {} > /dev/null`",
                         completion_path
                     );
-                } else {
-                    tracing::warn!("`msde-cli generate-completions`, then redirect its output to your shell's completion path.");
+                    if let Err(error) = File::create(&hint_marker) {
+                        tracing::debug!(%error, "failed to save that the completions hint was shown");
+                    }
                 }
             }
         }
@@ -123,14 +176,42 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::trace!(?cmd, "arguments parsed");
     tracing::trace!("attempting to connect to Docker daemon..");
-    let docker = new_docker()?;
-    msde_cli::init::ensure_docker(&docker).await?;
-    tracing::trace!("connected");
-    let client = reqwest::Client::new();
+    let docker_host = cmd.docker_host.as_deref().unwrap_or(DEFAULT_DOCKER_HOST);
+    let docker = new_docker(docker_host)?;
+    if matches!(cmd.command, Some(Commands::SupportBundle { .. })) {
+        // The bundle is needed the most when Docker is broken, so the parts that need it are skipped instead.
+        match docker.ping().await {
+            Ok(_) => tracing::trace!("connected"),
+            Err(error) => tracing::warn!(
+                %error,
+                "Failed to connect to the Docker daemon at `{docker_host}`, the support bundle will be incomplete"
+            ),
+        }
+    } else {
+        msde_cli::init::ensure_docker(&docker)
+            .await
+            .with_context(|| {
+                format!("Failed to connect to the Docker daemon at `{docker_host}`")
+            })?;
+        tracing::trace!("connected");
+    }
+    let client = utils::http_client(
+        Duration::from_secs(cmd.http_timeout),
+        Duration::from_secs(cmd.connect_timeout),
+    )?;
+
+    let rpc_timeout = Duration::from_secs(cmd.rpc_timeout);
 
     match cmd.command {
         Some(Commands::UpdateBeamFiles {
-            version, no_verify, ..
+            version,
+            no_verify,
+            base_url,
+            force,
+            keep_tmp,
+            quiet,
+            parallel,
+            ..
         }) => {
             let version = version
                 .or_else(|| {
@@ -149,10 +230,68 @@ async fn main() -> anyhow::Result<()> {
                 })
                 .unwrap_or(upstream_version);
 
-            msde_cli::updater::update_beam_files(&ctx, version.clone(), no_verify).await?;
-            tracing::info!("BEAM files updated to version `{version}`.");
+            let updated = msde_cli::updater::update_beam_files(
+                &ctx,
+                &client,
+                version.clone(),
+                UpdateBeamFilesOpts {
+                    base_url: &base_url,
+                    no_verify,
+                    force,
+                    keep_tmp,
+                    hashing: HashingOpts { quiet, parallel },
+                },
+            )
+            .await?;
+            if updated {
+                tracing::info!("BEAM files updated to version `{version}`.");
+            }
         }
-        Some(Commands::VerifyBeamFiles { version, path }) => {
+        Some(Commands::VerifyBeamFiles {
+            all: true,
+            quiet,
+            parallel,
+            ..
+        }) => {
+            let Some(msde_dir) = ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            let checks = msde_cli::updater::verify_all_beam_files(
+                msde_dir,
+                HashingOpts { quiet, parallel },
+            )?;
+            if checks.is_empty() {
+                anyhow::bail!("No extension directories found in the project.");
+            }
+            println!("{:<12} {:<8} PATH", "VERSION", "STATUS");
+            for check in &checks {
+                let version = check
+                    .version
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| String::from("unknown"));
+                let status = if check.error.is_none() {
+                    "ok"
+                } else {
+                    "failed"
+                };
+                print!("{version:<12} {status:<8} {}", check.path.display());
+                match &check.error {
+                    Some(error) => println!(" ({error})"),
+                    None => println!(),
+                }
+            }
+            if checks.iter().any(|check| check.error.is_some()) {
+                anyhow::bail!("Some of the BEAM files failed to verify.");
+            }
+        }
+        Some(Commands::VerifyBeamFiles {
+            version,
+            path,
+            quiet,
+            parallel,
+            ..
+        }) => {
             let version = version.unwrap_or(upstream_version);
 
             let Some(path) =
@@ -162,25 +301,129 @@ async fn main() -> anyhow::Result<()> {
                     "No path found to merigo extension. Please specify the --path argument."
                 )
             };
-            msde_cli::updater::verify_beam_files(version, path)?;
+            msde_cli::updater::verify_beam_files(version, path, HashingOpts { quiet, parallel })?;
             tracing::info!("BEAM files verified.");
         }
-        Some(Commands::Versions { target }) => {
-            let file = File::open(&ctx.config_dir.join("index.json"))
+        Some(Commands::Volumes {
+            features,
+            profile,
+            bind_dirs,
+        }) => {
+            let Some(msde_dir) = ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            let features = resolve_features(FeatureSelector::resolve(&features), profile, &ctx);
+            print!(
+                "{}",
+                compose::generate_volumes(&features, msde_dir, &bind_dirs, false)?
+            );
+        }
+        Some(Commands::SupportBundle {
+            output,
+            log_lines,
+            bind_dirs,
+        }) => {
+            let output = output.unwrap_or_else(|| {
+                PathBuf::from(format!(
+                    "msde-support-{}.zip",
+                    time::OffsetDateTime::now_utc().unix_timestamp()
+                ))
+            });
+            write_support_bundle(&ctx, &docker, &self_version, &output, log_lines, &bind_dirs)
+                .await?;
+            tracing::info!(path = %output.display(), "Support bundle written to");
+        }
+        Some(Commands::Games {
+            json,
+            running,
+            stages_file,
+        }) => {
+            let (stages, _) = parse_package_local_stages_file(&ctx, stages_file.as_deref(), None)?;
+            let live = if running {
+                Some(
+                    get_msde_config(docker.clone(), rpc_timeout)
+                        .await
+                        .context("Failed to query the running games, is MSDE running?")?,
+                )
+            } else {
+                None
+            };
+            let listing = list_games(&stages, live.as_deref());
+            let format = if json { OutputFormat::Json } else { cmd.format };
+            if !format.print_structured(&listing)? {
+                print_games(&listing);
+            }
+        }
+        Some(Commands::Validate { games_dir }) => {
+            let Some(msde_dir) = ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            let games_dir = games_dir.unwrap_or_else(|| msde_dir.join("games"));
+            let errors = validate_game_files(&games_dir);
+            if !errors.is_empty() {
+                for error in &errors {
+                    eprintln!("{error}");
+                }
+                anyhow::bail!("Found {} problem(s) in the game files.", errors.len());
+            }
+            tracing::info!("Game files are valid.");
+        }
+        Some(Commands::Versions { min, max, target }) => {
+            if let (Some(min), Some(max)) = (&min, &max) {
+                anyhow::ensure!(min <= max, "`--min {min}` is greater than `--max {max}`");
+            }
+            let file = File::open(ctx.config_dir.join("index.json"))
                 .context("local cache not found, please omit the `--no-cache` flag")?;
             let reader = BufReader::new(file);
             let index: Index = serde_json::from_reader(reader)?;
 
-            let entry = index
-                .content
-                .iter()
-                .find(|metadata| metadata.for_target(&target))
-                .unwrap();
+            let versions_of = |target: &Target| {
+                index
+                    .content
+                    .iter()
+                    .find(|metadata| metadata.for_target(target))
+                    .map(|entry| {
+                        entry
+                            .sorted_versions()
+                            .iter()
+                            .filter(|v| min.as_ref().is_none_or(|min| *v >= min))
+                            .filter(|v| max.as_ref().is_none_or(|max| *v <= max))
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                    })
+            };
 
-            println!(
-                "available versions for `{}` are {:?}",
-                target, entry.parsed_versions
-            );
+            match target {
+                Some(target) => {
+                    let versions = versions_of(&target)
+                        .with_context(|| format!("`{target}` is not in the local cache"))?;
+                    if !cmd.format.print_structured(&versions)? {
+                        println!("{:<12} VERSION", "TARGET");
+                        for version in &versions {
+                            println!("{:<12} {version}", target.to_string());
+                        }
+                    }
+                }
+                None => {
+                    let mut grouped = BTreeMap::new();
+                    for target in Target::all(None) {
+                        match versions_of(&target) {
+                            Some(versions) => {
+                                grouped.insert(target.to_string(), versions);
+                            }
+                            None => tracing::warn!("`{target}` is not in the local cache"),
+                        }
+                    }
+                    if !cmd.format.print_structured(&grouped)? {
+                        println!("{:<12} VERSION", "TARGET");
+                        for (target, versions) in &grouped {
+                            for version in versions {
+                                println!("{target:<12} {version}");
+                            }
+                        }
+                    }
+                }
+            }
         }
         Some(Commands::BuildCache { duration }) => {
             let credentials = try_legacy_login(&ctx)
@@ -193,11 +436,22 @@ async fn main() -> anyhow::Result<()> {
             )
             .await?
         }
-        Some(Commands::Containers { always_yes }) => {
+        Some(Commands::Containers {
+            always_yes,
+            timeout,
+            concurrency,
+            all,
+        }) => {
             let opts = ContainerListOpts::builder().all(true).build();
             let containers = docker.containers().list(&opts).await?;
             let running: Vec<_> = containers
                 .into_iter()
+                .filter(|container| {
+                    all || container
+                        .image
+                        .as_ref()
+                        .is_some_and(|image| REPOS_AND_IMAGES.iter().any(|im| image.contains(im)))
+                })
                 .filter_map(|container| {
                     if container.state? == "running" {
                         let names = container.names;
@@ -214,16 +468,32 @@ async fn main() -> anyhow::Result<()> {
                 .collect();
 
             let running_length = running.len();
+            let structured = cmd.format.print_structured(&running)?;
+            if structured && !always_yes {
+                // Without `-y`, the structured output is only a listing: prompting would mix with it on stdout.
+                return Ok(());
+            }
+            // Keep stdout clean for the structured output, the rest of the messages are for humans.
+            let say = |message: &str| {
+                if structured {
+                    eprintln!("{message}");
+                } else {
+                    println!("{message}");
+                }
+            };
             if running_length > 0 {
-                println!(
-                    "There are {} containers running.. These are",
-                    running_length
-                );
-                for container in &running {
-                    println!("id: {} | image: {}", container.id, container.image);
+                if !structured {
+                    println!(
+                        "There are {} {}containers running.. These are",
+                        running_length,
+                        if all { "" } else { "Merigo " }
+                    );
+                    for container in &running {
+                        println!("id: {} | image: {}", container.id, container.image);
+                    }
                 }
 
-                println!("To update, these containers must be stopped.");
+                say("To update, these containers must be stopped.");
 
                 let should_exit = if !always_yes {
                     !handle_yes_no_prompt()
@@ -232,78 +502,88 @@ async fn main() -> anyhow::Result<()> {
                 };
 
                 if should_exit {
-                    println!("exiting");
+                    say("exiting");
                     return Ok(());
                 }
 
-                println!("Stopping all running containers..");
-                let opts = ContainerStopOpts::default();
+                say("Stopping all running containers..");
+                let wait = Duration::from_secs(timeout);
+                let opts = ContainerStopOpts::builder().wait(wait).build();
 
-                let tasks = running.into_iter().map(|container| async {
-                    match docker.containers().get(container.id).stop(&opts).await {
-                        Ok(()) => {
+                let outcomes: Vec<bool> = futures::stream::iter(running)
+                    .map(|container| {
+                        let opts = &opts;
+                        let docker = &docker;
+                        let say = &say;
+                        async move {
                             let name = container.names.unwrap_or_default();
-                            println!("Container {:?} stopped...", name);
-                            Ok(())
-                        }
-                        Err(e) => {
-                            eprintln!("Error: {e}");
-                            Err(e)
+                            // The daemon kills the container after `wait`, this only guards against the daemon itself hanging.
+                            let handle = docker.containers().get(&container.id);
+                            let stop = handle.stop(opts);
+                            match tokio::time::timeout(wait + Duration::from_secs(30), stop).await {
+                                Ok(Ok(())) => {
+                                    say(&format!("Container {:?} stopped...", name));
+                                    true
+                                }
+                                Ok(Err(e)) => {
+                                    eprintln!("Failed to stop container {:?}: {e}", name);
+                                    false
+                                }
+                                Err(_) => {
+                                    eprintln!("Timed out stopping container {:?}", name);
+                                    false
+                                }
+                            }
                         }
-                    }
-                });
-                futures::future::try_join_all(tasks)
-                    .await
-                    .context("Something went wrong stopping a container.. The errors should be logged to the console.")?;
-
-                println!("All containers stopped successfully.");
+                    })
+                    .buffer_unordered(usize::from(concurrency))
+                    .collect()
+                    .await;
+
+                let failed = outcomes.iter().filter(|stopped| !**stopped).count();
+                if failed > 0 {
+                    anyhow::bail!("Failed to stop {failed} out of {running_length} containers. The errors are logged above.");
+                }
+                say("All containers stopped successfully.");
             }
 
-            println!("There shouldn't be any running containers now.");
+            say("There shouldn't be any running containers now.");
         }
-        Some(Commands::Pull { target, version }) => {
+        Some(Commands::Pull {
+            target,
+            version,
+            require_version,
+            quiet,
+            json,
+            max_concurrency,
+            no_retry,
+        }) => {
+            let targets = target
+                .map(|t| vec![t])
+                .unwrap_or_else(|| Target::all(version));
+            if require_version {
+                anyhow::ensure!(
+                    !cmd.no_cache,
+                    "`--require-version` needs the local cache, please omit the `--no-cache` flag"
+                );
+                target_version_check(&targets, &ctx, true)?;
+            } else if !&cmd.no_cache && target_version_check(&targets, &ctx, false).is_err() {
+                tracing::warn!("missing cache, skipping target version checks");
+            }
             let credentials = try_legacy_login(&ctx)
                 .context("No credentials found, run `msde_cli legacy-login` first.")?;
-            let targets = target.map(|t| vec![t]).unwrap_or_else(|| {
-                vec![
-                    Target::Msde {
-                        version: version.clone(),
-                    },
-                    Target::Compiler {
-                        version: version.clone(),
-                    },
-                    Target::Bot {
-                        version: version.clone(),
-                    },
-                    Target::Web3 {
-                        version,
-                        kind: Some(Web3Kind::All),
-                    },
-                ]
-            });
-            if !&cmd.no_cache {
-                if let Err(_) = target_version_check(&targets, &ctx) {
-                    tracing::warn!("missing cache, skipping target version checks");
-                }
-            }
-            let m = indicatif::MultiProgress::new();
-            let mut tasks = vec![];
-            for (image, tag) in get_images_and_tags(&targets) {
-                let pb = m.add(progress_bar());
-
-                tasks.push(pull(&docker, (image, tag), Some(&credentials), pb));
-            }
-            let outcome = futures::future::try_join_all(tasks).await.map_err(|e| {
-                m.clear().unwrap();
-                e
-            })?;
-            m.clear().unwrap();
-            if outcome.iter().all(|x| *x) {
-                tracing::info!("All targets pulled!")
-            } else {
-                tracing::error!("Error pulling some of the images. Check errors above.");
-                std::process::exit(-1);
-            }
+            pull_all(
+                &docker,
+                get_images_and_tags(&targets),
+                Some(&credentials),
+                PullAllOpts {
+                    quiet,
+                    format: if json { OutputFormat::Json } else { cmd.format },
+                    max_concurrency,
+                    retry: !no_retry,
+                },
+            )
+            .await?;
         }
         Some(Commands::LegacyLogin {
             ghcr_key,
@@ -312,6 +592,32 @@ async fn main() -> anyhow::Result<()> {
         }) => {
             legacy_login(&ctx, ghcr_key, pull_key, file)?;
         }
+        Some(Commands::Logout { all }) => {
+            let mut files = vec!["credentials.json", "auth.json"];
+            if all {
+                files.push("index.json");
+            }
+            let mut removed = 0;
+            for file in files {
+                let path = ctx.config_dir.join(file);
+                match std::fs::remove_file(&path) {
+                    Ok(()) => {
+                        removed += 1;
+                        tracing::info!(path = %path.display(), "Removed");
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to remove `{}`", path.display()))
+                    }
+                }
+            }
+            if removed == 0 {
+                tracing::info!("No stored credentials found, nothing to do.");
+            } else {
+                tracing::info!("Logged out.");
+            }
+        }
         Some(Commands::Clean { always_yes }) => {
             println!("About to remove {:?}", ctx.config_dir);
 
@@ -334,84 +640,119 @@ async fn main() -> anyhow::Result<()> {
             game,
             stage,
             guid,
+            reuse_guid: _,
+            new_guid,
             suid,
+            deterministic,
         }) => {
             let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
                 anyhow::bail!("project must be set")
             };
-            let target = msde_dir.join("games").join(&game).join(&stage);
-            if target.exists() {
-                anyhow::bail!(format!(
-                    "A game with name combination '{game}/{stage}' already exists."
-                ))
-            }
-
-            let mut archive = tar::Archive::new(GzDecoder::new(msde_cli::TEMPLATE));
-            archive.unpack(&target).with_context(|| {
-                format!(
-                    "Failed to initialize a new game at directory `{}`",
-                    target.display()
-                )
-            })?;
+            create_game(
+                &msde_dir.join("games"),
+                msde_cli::TEMPLATE,
+                &game,
+                &stage,
+                &CreateGameOpts {
+                    guid,
+                    suid,
+                    deterministic,
+                    new_guid,
+                },
+            )?;
+        }
+        Some(Commands::RemoveGame {
+            game,
+            stage,
+            force,
+            always_yes,
+        }) => {
+            let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            validate_game_name("game", &game)?;
+            validate_game_name("stage", &stage)?;
+            let games_dir = msde_dir.join("games");
+            let target = games_dir.join(&game).join(&stage);
+            let stage_prefix = Path::new(&game).join(&stage);
 
-            let stages_path = msde_dir.join("games/stages.yml");
+            let stages_path = games_dir.join("stages.yml");
             let stages = std::fs::read_to_string(&stages_path)
                 .context("games/stages.yml file doesn't exist, but it should..")?;
             let mut local_cfg = serde_yaml::from_str::<PackageStagesConfig>(&stages)
                 .context("Failed to deserialize stages.yml")?;
-            let guid = guid.unwrap_or_else(|| {
-                if let Some(existing_local_cfg) = local_cfg.try_find_guid_in(&game) {
-                    if let Ok(local_config) =
-                        std::fs::read_to_string(msde_dir.join("games").join(existing_local_cfg))
-                    {
-                        let local_cfg =
-                            serde_yaml::from_str::<GamePackageLocalConfig>(&local_config)
-                                .expect("local_config.yml is invalid");
-                        local_cfg.guid
-                    } else {
-                        Uuid::new_v4()
+            let entries_before = local_cfg.0.len();
+            let new_stages = remove_stage_entries(&stages, &mut local_cfg, |entry| {
+                entry.config.starts_with(&stage_prefix)
+            })?;
+            let removed_entries = entries_before - local_cfg.0.len();
+            anyhow::ensure!(
+                removed_entries > 0 || target.exists(),
+                "A game with name combination '{game}/{stage}' doesn't exist."
+            );
+
+            if !force {
+                if let Ok(local_config) = std::fs::read_to_string(target.join("local_config.yml")) {
+                    let local_config =
+                        serde_yaml::from_str::<GamePackageLocalConfig>(&local_config)
+                            .context("local_config.yml is invalid")?;
+                    // If MSDE isn't running, there's nothing to protect. If it is, but can't be asked, it's not safe to go on.
+                    let containers = compose::running_containers(&docker).await?;
+                    if containers.contains_key(ExtendedFeature::MSDE.wait_target()) {
+                        let live = get_msde_config(docker.clone(), rpc_timeout).await.with_context(|| {
+                            format!("Failed to check whether '{game}/{stage}' is loaded in the running MSDE. Pass `--force` to remove it anyway.")
+                        })?;
+                        let running = list_games(&live, None).iter().any(|entry| {
+                            entry.guid == local_config.guid && entry.suid == local_config.suid
+                        });
+                        anyhow::ensure!(
+                            !running,
+                            "'{game}/{stage}' is loaded in the running MSDE. Stop MSDE first, or pass `--force` to remove it anyway."
+                        );
                     }
-                } else {
-                    Uuid::new_v4()
                 }
-            });
-            local_cfg.0.push(PackageConfigEntry {
-                config: PathBuf::from(format!("{game}/{stage}/local_config.yml")),
-                scripts: PathBuf::from(format!("{game}/{stage}/scripts")),
-                tuning: PathBuf::from(format!("{game}/{stage}/tuning")),
-                disabled: Some(false),
-            });
-            let cfg = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(stages_path)?;
-            let mut writer = BufWriter::new(cfg);
-            serde_yaml::to_writer(&mut writer, &local_cfg)?;
-            writer.flush()?;
-
-            let local_config_path = target.join("local_config.yml");
-            let local_config = std::fs::read_to_string(&local_config_path)?;
-            let mut local_cfg = serde_yaml::from_str::<GamePackageLocalConfig>(&local_config)?;
-            local_cfg.game.clone_from(&game);
-            local_cfg.stage.clone_from(&stage);
-            local_cfg.guid = guid;
-            local_cfg.suid = suid.unwrap_or_else(Uuid::new_v4);
-            let cfg = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(local_config_path)?;
-            let mut writer = BufWriter::new(cfg);
-            serde_yaml::to_writer(&mut writer, &local_cfg)?;
-            writer.flush()?;
+            }
+
+            let proceed = always_yes
+                || dialoguer::Confirm::with_theme(&theme)
+                    .with_prompt(format!(
+                        "This deletes `{}` and its entry in games/stages.yml. Are you sure to continue?",
+                        target.display()
+                    ))
+                    .wait_for_newline(true)
+                    .default(false)
+                    .show_default(true)
+                    .report(true)
+                    .interact()?;
+            if !proceed {
+                tracing::info!("User chose to exit.");
+                return Ok(());
+            }
+
+            utils::write_atomically(&stages_path, new_stages.as_bytes())?;
+
+            if target.exists() {
+                std::fs::remove_dir_all(&target)
+                    .with_context(|| format!("Failed to delete `{}`", target.display()))?;
+            }
+            // Only succeeds if this was the last stage of the game.
+            let _ = std::fs::remove_dir(games_dir.join(&game));
+            tracing::info!("Removed game '{game}/{stage}'.");
         }
         Some(Commands::Up {
             features,
+            reset_features,
             timeout,
             quiet,
             attach,
             build,
             raw,
             profile,
+            only_base,
+            web3_consumers,
+            env,
+            bind_dirs,
+            attach_target,
         }) => {
             let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
                 anyhow::bail!("project must be set")
@@ -419,19 +760,32 @@ async fn main() -> anyhow::Result<()> {
             let Some(metadata) = ctx.run_project_checks(self_version)? else {
                 anyhow::bail!("No valid active project found");
             };
+            let timeout = metadata.resolve_timeout(timeout);
+
+            let mut features = if only_base {
+                vec![]
+            } else {
+                features_or_last_used(
+                    &ctx,
+                    features,
+                    profile,
+                    metadata.last_features.clone(),
+                    reset_features,
+                )?
+            };
+
+            let target = attach_target.to_target();
             let attach_future = if attach {
-                Some(Target::Msde { version: None }.attach(&docker))
+                attach_target.ensure_enabled(&features)?;
+                Some(target.attach(&docker))
             } else {
                 None
             };
 
-            let mut features = resolve_features(features, profile, &ctx);
-
             Pipeline::up_from_features(
                 features.as_mut_slice(),
                 msde_dir,
-                // FIXME: Why `target_msde_version` is an Option? Probably it shouldn't be.
-                metadata.target_msde_version.unwrap().to_string().as_str(),
+                metadata.target_version(),
                 timeout,
                 &docker,
                 quiet,
@@ -439,37 +793,165 @@ async fn main() -> anyhow::Result<()> {
                 attach_future,
                 Option::<BoxedFuture>::None,
                 raw,
+                only_base,
+                web3_consumers,
+                &env,
+                &bind_dirs,
+                rpc_timeout,
             )
             .await?;
+            if !only_base {
+                remember_features(&ctx, &features);
+            }
         }
         Some(Commands::Down { timeout }) => {
             let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
                 anyhow::bail!("project must be set")
             };
+            let timeout = ctx.resolve_timeout(timeout)?;
             Pipeline::down_all(&docker, msde_dir, timeout).await?;
         }
+        Some(Commands::Reset {
+            features,
+            profile,
+            timeout,
+            always_yes,
+            bind_dirs,
+        }) => {
+            let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            let Some(metadata) = ctx.run_project_checks(self_version)? else {
+                anyhow::bail!("No valid active project found");
+            };
+            let timeout = metadata.resolve_timeout(timeout);
+            let mut features = features_or_last_used(
+                &ctx,
+                features,
+                profile,
+                metadata.last_features.clone(),
+                false,
+            )?;
+
+            let proceed = if always_yes {
+                true
+            } else {
+                dialoguer::Confirm::with_theme(&theme)
+                    .with_prompt("Resetting removes all game data stored in the Docker volumes. This is an irreversible action. Are you sure to continue?")
+                    .wait_for_newline(true)
+                    .default(false)
+                    .show_default(true)
+                    .report(true)
+                    .interact()?
+            };
+            if !proceed {
+                tracing::info!("User chose to exit.");
+                return Ok(());
+            }
+
+            Pipeline::down_all(&docker, msde_dir, timeout).await?;
+            Pipeline::up_from_features(
+                features.as_mut_slice(),
+                msde_dir,
+                metadata.target_version(),
+                timeout,
+                &docker,
+                false,
+                false,
+                Option::<BoxedFuture>::None,
+                Option::<BoxedFuture>::None,
+                false,
+                false,
+                None,
+                &[],
+                &bind_dirs,
+                rpc_timeout,
+            )
+            .await?;
+            remember_features(&ctx, &features);
+        }
+        Some(Commands::Restart {
+            features,
+            profile,
+            timeout,
+            quiet,
+            build,
+            bind_dirs,
+        }) => {
+            let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            let Some(metadata) = ctx.run_project_checks(self_version)? else {
+                anyhow::bail!("No valid active project found");
+            };
+            let timeout = metadata.resolve_timeout(timeout);
+            let mut features = features_or_last_used(
+                &ctx,
+                features,
+                profile,
+                metadata.last_features.clone(),
+                false,
+            )?;
+
+            Pipeline::stop_all(&docker, msde_dir, timeout).await?;
+            Pipeline::up_from_features(
+                features.as_mut_slice(),
+                msde_dir,
+                metadata.target_version(),
+                timeout,
+                &docker,
+                quiet,
+                build,
+                Option::<BoxedFuture>::None,
+                Option::<BoxedFuture>::None,
+                false,
+                false,
+                None,
+                &[],
+                &bind_dirs,
+                rpc_timeout,
+            )
+            .await?;
+            remember_features(&ctx, &features);
+        }
         Some(Commands::Stop { timeout }) => {
             let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
                 anyhow::bail!("project must be set")
             };
+            let timeout = ctx.resolve_timeout(timeout)?;
             Pipeline::stop_all(&docker, msde_dir, timeout).await?;
         }
+        Some(Commands::Start { timeout }) => {
+            let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            let Some(metadata) = ctx.run_project_checks(self_version)? else {
+                anyhow::bail!("No valid active project found");
+            };
+            let timeout = metadata.resolve_timeout(timeout);
+            Pipeline::start_all(&docker, msde_dir, metadata.target_version(), timeout).await?;
+        }
         Some(Commands::RunHooks { pre, post }) => {
-            anyhow::ensure!(ctx.msde_dir.is_some(), "project must be set");
+            let Some(msde_dir) = ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
             let Some(metadata) = ctx.run_project_checks(self_version)? else {
                 anyhow::bail!("No valid active project found");
             };
             if let Some(hooks) = metadata.hooks {
                 if pre {
-                    execute_all(hooks.pre_run).context("failed to execute pre-run hook")?;
+                    execute_all(hooks.pre_run, msde_dir)
+                        .context("failed to execute pre-run hook")?;
                 }
                 if post {
-                    execute_all(hooks.post_run).context("failed to execute pre-run hook")?;
+                    execute_all(hooks.post_run, msde_dir)
+                        .context("failed to execute pre-run hook")?;
                 }
             }
         }
         Some(Commands::Run {
             features,
+            reset_features,
             timeout,
             quiet,
             attach,
@@ -477,26 +959,54 @@ async fn main() -> anyhow::Result<()> {
             raw,
             no_hooks,
             profile,
+            web3_consumers,
+            env,
+            bind_dirs,
+            attach_target,
+            import_config_path,
+            strict,
+            no_start,
+            import_timeout,
         }) => {
             let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
                 anyhow::bail!("project must be set")
             };
+            ensure_stages_file(import_config_path.as_deref())?;
+            if strict {
+                // Fail before booting anything, rather than after the whole stack is up.
+                let (_, warnings) = parse_package_local_stages_file(
+                    &ctx,
+                    import_config_path.as_deref(),
+                    bind_dirs.games_dir.as_deref(),
+                )?;
+                ensure_no_skipped_games(&warnings)?;
+            }
             let Some(mut metadata) = ctx.run_project_checks(self_version)? else {
                 anyhow::bail!("No valid active project found");
             };
+            let timeout = metadata.resolve_timeout(timeout);
 
-            let mut features = resolve_features(features, profile, &ctx);
+            let mut features = features_or_last_used(
+                &ctx,
+                features,
+                profile,
+                std::mem::take(&mut metadata.last_features),
+                reset_features,
+            )?;
 
             let d = docker.clone();
+            let target = attach_target.to_target();
             let attach_future = if attach {
-                Some(Target::Msde { version: None }.attach(&d))
+                attach_target.ensure_enabled(&features)?;
+                Some(target.attach(&d))
             } else {
                 None
             };
 
             if !no_hooks {
                 if let Some(hooks) = std::mem::take(&mut metadata.hooks) {
-                    execute_all(hooks.pre_run).context("failed to execute pre-run hook")?;
+                    execute_all(hooks.pre_run, msde_dir)
+                        .context("failed to execute pre-run hook")?;
 
                     metadata.hooks = Some(Hooks {
                         pre_run: Vec::new(),
@@ -508,19 +1018,44 @@ async fn main() -> anyhow::Result<()> {
             Pipeline::up_from_features(
                 features.as_mut_slice(),
                 msde_dir,
-                metadata.target_msde_version.unwrap().to_string().as_str(),
+                metadata.target_version(),
                 timeout,
                 &docker,
                 quiet,
                 build,
                 attach_future,
-                Some(import_games(&ctx, docker.clone(), quiet || raw || attach)),
+                Some(import_games(
+                    &ctx,
+                    docker.clone(),
+                    ImportOpts {
+                        progress: if attach {
+                            ImportProgress::Plain
+                        } else if quiet || raw {
+                            ImportProgress::Quiet
+                        } else {
+                            ImportProgress::Spinner
+                        },
+                        stages_file: import_config_path.as_deref(),
+                        games_dir: bind_dirs.games_dir.as_deref(),
+                        strict,
+                        timeout: import_timeout.map(Duration::from_secs),
+                        no_start,
+                        rpc_timeout,
+                    },
+                )),
                 raw,
+                false,
+                web3_consumers,
+                &env,
+                &bind_dirs,
+                rpc_timeout,
             )
             .await?;
+            remember_features(&ctx, &features);
             if !no_hooks {
                 if let Some(hooks) = metadata.hooks {
-                    execute_all(hooks.post_run).context("failed to execute post-run hook")?;
+                    execute_all(hooks.post_run, msde_dir)
+                        .context("failed to execute post-run hook")?;
                 }
             }
         }
@@ -530,7 +1065,22 @@ async fn main() -> anyhow::Result<()> {
             pull_images,
             no_pull_images,
             features,
+            profile,
+            up,
+            bind_dirs,
+            quiet,
         }) => {
+            // There may be no config file yet, so the built-in profiles are used as a fallback.
+            let selected_features = match &profile {
+                Some(profile) => Some(
+                    ctx.profiles()
+                        .0
+                        .get(profile)
+                        .cloned()
+                        .with_context(|| format!("profile `{profile}` does not exist"))?,
+                ),
+                None => features.as_deref().map(FeatureSelector::resolve),
+            };
             // TODO: integrate login, integrate BEAM file stuff.
             // Prompt whether example games should be included
             // Message to put their existing games inside a folder..
@@ -543,13 +1093,7 @@ async fn main() -> anyhow::Result<()> {
                 PathBuf::from(res)
             });
 
-            if utils::wsl()
-                && (target.starts_with("/mnt/")
-                    || target
-                        .canonicalize()
-                        .map(|p| p.starts_with("/mnt/"))
-                        .unwrap_or(false))
-            {
+            if utils::on_windows_filesystem(&target) {
                 tracing::warn!("You seem to be using the Windows filesystem.\nIt's highly recommended to use the WSL filesystem, otherwise the package will not work correctly.");
                 let res: String = Input::with_theme(&theme)
                     .with_prompt("Input a directory, or press enter to accept the default.")
@@ -569,7 +1113,7 @@ async fn main() -> anyhow::Result<()> {
                 )
             })?;
             ctx.write_config(target.canonicalize().unwrap())?;
-            ctx.write_package_local_config(self_version)?;
+            ctx.write_package_local_config(self_version.clone())?;
             let should_pull = if pull_images {
                 true
             } else if !no_pull_images {
@@ -581,26 +1125,31 @@ async fn main() -> anyhow::Result<()> {
                 false
             };
             tracing::info!(path = %target.display(), "Successfully initialized project at");
-            if should_pull {
+            let mut features = if should_pull {
                 let mut images_and_tags = vec![
                     (String::from("postgres"), String::from("13")),
                     (String::from("dpage/pgadmin4"), String::from("latest")),
                     (String::from("hashicorp/consul"), String::from("latest")),
                     (String::from("redis"), String::from("6.2")),
                 ];
-                let features = features.unwrap_or_else(|| {
+                let features = selected_features.unwrap_or_else(|| {
+                    let all_features = Feature::value_variants();
                     let selection = dialoguer::MultiSelect::new()
                         .with_prompt("Which features do you wish to use? Use the arrow keys to move, Space to select and Enter to confirm.")
-                        // Note: Do not change the order of these, as the ordering corresponds to the `Feature` enum.
-                        .items(&["Metrics", "OTEL", "Web3", "Bot"])
-                        .defaults(&[true, false, true, false])
+                        .items(all_features)
+                        .defaults(
+                            &all_features
+                                .iter()
+                                .map(|feature| matches!(feature, Feature::Metrics | Feature::Web3))
+                                .collect::<Vec<_>>(),
+                        )
                         .interact()
                         .unwrap();
                     selection
                         .into_iter()
-                        .flat_map(Feature::from_primitive)
+                        .map(|i| all_features[i].clone())
                         .collect::<Vec<Feature>>()
-            });
+                });
 
                 images_and_tags.extend(
                     features
@@ -608,26 +1157,48 @@ async fn main() -> anyhow::Result<()> {
                         .flat_map(|feature| feature.required_images_and_tags()),
                 );
 
-                let m = indicatif::MultiProgress::new();
-                let mut tasks = vec![];
-                for (image, tag) in images_and_tags {
-                    let pb = m.add(progress_bar());
-
-                    tasks.push(pull(&docker, (image, tag), None, pb));
-                }
-                let outcome = futures::future::try_join_all(tasks).await.map_err(|e| {
-                    m.clear().unwrap();
-                    e
-                })?;
-                m.clear().unwrap();
-                if outcome.iter().all(|x| *x) {
-                    tracing::info!("All targets pulled!")
-                } else {
-                    tracing::error!("Error pulling some of the images. Check errors above.");
-                    std::process::exit(-1);
+                pull_all(
+                    &docker,
+                    images_and_tags,
+                    None,
+                    PullAllOpts {
+                        quiet,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+                features
+            } else {
+                if !up && selected_features.is_some() {
+                    tracing::warn!("Passing --features or --profile without --pull-images or --up has no effect.")
                 }
-            } else if features.is_some() {
-                tracing::warn!("Passing --features without --pull-images has no effect.")
+                selected_features.unwrap_or_default()
+            };
+
+            if up {
+                let Some(metadata) = ctx.run_project_checks(self_version)? else {
+                    anyhow::bail!("No valid active project found");
+                };
+                let timeout = metadata.resolve_timeout(None);
+                Pipeline::up_from_features(
+                    features.as_mut_slice(),
+                    &target,
+                    metadata.target_version(),
+                    timeout,
+                    &docker,
+                    quiet,
+                    false,
+                    Option::<BoxedFuture>::None,
+                    Option::<BoxedFuture>::None,
+                    false,
+                    false,
+                    None,
+                    &[],
+                    &bind_dirs,
+                    rpc_timeout,
+                )
+                .await?;
+                remember_features(&ctx, &features);
             }
         }
         Some(Commands::UpgradeProject {
@@ -664,11 +1235,14 @@ async fn main() -> anyhow::Result<()> {
                 ..
             } = serde_json::from_reader(reader)
                 .context("metadata.json file is invalid. Please rerun `msde_cli init`.")?;
-            // 2. Compare the current self_version and the metadata's version.
-            let project_self_version = semver::Version::parse(&project_self_version).unwrap();
-            // 3. Lookup the migration matrix function
+            let project_self_version =
+                semver::Version::parse(&project_self_version).with_context(|| {
+                    format!("metadata.json contains an invalid version `{project_self_version}`")
+                })?;
 
-            let proceed = if allow_overwrite {
+            // Displaying the manual steps doesn't touch the files, so there's nothing to confirm.
+            let proceed = if allow_overwrite || manual_only || self_version <= project_self_version
+            {
                 true
             } else {
                 let prompt = format!("Upgrading will potentially overwrite all files in: {}\nDo you wish to continue?", project_path.join("docker").display());
@@ -684,26 +1258,79 @@ async fn main() -> anyhow::Result<()> {
                 tracing::info!("User chose to exit.");
                 return Ok(());
             }
+            // The upgrade steps operate on the active project, which may differ from the `--path` argument.
+            ctx.set_project_path(&project_path);
             // TODO: This doesn't increase the target_msde_version.
             // also TODO: Display a prompt what will be overwritten.
             updater::upgrade_project(self_version, project_self_version, &ctx, manual_only)?;
-            // 4. Write the changes to disk, or display migration steps that need to be done manually.
-            // 5. Update the metadata.json.
-            // 6. Optionally display a warning message if the current project is not using the right self_version.
         }
-        Some(Commands::GenerateCompletions { shell }) => {
+        Some(Commands::GenerateCompletions { shell, dynamic }) => {
+            let shell = shell.unwrap_or(current_shell);
+            let mut script = vec![];
             generate(
-                shell.unwrap_or(current_shell),
+                shell,
                 &mut <Command as clap::CommandFactory>::command(),
                 "msde-cli",
-                &mut std::io::stdout(),
+                &mut script,
+            );
+            let script = String::from_utf8(script)?;
+            print!(
+                "{}",
+                completions::with_dynamic_values(shell, script, dynamic)
             );
         }
+        Some(Commands::Complete { .. }) => {
+            unreachable!("completions are handled before connecting to Docker")
+        }
         Some(Commands::AddProfile { name, features }) => {
             ctx.write_profiles(name, features)
                 .context("Failed to write profile.")?;
         }
+        Some(Commands::Profile { command }) => match command {
+            ProfileCommand::List => {
+                let profiles = ctx.profiles();
+                let mut names: Vec<_> = profiles.0.keys().collect();
+                names.sort();
+                for name in names {
+                    println!("{name}: {}", describe_features(&profiles.0[name]));
+                }
+            }
+            ProfileCommand::Show { name } => {
+                let profiles = ctx.profiles();
+                let mut features = profiles
+                    .0
+                    .get(&name)
+                    .with_context(|| format!("profile `{name}` does not exist"))?
+                    .clone();
+                features.sort_by_key(Feature::startup_order);
+                features.dedup();
+                println!("{}", describe_features(&features));
+            }
+            ProfileCommand::Remove { name, force } => {
+                if BUILTIN_PROFILES.contains(&name.as_str()) && !force {
+                    anyhow::bail!(
+                        "`{name}` is a built-in profile, pass `--force` to remove it anyway"
+                    );
+                }
+                if ctx
+                    .remove_profile(&name)
+                    .context("Failed to remove profile.")?
+                {
+                    tracing::info!("Removed profile `{name}`.");
+                } else {
+                    anyhow::bail!("profile `{name}` does not exist");
+                }
+            }
+        },
         Some(Commands::SetProject { path }) => {
+            let path = if path.as_deref() == Some(Path::new("-")) {
+                let Some(previous) = ctx.previous_project() else {
+                    anyhow::bail!("there's no previous project to switch back to")
+                };
+                Some(previous.clone())
+            } else {
+                path
+            };
             let path = path.unwrap_or_else(|| {
                 let p = Input::<'_, String>::with_theme(&theme)
                     .with_prompt("Where is the project located?")
@@ -711,43 +1338,215 @@ async fn main() -> anyhow::Result<()> {
                     .unwrap();
                 PathBuf::from(p)
             });
-            ensure_valid_project_path(&path, true)
-                .context("Project directory seems to be invalid")?;
-            ctx.set_project_path(&path);
-            ctx.run_project_checks(self_version)?;
-            ctx.write_config(path)?;
+            set_project(&mut ctx, path, self_version)?;
+        }
+        Some(Commands::Recent { index: Some(index) }) => {
+            let recent = ctx.recent_projects();
+            let Some(path) = index.checked_sub(1).and_then(|i| recent.get(i)) else {
+                anyhow::bail!(
+                    "there's no recent project with number {index}, run `msde-cli recent` to list them"
+                )
+            };
+            let path = path.clone();
+            set_project(&mut ctx, path, self_version)?;
         }
-        Some(Commands::Status) => {
-            // TODO: A lot of things here.
-            println!("Merigo developer package version {self_version}");
+        Some(Commands::Recent { index: None }) => {
+            let recent = ctx.recent_projects();
+            if recent.is_empty() {
+                tracing::info!(
+                    "No recent projects yet. Use `init` or `set-project` to set up one."
+                );
+            }
+            for (i, path) in recent.iter().enumerate() {
+                let active = if ctx.msde_dir.as_ref() == Some(path) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!("{active} {}: {}", i + 1, path.display());
+            }
+        }
+        Some(Commands::Status { json }) => {
+            let report = status_report(&ctx, &docker, &self_version).await?;
+            let format = if json { OutputFormat::Json } else { cmd.format };
+            let mut redacted = serde_json::to_value(&report)?;
+            redactor(&ctx).json(&mut redacted);
+            if !format.print_structured(&redacted)? {
+                report.print();
+            }
+        }
+        Some(Commands::GamesStatus { json }) => {
+            let statuses = games_status(docker, rpc_timeout).await?;
+            let format = if json { OutputFormat::Json } else { cmd.format };
+            if !format.print_structured(&statuses)? {
+                print_games_status(&statuses);
+            }
+        }
+        Some(Commands::StopGames { only }) => {
+            stop_games(docker, only.as_deref(), rpc_timeout).await?;
+        }
+        Some(Commands::GameDir {
+            game,
+            stage,
+            reveal,
+        }) => {
+            let Some(msde_dir) = &ctx.msde_dir.as_ref() else {
+                anyhow::bail!("project must be set")
+            };
+            validate_game_name("game", &game)?;
+            let mut dir = msde_dir.join("games").join(&game);
+            if let Some(stage) = &stage {
+                validate_game_name("stage", stage)?;
+                dir.push(stage);
+            }
+            let dir = dir.canonicalize().with_context(|| match &stage {
+                Some(stage) => format!("Game '{game}/{stage}' doesn't exist"),
+                None => format!("Game '{game}' doesn't exist"),
+            })?;
+            println!("{}", dir.display());
+            if reveal {
+                utils::reveal(&dir)?;
+            }
+        }
+        Some(Commands::Wait {
+            targets,
+            timeout,
+            json,
+        }) => {
+            let targets = if targets.is_empty() {
+                vec![ExtendedFeature::MSDE]
+            } else {
+                targets
+            };
+            let timeout = Duration::from_secs(ctx.resolve_timeout(timeout)?);
+            let statuses = msde_cli::compose::wait_for_targets(&docker, &targets, timeout).await?;
+            let format = if json { OutputFormat::Json } else { cmd.format };
+            if !format.print_structured(&statuses)? {
+                for status in &statuses {
+                    let name = status.target.to_possible_value().unwrap();
+                    match &status.error {
+                        Some(error) => println!("{}: {} ({error})", name.get_name(), status.status),
+                        None => println!("{}: {}", name.get_name(), status.status),
+                    }
+                }
+            }
+            let not_ready = statuses.iter().filter(|status| !status.is_ready()).count();
+            anyhow::ensure!(
+                not_ready == 0,
+                "{not_ready} out of {} targets are not healthy",
+                statuses.len()
+            );
         }
         Some(Commands::Docs) => {
             webbrowser::open("https://docs.merigo.co/getting-started/devpackage")
                 .context("failed to open a browser")?;
         }
-        Some(Commands::Rpc { cmd }) => {
-            let op = msde_cli::game::rpc(docker, cmd).await?;
+        Some(Commands::Rpc {
+            cmd,
+            file,
+            stdin,
+            all_nodes: true,
+            ..
+        }) => {
+            let cmd = rpc_script(cmd, file.as_deref(), stdin)?;
+            let nodes = msde_cli::game::msde_node_containers(&docker).await?;
+            anyhow::ensure!(!nodes.is_empty(), "No MSDE nodes are running");
+            let outputs = futures::future::join_all(nodes.iter().map(|node| {
+                msde_cli::game::rpc_script_on(docker.clone(), node, &cmd, rpc_timeout)
+            }))
+            .await;
+            let mut failed = 0;
+            for (node, output) in nodes.iter().zip(outputs) {
+                println!("==> {node} <==");
+                match output {
+                    Ok(op) => println!("{}", msde_cli::game::process_rpc_output(&op)),
+                    Err(e) => {
+                        failed += 1;
+                        eprintln!("Error: {e}");
+                    }
+                }
+            }
+            anyhow::ensure!(
+                failed == 0,
+                "The RPC failed on {failed} out of {} nodes",
+                nodes.len()
+            );
+        }
+        Some(Commands::Rpc {
+            cmd,
+            file,
+            stdin,
+            node,
+            ..
+        }) => {
+            let cmd = rpc_script(cmd, file.as_deref(), stdin)?;
+            let op = msde_cli::game::rpc_script_on(docker, &node, &cmd, rpc_timeout).await?;
             println!("{}", msde_cli::game::process_rpc_output(&op));
         }
-        Some(Commands::ImportGames { quiet }) => {
-            import_games(&ctx, docker, quiet).await?;
+        Some(Commands::ImportGames {
+            quiet,
+            import_config_path,
+            strict,
+            no_start,
+            import_timeout,
+            games_dir,
+        }) => {
+            ensure_stages_file(import_config_path.as_deref())?;
+            let progress = if quiet {
+                ImportProgress::Quiet
+            } else {
+                ImportProgress::Spinner
+            };
+            import_games(
+                &ctx,
+                docker,
+                ImportOpts {
+                    progress,
+                    stages_file: import_config_path.as_deref(),
+                    games_dir: games_dir.as_deref(),
+                    strict,
+                    timeout: import_timeout.map(Duration::from_secs),
+                    no_start,
+                    rpc_timeout,
+                },
+            )
+            .await?;
         }
-        Some(Commands::Log { target }) => {
-            target.attach(&docker).await?;
+        Some(Commands::Log {
+            save,
+            follow,
+            no_follow,
+            since,
+            tail,
+            timestamps,
+            target,
+        }) => {
+            let opts = LogOptions {
+                since,
+                tail,
+                follow: if save.is_some() { follow } else { !no_follow },
+                timestamps,
+            };
+            match (save, target) {
+                (Some(path), Some(target)) => {
+                    if follow {
+                        tracing::info!(path = %path.display(), "Saving logs, press Ctrl+C to stop..");
+                    }
+                    target.save_logs(&docker, &path, &opts).await?;
+                    tracing::info!(path = %path.display(), "Logs saved to");
+                }
+                (Some(_), None) => anyhow::bail!(
+                    "`--save` requires a target, e.g. `msde-cli log --save msde.log msde`"
+                ),
+                (None, Some(target)) => target.print_logs(&docker, &opts).await?,
+                (None, None) => Target::print_all_logs(&docker, &opts).await?,
+            }
         }
         Some(Commands::Ssh { target }) => {
             let Some(name) = target.container_name() else {
                 anyhow::bail!("Invalid target for command")
             };
-            let pty = pty_process::blocking::Pty::new()?;
-            pty.resize(pty_process::Size::new(1920, 1080))?;
-            let mut cmd = pty_process::blocking::Command::new("docker");
-            cmd.args(&["exec", "-it", name, "/bin/bash"]);
-            cmd.stdin(Stdio::inherit());
-            cmd.stdout(Stdio::inherit());
-            cmd.stderr(Stdio::inherit());
-            let mut child = cmd.spawn(&pty.pts()?)?;
-            child.wait()?;
+            docker_exec_interactive(&["exec", "-it", name, "/bin/bash"]).await?;
         }
         Some(Commands::Shell { target }) => {
             let (name, remote_console_path) = match (
@@ -757,17 +1556,13 @@ async fn main() -> anyhow::Result<()> {
                 (Some(container_name), Some(remote_console_path)) => {
                     (container_name, remote_console_path)
                 }
+                (Some(_), None) => anyhow::bail!(
+                    "The {target} target has no Elixir shell. Use `msde-cli ssh {target}` to get a shell in its container."
+                ),
                 _ => anyhow::bail!("Invalid target for command"),
             };
-            let pty = pty_process::blocking::Pty::new()?;
-            pty.resize(pty_process::Size::new(1920, 1080))?;
-            let mut cmd = pty_process::blocking::Command::new("docker");
-            cmd.args(&["exec", "-it", name, remote_console_path, "remote_console"]);
-            cmd.stdin(Stdio::inherit());
-            cmd.stdout(Stdio::inherit());
-            cmd.stderr(Stdio::inherit());
-            let mut child = cmd.spawn(&pty.pts()?)?;
-            child.wait()?;
+            docker_exec_interactive(&["exec", "-it", name, remote_console_path, "remote_console"])
+                .await?;
         }
         #[cfg(all(feature = "local_auth", debug_assertions))]
         Some(Commands::RunAuthServer) => {
@@ -800,7 +1595,7 @@ async fn main() -> anyhow::Result<()> {
                 None,
                 self_version.to_string(),
             );
-            #[cfg(not(debug_assertions))]
+            #[cfg(not(all(feature = "local_auth", debug_assertions)))]
             let merigo_client = MerigoApiClient::new(
                 std::env::var("MERIGO_AUTH_URL")
                     .unwrap_or_else(|_| String::from("https://production_url.com")),
@@ -817,123 +1612,14 @@ async fn main() -> anyhow::Result<()> {
             let writer = BufWriter::new(f);
             serde_json::to_writer(writer, &Authorization { token })?;
 
-            tracing::info!("Authenticated as `{name}`.");
-        }
-        None => {
-            tracing::trace!("No subcommand was passed, starting diagnostic..");
-            let version_re = regex::Regex::new(r"\d+\.\d+\.\d+$").unwrap();
-
-            let mut sys = System::new_all();
-
-            sys.refresh_all();
-
-            println!("System:");
-            println!("total memory  : {} bytes", sys.total_memory());
-            println!("used memory   : {} bytes", sys.used_memory());
-            println!("total swap    : {} bytes", sys.total_swap());
-            println!("used swap     : {} bytes", sys.used_swap());
-            #[cfg(not(target_arch = "aarch64"))]
-            {
-                use raw_cpuid::CpuId;
-                let cpuid = CpuId::new();
-                if let Some(cpu_info) = cpuid.get_processor_brand_string() {
-                    println!("CPU           : {}", cpu_info.as_str());
-                }
-            }
-            println!("system name   : {}", sysinfo::System::name().unwrap());
-            println!(
-                "kernel version: {}",
-                sysinfo::System::kernel_version().unwrap()
-            );
-            println!(
-                "OS version    : {}",
-                sysinfo::System::long_os_version().unwrap()
-            );
-            println!("host name     : {}", sysinfo::System::host_name().unwrap());
-            println!("CPU arch      : {}", sysinfo::System::cpu_arch().unwrap());
-            println!(
-                "Docker version: {}",
-                docker.version().await.unwrap().version.unwrap()
-            );
-
-            let opts = docker_api::opts::ImageListOpts::default();
-            let docker_images = docker.images().list(&opts).await?;
-            let mut local_image_stats: HashMap<String, Vec<String>> = HashMap::new();
-
-            for docker_image in &docker_images {
-                if docker_image
-                    .repo_tags
-                    .iter()
-                    .any(|tag| REPOS_AND_IMAGES.iter().any(|im| tag.contains(im)))
-                {
-                    tracing::trace!(image = ?docker_image.repo_tags, "Looking at image..");
-                    let image = docker
-                        .images()
-                        .get(&docker_image.id)
-                        .inspect()
-                        .await
-                        .unwrap();
-                    let image_tags = image.repo_tags.unwrap_or_default();
-
-                    let version: Option<VersionedImage> =
-                        image_tags.iter().fold(None, |img, tag| {
-                            let result = if tag.ends_with(LATEST) {
-                                let (name, original_tag) =
-                                    tag.split_once(':').expect("a valid Docker image name");
-                                Some((LATEST, name, original_tag))
-                            } else if let Some(cap) = version_re.captures(tag) {
-                                if let Some(version) = cap.get(0).map(|m| m.as_str()) {
-                                    let (name, original_tag) =
-                                        tag.split_once(':').expect("a valid Docker image name");
-                                    Some((version, name, original_tag))
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            };
-
-                            let Some((version, name, original_tag)) = result else {
-                                return img;
-                            };
-                            match img {
-                                Some(mut a) => {
-                                    a.aliases.push(name);
-                                    Some(a)
-                                }
-                                None => Some(VersionedImage {
-                                    version,
-                                    name,
-                                    aliases: vec![],
-                                    id: docker_image.id.clone(),
-                                    original_tag,
-                                    resolved_version: semver::Version::parse(version).ok(),
-                                }),
-                            }
-                        });
-
-                    if let Some(version_info) = version {
-                        tracing::trace!(
-                            name = ?version_info.name,
-                            version = ?version_info.version,
-                            resolved_version = ?version_info.resolved_version,
-                            original_tag = ?version_info.original_tag,
-                            aliases = ?version_info.aliases,
-                            "parsed local image"
-                        );
-
-                        local_image_stats
-                            .entry(version_info.name.to_owned())
-                            .or_default()
-                            .push(version_info.version.to_owned());
-                    }
-                }
-            }
-            println!("Available local Merigo related images are:\n{local_image_stats:#?}");
+            tracing::info!("Authenticated as `{name}`.");
         }
-        _ => {
-            tracing::debug!("not now..");
-            unimplemented!();
+        None => {
+            tracing::trace!("No subcommand was passed, starting diagnostic..");
+            let diagnostics = collect_diagnostics(&docker).await?;
+            if !cmd.format.print_structured(&diagnostics)? {
+                diagnostics.write(&mut std::io::stdout())?;
+            }
         }
     }
 
@@ -987,9 +1673,639 @@ impl ParsedMetadataResponse {
     fn contains_version(&self, version: &str) -> bool {
         self.parsed_versions.iter().any(|v| v == version)
     }
+
+    /// The parsed versions from newest to oldest, without duplicates.
+    fn sorted_versions(&self) -> Vec<semver::Version> {
+        let mut versions: Vec<_> = self
+            .parsed_versions
+            .iter()
+            .filter_map(|v| semver::Version::parse(v).ok())
+            .collect();
+        versions.sort_by(|a, b| b.cmp(a));
+        versions.dedup();
+        versions
+    }
+}
+
+/// The system and Docker information printed when no subcommand is given.
+#[derive(Debug, serde::Serialize)]
+struct Diagnostics {
+    total_memory: u64,
+    used_memory: u64,
+    total_swap: u64,
+    used_swap: u64,
+    cpu: Option<String>,
+    system_name: Option<String>,
+    kernel_version: Option<String>,
+    os_version: Option<String>,
+    host_name: Option<String>,
+    cpu_arch: Option<String>,
+    docker_version: Option<String>,
+    /// The versions of the local Merigo related images, keyed by the image name.
+    local_images: BTreeMap<String, Vec<String>>,
+}
+
+impl Diagnostics {
+    /// Write the system, Docker and local image information. This is what running the tool without a subcommand prints.
+    fn write(&self, out: &mut impl std::io::Write) -> anyhow::Result<()> {
+        let unknown = String::from("unknown");
+        writeln!(out, "System:")?;
+        writeln!(out, "total memory  : {} bytes", self.total_memory)?;
+        writeln!(out, "used memory   : {} bytes", self.used_memory)?;
+        writeln!(out, "total swap    : {} bytes", self.total_swap)?;
+        writeln!(out, "used swap     : {} bytes", self.used_swap)?;
+        if let Some(cpu) = &self.cpu {
+            writeln!(out, "CPU           : {cpu}")?;
+        }
+        writeln!(
+            out,
+            "system name   : {}",
+            self.system_name.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            out,
+            "kernel version: {}",
+            self.kernel_version.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            out,
+            "OS version    : {}",
+            self.os_version.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            out,
+            "host name     : {}",
+            self.host_name.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            out,
+            "CPU arch      : {}",
+            self.cpu_arch.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            out,
+            "Docker version: {}",
+            self.docker_version.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            out,
+            "Available local Merigo related images are:\n{:#?}",
+            self.local_images
+        )?;
+        Ok(())
+    }
+}
+
+async fn collect_diagnostics(docker: &Docker) -> anyhow::Result<Diagnostics> {
+    let version_re = regex::Regex::new(r"\d+\.\d+\.\d+$").unwrap();
+
+    let mut sys = System::new_all();
+
+    sys.refresh_all();
+
+    #[cfg(not(target_arch = "aarch64"))]
+    let cpu = raw_cpuid::CpuId::new()
+        .get_processor_brand_string()
+        .map(|cpu_info| cpu_info.as_str().to_owned());
+    #[cfg(target_arch = "aarch64")]
+    let cpu = None;
+
+    let opts = docker_api::opts::ImageListOpts::default();
+    let docker_images = docker.images().list(&opts).await?;
+    let mut local_images: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for docker_image in &docker_images {
+        if docker_image
+            .repo_tags
+            .iter()
+            .any(|tag| REPOS_AND_IMAGES.iter().any(|im| tag.contains(im)))
+        {
+            tracing::trace!(image = ?docker_image.repo_tags, "Looking at image..");
+            let image = docker
+                .images()
+                .get(&docker_image.id)
+                .inspect()
+                .await
+                .unwrap();
+            let image_tags = image.repo_tags.unwrap_or_default();
+
+            let version: Option<VersionedImage> = image_tags.iter().fold(None, |img, tag| {
+                let result = if tag.ends_with(LATEST) {
+                    let (name, original_tag) =
+                        tag.split_once(':').expect("a valid Docker image name");
+                    Some((LATEST, name, original_tag))
+                } else if let Some(cap) = version_re.captures(tag) {
+                    if let Some(version) = cap.get(0).map(|m| m.as_str()) {
+                        let (name, original_tag) =
+                            tag.split_once(':').expect("a valid Docker image name");
+                        Some((version, name, original_tag))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                };
+
+                let Some((version, name, original_tag)) = result else {
+                    return img;
+                };
+                match img {
+                    Some(mut a) => {
+                        a.aliases.push(name);
+                        Some(a)
+                    }
+                    None => Some(VersionedImage {
+                        version,
+                        name,
+                        aliases: vec![],
+                        id: docker_image.id.clone(),
+                        original_tag,
+                        resolved_version: semver::Version::parse(version).ok(),
+                    }),
+                }
+            });
+
+            if let Some(version_info) = version {
+                tracing::trace!(
+                    name = ?version_info.name,
+                    version = ?version_info.version,
+                    resolved_version = ?version_info.resolved_version,
+                    original_tag = ?version_info.original_tag,
+                    aliases = ?version_info.aliases,
+                    "parsed local image"
+                );
+
+                local_images
+                    .entry(version_info.name.to_owned())
+                    .or_default()
+                    .push(version_info.version.to_owned());
+            }
+        }
+    }
+    Ok(Diagnostics {
+        total_memory: sys.total_memory(),
+        used_memory: sys.used_memory(),
+        total_swap: sys.total_swap(),
+        used_swap: sys.used_swap(),
+        cpu,
+        system_name: System::name(),
+        kernel_version: System::kernel_version(),
+        os_version: System::long_os_version(),
+        host_name: System::host_name(),
+        cpu_arch: System::cpu_arch(),
+        docker_version: docker.version().await?.version,
+        local_images,
+    })
+}
+
+/// The Elixir source of the `rpc` command, either given inline, or read from a file or the standard input.
+fn rpc_script(cmd: Option<String>, file: Option<&Path>, stdin: bool) -> anyhow::Result<String> {
+    let script = match (cmd, file) {
+        (Some(cmd), _) => cmd,
+        (None, Some(file)) => std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read `{}`", file.display()))?,
+        (None, None) => {
+            anyhow::ensure!(stdin, "No Elixir command was given");
+            std::io::read_to_string(std::io::stdin())
+                .context("Failed to read the standard input")?
+        }
+    };
+    anyhow::ensure!(!script.trim().is_empty(), "The Elixir command is empty");
+    Ok(script)
+}
+
+/// Run `docker` with `args` in a pseudo terminal. The terminal has the size of the current one, and it's resized along with
+/// it, so interactive sessions reflow properly.
+async fn docker_exec_interactive(args: &[&str]) -> anyhow::Result<()> {
+    let pty = std::sync::Arc::new(pty_process::blocking::Pty::new()?);
+    pty.resize(terminal_size())?;
+    let mut window_changes =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())?;
+    let resizer = {
+        let pty = std::sync::Arc::clone(&pty);
+        tokio::spawn(async move {
+            while window_changes.recv().await.is_some() {
+                if let Err(error) = pty.resize(terminal_size()) {
+                    tracing::debug!(%error, "failed to resize the terminal");
+                }
+            }
+        })
+    };
+
+    let mut cmd = pty_process::blocking::Command::new("docker");
+    cmd.args(args);
+    cmd.stdin(Stdio::inherit());
+    cmd.stdout(Stdio::inherit());
+    cmd.stderr(Stdio::inherit());
+    let mut child = cmd.spawn(&pty.pts()?)?;
+    let status = tokio::task::block_in_place(|| child.wait());
+    resizer.abort();
+    status?;
+    Ok(())
+}
+
+/// The size of the terminal in rows and columns, or 24x80 if not attached to a terminal.
+fn terminal_size() -> pty_process::Size {
+    let (rows, cols) = console::Term::stdout().size_checked().unwrap_or((24, 80));
+    pty_process::Size::new(rows, cols)
+}
+
+/// Validate the project at `path` and make it the active project.
+fn set_project(
+    ctx: &mut Context,
+    path: PathBuf,
+    self_version: semver::Version,
+) -> anyhow::Result<()> {
+    ensure_valid_project_path(&path, true).context("Project directory seems to be invalid")?;
+    let path = path.canonicalize().unwrap_or(path);
+    ctx.set_project_path(&path);
+    ctx.run_project_checks(self_version)?;
+    tracing::info!(path = %path.display(), "Active project is set to");
+    ctx.write_config(path)?;
+    Ok(())
+}
+
+/// A redactor that masks the stored credentials and token, whenever they appear in a diagnostic output.
+fn redactor(ctx: &Context) -> Redactor {
+    let mut secrets = vec![];
+    if let Ok(credentials) = try_legacy_login(ctx) {
+        secrets.push(credentials.ghcr_key.expose_secret().clone());
+        secrets.push(credentials.pull_key.expose_secret().clone());
+    }
+    if let Some(authorization) = ctx.authorization.as_ref() {
+        secrets.push(authorization.token.clone());
+    }
+    Redactor::new(secrets)
+}
+
+/// Write the support bundle zip. Parts that can't be collected are skipped with a warning, so a broken setup (which is
+/// usually why the bundle is needed) still produces a bundle.
+async fn write_support_bundle(
+    ctx: &Context,
+    docker: &Docker,
+    self_version: &semver::Version,
+    output: &Path,
+    log_lines: usize,
+    bind_dirs: &compose::BindDirs,
+) -> anyhow::Result<()> {
+    let f =
+        File::create(output).with_context(|| format!("Failed to create `{}`", output.display()))?;
+    let mut zip = zip::ZipWriter::new(f);
+    let redactor = redactor(ctx);
+    let mut add = |name: &str, content: anyhow::Result<Vec<u8>>| -> anyhow::Result<()> {
+        // Everything goes through the redactor: logs and hook definitions may contain secrets too.
+        let content = content.and_then(|content| {
+            if name.ends_with(".json") {
+                redactor.json_bytes(content)
+            } else {
+                Ok(redactor.bytes(content))
+            }
+        });
+        match content {
+            Ok(content) => {
+                zip.start_file(name, zip::write::SimpleFileOptions::default())?;
+                zip.write_all(&content)?;
+            }
+            Err(error) => tracing::warn!(%error, "Skipping `{name}` in the support bundle"),
+        }
+        Ok(())
+    };
+
+    let diagnostics = collect_diagnostics(docker).await.and_then(|diagnostics| {
+        let mut out = vec![];
+        diagnostics.write(&mut out)?;
+        Ok(out)
+    });
+    add("diagnostics.txt", diagnostics)?;
+
+    let status = status_report(ctx, docker, self_version)
+        .await
+        .and_then(|report| serde_json::to_vec_pretty(&report).map_err(Into::into));
+    add("status.json", status)?;
+
+    add(
+        CONFIG_JSON,
+        std::fs::read(ctx.config_dir.join(CONFIG_JSON)).map_err(Into::into),
+    )?;
+
+    if let Some(msde_dir) = ctx.msde_dir.as_ref() {
+        add(
+            METADATA_JSON,
+            std::fs::read(msde_dir.join(METADATA_JSON)).map_err(Into::into),
+        )?;
+        let output_log = msde_dir.join("log").join("output.log");
+        if output_log.exists() {
+            add("output.log", std::fs::read(output_log).map_err(Into::into))?;
+        }
+        let features = std::fs::read_to_string(msde_dir.join(METADATA_JSON))
+            .ok()
+            .and_then(|f| serde_json::from_str::<msde_cli::env::PackageLocalConfig>(&f).ok())
+            .and_then(|metadata| metadata.last_features)
+            .unwrap_or_default();
+        add(
+            "volumes.yml",
+            compose::generate_volumes(&features, msde_dir, bind_dirs, false)
+                .map(String::into_bytes),
+        )?;
+    }
+
+    // Stopped containers too: the logs of a crashed service are usually the reason for the bundle.
+    let opts = ContainerListOpts::builder().all(true).build();
+    let containers = match docker.containers().list(&opts).await {
+        Ok(containers) => containers,
+        Err(error) => {
+            tracing::warn!(%error, "Skipping the container logs in the support bundle");
+            vec![]
+        }
+    };
+    for container in containers {
+        let Some(name) = container.names.as_ref().and_then(|names| names.first()) else {
+            continue;
+        };
+        let is_merigo = container
+            .image
+            .as_ref()
+            .is_some_and(|image| REPOS_AND_IMAGES.iter().any(|im| image.contains(im)))
+            || ExtendedFeature::value_variants()
+                .iter()
+                .any(|feature| feature.wait_target() == name);
+        let (true, Some(id)) = (is_merigo, container.id.as_ref()) else {
+            continue;
+        };
+        let opts = docker_api::opts::LogsOpts::builder()
+            .stdout(true)
+            .stderr(true)
+            .timestamps(true)
+            .n_lines(log_lines)
+            .build();
+        let handle = docker.containers().get(id);
+        let logs = handle
+            .logs(&opts)
+            .try_fold(vec![], |mut logs, chunk| async move {
+                if let TtyChunk::StdOut(chunk) | TtyChunk::StdErr(chunk) = chunk {
+                    logs.extend(chunk);
+                }
+                Ok(logs)
+            })
+            .await
+            .map_err(Into::into);
+        add(&format!("logs/{}.log", name.trim_start_matches('/')), logs)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Resolve the features of `up`, `run`, `reset` and `restart`. Explicit `--features` or `--profile` always win, otherwise the features of the
+/// last successful run are reused, unless they're cleared with `reset`.
+fn features_or_last_used(
+    ctx: &Context,
+    features: Vec<FeatureSelector>,
+    profile: Option<String>,
+    last_features: Option<Vec<Feature>>,
+    reset: bool,
+) -> anyhow::Result<Vec<Feature>> {
+    if reset {
+        ctx.clear_last_features()?;
+    }
+    match last_features {
+        Some(last_features) if !reset && features.is_empty() && profile.is_none() => {
+            tracing::info!(
+                features = %describe_features(&last_features),
+                "No features or profile given, using the features of the last run. Pass `--features` or `--profile` to choose others."
+            );
+            Ok(last_features)
+        }
+        None if !reset && features.is_empty() && profile.is_none() => {
+            tracing::info!("No previously used features are recorded, using the minimal profile.");
+            Ok(vec![])
+        }
+        _ => Ok(resolve_features(
+            FeatureSelector::resolve(&features),
+            profile,
+            ctx,
+        )),
+    }
+}
+
+/// Record the features of a successful run for `restart`. Failing to do so shouldn't fail the command itself.
+fn remember_features(ctx: &Context, features: &[Feature]) {
+    if let Err(error) = ctx.write_last_features(features) {
+        tracing::warn!(%error, "failed to record the used features");
+    }
+}
+
+fn print_games(listing: &[GameListing]) {
+    if listing.is_empty() {
+        println!("No games found.");
+        return;
+    }
+    let show_running = listing.iter().any(|entry| entry.running.is_some());
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    print!(
+        "{:<24} {:<16} {:<36} {:<36} {:<6} {:<8}",
+        "GAME", "STAGE", "GUID", "SUID", "LAUNCH", "DISABLED"
+    );
+    println!("{}", if show_running { " RUNNING" } else { "" });
+    for entry in listing {
+        print!(
+            "{:<24} {:<16} {:<36} {:<36} {:<6} {:<8}",
+            entry.game,
+            entry.stage.as_deref().unwrap_or("-"),
+            entry.guid,
+            entry.suid,
+            yes_no(entry.launch),
+            yes_no(entry.disabled),
+        );
+        match entry.running {
+            Some(running) => println!(" {}", yes_no(running)),
+            None => println!(),
+        }
+    }
+}
+
+fn print_games_status(statuses: &[StageStatus]) {
+    if statuses.is_empty() {
+        println!("No games are loaded.");
+        return;
+    }
+    println!(
+        "{:<24} {:<16} {:<36} {:<36} {:<6} STATE",
+        "GAME", "STAGE", "GUID", "SUID", "LAUNCH"
+    );
+    for status in statuses {
+        println!(
+            "{:<24} {:<16} {:<36} {:<36} {:<6} {}",
+            status.game,
+            status.stage.as_deref().unwrap_or("-"),
+            status.guid,
+            status.suid,
+            if status.launch { "yes" } else { "no" },
+            status.state,
+        );
+    }
+}
+
+fn describe_features(features: &[Feature]) -> String {
+    if features.is_empty() {
+        return String::from("(no features)");
+    }
+    features
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, serde::Serialize)]
+struct StatusReport {
+    cli_version: String,
+    project: Option<ProjectStatus>,
+    services: Vec<ServiceStatus>,
+    msde_health: Option<String>,
+    registry_cache: RegistryCacheStatus,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ProjectStatus {
+    path: PathBuf,
+    self_version: Option<String>,
+    target_msde_version: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ServiceStatus {
+    name: String,
+    running: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RegistryCacheStatus {
+    Missing,
+    Invalid,
+    Expired,
+    Valid,
+}
+
+impl StatusReport {
+    fn print(&self) {
+        println!("CLI version    : {}", self.cli_version);
+        match &self.project {
+            None => println!("Project        : not set"),
+            Some(project) => {
+                println!("Project        : {}", project.path.display());
+                let unknown = String::from("unknown");
+                println!(
+                    "Project version: {}",
+                    project.self_version.as_ref().unwrap_or(&unknown)
+                );
+                println!(
+                    "MSDE version   : {}",
+                    project.target_msde_version.as_ref().unwrap_or(&unknown)
+                );
+                if let Some(error) = &project.error {
+                    println!("Project error  : {error}");
+                }
+            }
+        }
+        println!("Services:");
+        for service in &self.services {
+            let state = if service.running {
+                "running"
+            } else {
+                "stopped"
+            };
+            println!("  {:<8} {state}", service.name);
+        }
+        println!(
+            "MSDE health    : {}",
+            self.msde_health.as_deref().unwrap_or("not running")
+        );
+        let cache = match self.registry_cache {
+            RegistryCacheStatus::Missing => "missing",
+            RegistryCacheStatus::Invalid => "invalid",
+            RegistryCacheStatus::Expired => "expired",
+            RegistryCacheStatus::Valid => "valid",
+        };
+        println!("Registry cache : {cache}");
+    }
+}
+
+async fn status_report(
+    ctx: &Context,
+    docker: &Docker,
+    self_version: &semver::Version,
+) -> anyhow::Result<StatusReport> {
+    let project = ctx.msde_dir.as_ref().map(|msde_dir| {
+        match std::fs::read_to_string(msde_dir.join(METADATA_JSON))
+            .map_err(anyhow::Error::from)
+            .and_then(|f| {
+                serde_json::from_str::<msde_cli::env::PackageLocalConfig>(&f).map_err(Into::into)
+            }) {
+            Ok(metadata) => ProjectStatus {
+                path: msde_dir.clone(),
+                self_version: Some(metadata.self_version),
+                target_msde_version: metadata.target_msde_version,
+                error: None,
+            },
+            Err(e) => ProjectStatus {
+                path: msde_dir.clone(),
+                self_version: None,
+                target_msde_version: None,
+                error: Some(format!("failed to read {METADATA_JSON}: {e}")),
+            },
+        }
+    });
+
+    let containers = msde_cli::compose::running_containers(docker).await?;
+    let services = ExtendedFeature::value_variants()
+        .iter()
+        // The bot runs in the MSDE container.
+        .filter(|feature| !matches!(feature, ExtendedFeature::Bot))
+        .map(|feature| ServiceStatus {
+            name: feature.to_string(),
+            running: containers.contains_key(feature.wait_target()),
+        })
+        .collect();
+
+    let msde_health = match containers.get(ExtendedFeature::MSDE.wait_target()) {
+        Some(id) => Some(
+            docker
+                .containers()
+                .get(id)
+                .inspect()
+                .await?
+                .state
+                .and_then(|state| state.health)
+                .and_then(|health| health.status)
+                .unwrap_or_else(|| String::from("unknown")),
+        ),
+        None => None,
+    };
+
+    let registry_cache = match std::fs::read_to_string(ctx.config_dir.join("index.json")) {
+        Err(_) => RegistryCacheStatus::Missing,
+        Ok(f) => match serde_json::from_str::<Index>(&f) {
+            Err(_) => RegistryCacheStatus::Invalid,
+            Ok(index) if index.valid_until < time::OffsetDateTime::now_utc().unix_timestamp() => {
+                RegistryCacheStatus::Expired
+            }
+            Ok(_) => RegistryCacheStatus::Valid,
+        },
+    };
+
+    Ok(StatusReport {
+        cli_version: self_version.to_string(),
+        project,
+        services,
+        msde_health,
+        registry_cache,
+    })
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 struct ListedContainer {
     id: String,
     names: Option<Vec<String>>,
@@ -1057,9 +2373,11 @@ async fn create_index(
                 .get(&url)
                 .bearer_auth(key)
                 .send()
-                .await?
+                .await
+                .map_err(registry_error)?
                 .json::<ApiResponse>()
                 .await
+                .map_err(registry_error)
         }
     });
 
@@ -1069,9 +2387,9 @@ async fn create_index(
         .into_iter()
         .filter_map(|response| match response {
             ApiResponse::Ok(metadata) => Some(metadata),
-            ApiResponse::Error(e) => { 
+            ApiResponse::Error(e) => {
                 tracing::error!(error = ?e, "Error getting a response");
-                None 
+                None
             }
         })
         .map(|metadata| {
@@ -1110,6 +2428,29 @@ async fn create_index(
     Ok(())
 }
 
+fn registry_error(e: reqwest::Error) -> anyhow::Error {
+    if e.is_timeout() {
+        anyhow::anyhow!("The registry timed out. Consider raising `--http-timeout` or `--connect-timeout`. ({e})")
+    } else {
+        e.into()
+    }
+}
+
+/// Fail early if an explicitly given stages file doesn't exist, before anything is started.
+fn ensure_stages_file(path: Option<&Path>) -> anyhow::Result<()> {
+    if let Some(path) = path {
+        anyhow::ensure!(
+            path.is_file(),
+            "The stages file `{}` does not exist",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// The file in the config directory that marks that the completions install hint was already shown.
+const COMPLETIONS_HINT_MARKER: &str = ".completions-hint-shown";
+
 fn completions_path(shell: Shell) -> Option<&'static str> {
     match shell {
         Shell::Bash => Some("/usr/share/bash-completion/completions/msde-cli.bash"),
@@ -1132,13 +2473,25 @@ struct VersionedImage<'v> {
 }
 
 #[cfg(unix)]
-pub fn new_docker() -> docker_api::Result<Docker> {
-    Ok(Docker::unix("/var/run/docker.sock"))
-}
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
 
 #[cfg(not(unix))]
-pub fn new_docker() -> docker_api::Result<Docker> {
-    Docker::new("tcp://127.0.0.1:2375")
+const DEFAULT_DOCKER_HOST: &str = "tcp://127.0.0.1:2375";
+
+/// The names of the running targets for completions. The daemon must answer quickly, since this runs on every <TAB>.
+async fn running_targets(docker_host: Option<&str>) -> anyhow::Result<Vec<String>> {
+    let docker = new_docker(docker_host.unwrap_or(DEFAULT_DOCKER_HOST))?;
+    let targets = tokio::time::timeout(Duration::from_secs(2), Target::running(&docker)).await??;
+    Ok(targets.iter().map(ToString::to_string).collect())
+}
+
+/// Connect to the Docker daemon at the given endpoint, in the same format as the `DOCKER_HOST` environment variable of the Docker CLI.
+pub fn new_docker(host: &str) -> anyhow::Result<Docker> {
+    // docker_api only has Unix socket and TCP transports.
+    if host.starts_with("npipe://") {
+        anyhow::bail!("Named pipes (`{host}`) are not supported. Expose the Docker daemon over TCP, and use a `tcp://` address instead.");
+    }
+    Docker::new(host).with_context(|| format!("Invalid Docker host `{host}`"))
 }
 
 fn handle_yes_no_prompt() -> bool {
@@ -1158,14 +2511,127 @@ fn handle_yes_no_prompt() -> bool {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum PullStatus {
+    Pulled,
+    UpToDate,
+    Failed,
+}
+
+/// The result of pulling a single image, as printed by `pull --json`.
+#[derive(Debug, serde::Serialize)]
+struct PullReport {
+    image: String,
+    tag: String,
+    status: PullStatus,
+    /// The size of the image in bytes, if it's available locally after the pull.
+    size: Option<i64>,
+    duration_secs: f64,
+}
+
+/// The number of images pulled at the same time by default.
+const DEFAULT_PULL_CONCURRENCY: usize = 4;
+
+/// The maximum number of attempts to pull a single image, including the first one.
+const MAX_PULL_ATTEMPTS: u32 = 4;
+
+struct PullAllOpts {
+    quiet: bool,
+    format: OutputFormat,
+    /// The maximum number of images pulled at the same time.
+    max_concurrency: usize,
+    /// Retry transient failures (network errors, registry 5xx and rate limits) with an exponential backoff.
+    retry: bool,
+}
+
+impl Default for PullAllOpts {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            format: OutputFormat::Text,
+            max_concurrency: DEFAULT_PULL_CONCURRENCY,
+            retry: true,
+        }
+    }
+}
+
+/// Pull all images with bounded concurrency, and exit with an error if any of them failed. In quiet mode the progress bars
+/// are hidden, and a single line is logged per image instead. In the structured formats, the per-image results are printed to stdout
+/// as well.
+async fn pull_all(
+    docker: &Docker,
+    images_and_tags: Vec<(String, String)>,
+    credentials: Option<&SecretCredentials>,
+    opts: PullAllOpts,
+) -> anyhow::Result<()> {
+    let m = indicatif::MultiProgress::new();
+    if opts.quiet {
+        m.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    let mut tasks = vec![];
+    for (i, (image, tag)) in images_and_tags.into_iter().enumerate() {
+        let pb = m.add(progress_bar());
+        pb.set_message(format!("Waiting to pull image {}:{}", &image, &tag));
+
+        tasks.push(async move {
+            pull(
+                docker,
+                (image, tag),
+                credentials,
+                pb,
+                opts.quiet,
+                opts.retry,
+            )
+            .await
+            .map(|report| (i, report))
+        });
+    }
+    let mut outcome = futures::stream::iter(tasks)
+        .buffer_unordered(opts.max_concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .inspect_err(|_| {
+            m.clear().unwrap();
+        })?;
+    m.clear().unwrap();
+    // Report in the requested order, not in the order of completion.
+    outcome.sort_by_key(|(i, _)| *i);
+    let outcome = outcome
+        .into_iter()
+        .map(|(_, report)| report)
+        .collect::<Vec<_>>();
+    opts.format.print_structured(&outcome)?;
+    if outcome
+        .iter()
+        .all(|report| report.status != PullStatus::Failed)
+    {
+        tracing::info!("All targets pulled!")
+    } else {
+        tracing::error!("Error pulling some of the images. Check errors above.");
+        std::process::exit(-1);
+    }
+    Ok(())
+}
+
+enum PullAttempt {
+    Pulled,
+    UpToDate,
+    Failed { transient: bool },
+}
+
 #[tracing::instrument(skip(docker, credentials, pb))]
 async fn pull(
     docker: &Docker,
     (image, tag): (String, String),
     credentials: Option<&SecretCredentials>,
     pb: ProgressBar,
-) -> anyhow::Result<bool> {
-    let mut errored = false;
+    quiet: bool,
+    retry: bool,
+) -> anyhow::Result<PullReport> {
+    let started = std::time::Instant::now();
     let opts = docker_api::opts::PullOpts::builder()
         .image(&image)
         .tag(&tag)
@@ -1180,9 +2646,130 @@ async fn pull(
         .build();
 
     let images = docker.images();
-    let mut stream = images.pull(&opts);
+    let mut backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_secs(2))
+        .with_max_elapsed_time(Some(Duration::from_secs(120)))
+        .build();
+    let mut attempts = 0;
+
+    let status = loop {
+        attempts += 1;
+        pb.set_message(format!("Pulling image {}:{}", &image, &tag));
+        match pull_once(&images, &opts, &pb).await {
+            PullAttempt::Pulled => break PullStatus::Pulled,
+            PullAttempt::UpToDate => break PullStatus::UpToDate,
+            PullAttempt::Failed { transient: true } if retry && attempts < MAX_PULL_ATTEMPTS => {
+                let Some(backoff_duration) = backoff.next_backoff() else {
+                    break PullStatus::Failed;
+                };
+                pb.suspend(|| {
+                    tracing::warn!(
+                        "Pulling image {image}:{tag} failed with a transient error, retrying in {:.1}s (attempt {}/{MAX_PULL_ATTEMPTS})",
+                        backoff_duration.as_secs_f64(),
+                        attempts + 1,
+                    )
+                });
+                pb.set_message(format!(
+                    "Waiting to retry pulling image {}:{}",
+                    &image, &tag
+                ));
+                tokio::time::sleep(backoff_duration).await;
+            }
+            PullAttempt::Failed { .. } => break PullStatus::Failed,
+        }
+    };
+
+    match status {
+        PullStatus::Failed => {
+            pb.finish_with_message("Error pulling image. Errors should be logged above.");
+            if quiet {
+                tracing::error!("Failed to pull image {image}:{tag}");
+            }
+        }
+        PullStatus::UpToDate => {
+            pb.finish_with_message("Up to date.");
+            if quiet {
+                tracing::info!("Image {image}:{tag} is up to date");
+            }
+        }
+        PullStatus::Pulled => {
+            pb.finish_with_message("Done.");
+            if quiet {
+                tracing::info!("Pulled image {image}:{tag}");
+            }
+        }
+    }
+    let size = match status {
+        PullStatus::Failed => None,
+        _ => images
+            .get(format!("{image}:{tag}"))
+            .inspect()
+            .await
+            .ok()
+            .and_then(|inspect| inspect.size),
+    };
+
+    Ok(PullReport {
+        image,
+        tag,
+        status,
+        size,
+        duration_secs: started.elapsed().as_secs_f64(),
+    })
+}
+
+/// The download progress of the layers of a single image, keyed by the layer id. The daemon reports the layers
+/// separately (and downloads some of them at the same time), so the overall progress is their sum.
+#[derive(Default)]
+struct LayerProgress {
+    layers: HashMap<String, (u64, u64)>,
+}
+
+impl LayerProgress {
+    /// Record a pull status chunk, and return the overall downloaded and total bytes, if the daemon reported any totals yet.
+    fn update(
+        &mut self,
+        id: Option<&str>,
+        status: &str,
+        detail: Option<&docker_api::models::ProgressDetail>,
+    ) -> Option<(u64, u64)> {
+        let id = id?;
+        match (status, detail) {
+            ("Downloading", Some(&docker_api::models::ProgressDetail { current, total })) => {
+                if let Some(total) = total.filter(|total| *total > 0) {
+                    self.layers
+                        .insert(id.to_owned(), (current.unwrap_or(0).min(total), total));
+                }
+            }
+            ("Download complete" | "Pull complete", _) => {
+                if let Some((current, total)) = self.layers.get_mut(id) {
+                    *current = *total;
+                }
+            }
+            _ => {}
+        }
+        if self.layers.is_empty() {
+            return None;
+        }
+        Some(
+            self.layers
+                .values()
+                .fold((0, 0), |(current, total), (c, t)| (current + c, total + t)),
+        )
+    }
+}
 
-    pb.set_message(format!("Pulling image {}:{}", &image, &tag));
+async fn pull_once(
+    images: &docker_api::Images,
+    opts: &docker_api::opts::PullOpts,
+    pb: &ProgressBar,
+) -> PullAttempt {
+    let mut up_to_date = false;
+    let mut layers = LayerProgress::default();
+    let mut downloading = false;
+    // A previous attempt may have switched to the byte-based bar.
+    pb.set_style(spinner_style());
+    let mut stream = images.pull(opts);
     while let Some(pull_result) = stream.next().await {
         match pull_result {
             Ok(output) => match output {
@@ -1193,31 +2780,78 @@ async fn pull(
                     pb.suspend(|| {
                         tracing::error!(err = ?error, detail = ?error_detail, "Error occurred");
                     });
-                    errored = true;
-                    pb.finish_with_message("Error pulling image. Errors should be logged above.");
-                    break;
+                    return PullAttempt::Failed {
+                        transient: is_transient_pull_error(&error),
+                    };
                 }
 
-                docker_api::models::ImageBuildChunk::PullStatus { .. } => {
-                    pb.inc(1);
+                docker_api::models::ImageBuildChunk::PullStatus {
+                    status,
+                    id,
+                    progress_detail,
+                    ..
+                } => {
+                    // The daemon reports "Status: Image is up to date for <image>" when nothing was downloaded.
+                    if status.starts_with("Status: Image is up to date") {
+                        up_to_date = true;
+                    }
+                    match layers.update(id.as_deref(), &status, progress_detail.as_ref()) {
+                        Some((current, total)) => {
+                            if !downloading {
+                                pb.set_style(download_style());
+                                downloading = true;
+                            }
+                            pb.set_length(total);
+                            pb.set_position(current);
+                        }
+                        // Without any totals, there's nothing better than a spinner.
+                        None => pb.inc(1),
+                    }
                 }
                 _ => {}
             },
             Err(e) => {
                 pb.suspend(|| tracing::error!(err = ?e, "Error occurred"));
-                errored = true;
-                pb.finish_with_message("Error pulling image. Errors should be logged above.");
-                break;
+                let transient = match &e {
+                    docker_api::Error::Hyper(_) | docker_api::Error::IO(_) => true,
+                    docker_api::Error::Fault { code, message } => {
+                        code.as_u16() == 429
+                            || matches!(code.as_u16(), 502..=504)
+                            || is_transient_pull_error(message)
+                    }
+                    _ => false,
+                };
+                return PullAttempt::Failed { transient };
             }
         }
     }
-
-    if !errored {
-        pb.finish_with_message("Done.");
-        return Ok(true);
+    if up_to_date {
+        PullAttempt::UpToDate
+    } else {
+        PullAttempt::Pulled
     }
+}
 
-    Ok(false)
+/// Whether a pull error reported by the Docker daemon is worth retrying. The daemon forwards registry errors as plain
+/// messages, so this is a best-effort match on the usual network and server-side failures.
+fn is_transient_pull_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    [
+        "timeout",
+        "timed out",
+        "connection reset",
+        "connection refused",
+        "unexpected eof",
+        "toomanyrequests",
+        "too many requests",
+        "500 internal server error",
+        "502 bad gateway",
+        "503 service unavailable",
+        "504 gateway timeout",
+        "temporary failure",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
 }
 
 fn get_images_and_tags(targets: &[Target]) -> Vec<(String, String)> {
@@ -1230,30 +2864,62 @@ fn get_images_and_tags(targets: &[Target]) -> Vec<(String, String)> {
 fn progress_bar() -> ProgressBar {
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(Duration::from_millis(80));
-    pb.set_style(
-        ProgressStyle::with_template("{spinner:.blue} {elapsed:3} {msg}")
-            .unwrap()
-            .tick_strings(&[
-                "[    ]", "[=   ]", "[==  ]", "[=== ]", "[====]", "[ ===]", "[  ==]", "[   =]",
-                "[    ]", "[   =]", "[  ==]", "[ ===]", "[====]", "[=== ]", "[==  ]", "[=   ]",
-            ]),
-    );
+    pb.set_style(spinner_style());
     pb
 }
 
-fn target_version_check(targets: &[Target], ctx: &Context) -> anyhow::Result<()> {
-    let file = File::open(ctx.config_dir.join("index.json"))?;
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{spinner:.blue} {elapsed:3} {msg}")
+        .unwrap()
+        .tick_strings(&[
+            "[    ]", "[=   ]", "[==  ]", "[=== ]", "[====]", "[ ===]", "[  ==]", "[   =]",
+            "[    ]", "[   =]", "[  ==]", "[ ===]", "[====]", "[=== ]", "[==  ]", "[=   ]",
+        ])
+}
+
+fn download_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{spinner:.blue} {elapsed:3} [{bar:30.cyan/blue}] {percent:>3}% {bytes}/{total_bytes} ({bytes_per_sec}) {msg}",
+    )
+    .unwrap()
+    .progress_chars("=> ")
+}
+
+/// Checks the requested versions against the local cache. Unknown versions are only reported, unless `require_version`
+/// is set, in which case the first unknown version is an error.
+fn target_version_check(
+    targets: &[Target],
+    ctx: &Context,
+    require_version: bool,
+) -> anyhow::Result<()> {
+    let file = File::open(ctx.config_dir.join("index.json"))
+        .context("local cache not found, run `msde-cli build-cache` first")?;
     let reader = BufReader::new(file);
     let index: Index = serde_json::from_reader(reader)?;
     for target in targets {
         let version = target.get_version();
         if let Some(version) = version {
-            let entry = index
+            let Some(entry) = index
                 .content
                 .iter()
                 .find(|metadata| metadata.for_target(target))
-                .unwrap();
+            else {
+                anyhow::ensure!(!require_version, "`{target}` is not in the local cache");
+                tracing::warn!(%target, "target is not in the local cache");
+                continue;
+            };
             if !entry.contains_version(version) {
+                if require_version {
+                    let available: Vec<_> = entry
+                        .sorted_versions()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect();
+                    anyhow::bail!(
+                        "unknown version `{version}` for `{target}`, available versions: {}",
+                        available.join(", ")
+                    );
+                }
                 tracing::warn!(%target, %version, available_versions = ?entry.parsed_versions.iter(), "Specified unknown version for target");
             }
         }
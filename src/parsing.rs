@@ -1,6 +1,6 @@
-use winnow::ascii::space0;
+use winnow::ascii::{dec_int, space0};
 use winnow::combinator::{alt, delimited, preceded, terminated};
-use winnow::error::StrContext;
+use winnow::error::{ErrMode, ErrorKind, ParserError, StrContext};
 use winnow::prelude::PResult;
 use winnow::token::{literal, take_while};
 use winnow::Parser;
@@ -9,12 +9,21 @@ use winnow::Parser;
 pub enum OkVariant<'a> {
     Uuid(uuid::Uuid),
     String(&'a str),
+    Int(i64),
+    /// The name of an atom, without the leading colon.
+    Atom(&'a str),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ElixirTuple<'a> {
     ErrorEx(&'a str),
     OkEx(OkVariant<'a>),
+    /// An error with a nested `{reason, detail}` tuple, e.g. `{:error, {:not_found, "game"}}`. The detail is the content of
+    /// a quoted string, the name of an atom or the digits of an integer.
+    ErrorDetailed {
+        atom: &'a str,
+        detail: &'a str,
+    },
 }
 
 fn is_atom_char(c: char) -> bool {
@@ -62,10 +71,18 @@ fn parse_quoted_string<'a>(input: &'_ mut &'a str) -> PResult<&'a str> {
     .parse_next(input)
 }
 
+fn parse_integer(input: &mut &str) -> PResult<i64> {
+    delimited(space0, dec_int, space0)
+        .context(StrContext::Label("integer"))
+        .parse_next(input)
+}
+
 fn parse_ok_variant<'a>(input: &mut &'a str) -> PResult<OkVariant<'a>> {
     alt((
         parse_uuid.map(OkVariant::Uuid),
         parse_quoted_string.map(OkVariant::String),
+        parse_integer.map(OkVariant::Int),
+        parse_atom.map(OkVariant::Atom),
     ))
     .context(StrContext::Label("ok_variant"))
     .parse_next(input)
@@ -84,6 +101,33 @@ fn parse_error<'a>(input: &mut &'a str) -> PResult<ElixirTuple<'a>> {
         .parse_next(input)
 }
 
+fn parse_detail<'a>(input: &mut &'a str) -> PResult<&'a str> {
+    alt((
+        parse_quoted_string,
+        parse_atom,
+        delimited(space0, parse_integer.recognize(), space0),
+    ))
+    .context(StrContext::Label("detail"))
+    .parse_next(input)
+}
+
+fn parse_nested_error<'a>(input: &mut &'a str) -> PResult<ElixirTuple<'a>> {
+    (
+        literal("{"),
+        delimited(space0, literal(":error"), space0),
+        delimited(space0, literal(","), space0),
+        delimited(space0, literal("{"), space0),
+        parse_atom,
+        delimited(space0, literal(","), space0),
+        parse_detail,
+        delimited(space0, literal("}"), space0),
+        delimited(space0, literal("}"), space0),
+    )
+        .map(|(_, _, _, _, atom, _, detail, _, _)| ElixirTuple::ErrorDetailed { atom, detail })
+        .context(StrContext::Label("nested_error_tuple"))
+        .parse_next(input)
+}
+
 fn parse_ok<'a>(input: &mut &'a str) -> PResult<ElixirTuple<'a>> {
     (
         literal("{"),
@@ -99,7 +143,292 @@ fn parse_ok<'a>(input: &mut &'a str) -> PResult<ElixirTuple<'a>> {
 
 /// Parses two-element simple Elixir ok and error tuples _reliably_. These usually come from the Game.start and Game.sync calls.
 pub fn parse_simple_tuple<'a>(input: &mut &'a str) -> PResult<ElixirTuple<'a>> {
-    terminated(alt((parse_error, parse_ok)), space0).parse_next(input)
+    terminated(alt((parse_error, parse_nested_error, parse_ok)), space0).parse_next(input)
 }
 
-// TODO: Add unit tests
+/// Removes every ANSI escape sequence (e.g. `\u{1b}[36m`) from the input, wherever it is.
+pub fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("\u{1b}[") {
+        output.push_str(&rest[..start]);
+        let sequence = &rest[start + 2..];
+        // The sequence ends with its first character in the `@`..`~` range, everything before that are parameters.
+        rest = match sequence.find(|c: char| ('@'..='~').contains(&c)) {
+            Some(end) => &sequence[end + 1..],
+            None => "",
+        };
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Strips the ANSI escape sequences (e.g. `\u{1b}[36m`) and whitespace from both ends of the input.
+fn trim_ansi(mut input: &str) -> &str {
+    loop {
+        let trimmed = input.trim();
+        let trimmed = match trimmed.strip_prefix("\u{1b}[") {
+            Some(rest) => match rest.find(|c: char| c.is_ascii_alphabetic()) {
+                Some(end) => &rest[end + 1..],
+                None => trimmed,
+            },
+            None => trimmed,
+        };
+        let trimmed = match trimmed.rfind("\u{1b}[") {
+            Some(start)
+                if trimmed[start + 2..]
+                    .strip_suffix(|c: char| c.is_ascii_alphabetic())
+                    .is_some_and(|params| {
+                        params.chars().all(|c| c.is_ascii_digit() || c == ';')
+                    }) =>
+            {
+                &trimmed[..start]
+            }
+            _ => trimmed,
+        };
+        if trimmed == input {
+            return input;
+        }
+        input = trimmed;
+    }
+}
+
+/// Same as [`parse_simple_tuple`], but tolerates the noise that real RPC output contains: ANSI color codes and log lines
+/// printed before the tuple. The tuple must be the last thing in the output.
+pub fn parse_tuple_lenient(input: &str) -> PResult<ElixirTuple<'_>> {
+    let trimmed = trim_ansi(input);
+    let last_line = trim_ansi(trimmed.lines().last().unwrap_or_default());
+    // Log output may also precede the tuple on the same line. The tuple starts at the first brace from which the rest of
+    // the line parses, so braces in the log output, nested tuples and braces in quoted strings are all handled.
+    let mut error = ErrMode::from_error_kind(&last_line, ErrorKind::Eof);
+    for (start, _) in last_line.match_indices('{') {
+        let mut tuple = &last_line[start..];
+        match parse_simple_tuple(&mut tuple) {
+            Ok(parsed) if tuple.is_empty() => return Ok(parsed),
+            Ok(_) => error = ErrMode::from_error_kind(&tuple, ErrorKind::Eof),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "5f0c6a38-2d0c-4c8e-9d0b-0d6c2b0f8a1e";
+
+    fn parse(input: &str) -> PResult<ElixirTuple<'_>> {
+        parse_simple_tuple(&mut &*input)
+    }
+
+    #[test]
+    fn parses_ok_uuid() {
+        let input = format!("{{:ok, \"{UUID}\"}}");
+        assert_eq!(
+            parse(&input).unwrap(),
+            ElixirTuple::OkEx(OkVariant::Uuid(UUID.parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn parses_ok_string() {
+        assert_eq!(
+            parse("{:ok, \"Verify Error\"}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("Verify Error"))
+        );
+    }
+
+    #[test]
+    fn parses_ok_string_that_is_not_a_uuid() {
+        assert_eq!(
+            parse("{:ok, \"abc-def\"}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("abc-def"))
+        );
+    }
+
+    #[test]
+    fn parses_error_atom() {
+        assert_eq!(
+            parse("{:error, :game_running}").unwrap(),
+            ElixirTuple::ErrorEx("game_running")
+        );
+    }
+
+    #[test]
+    fn parses_ok_atom() {
+        assert_eq!(
+            parse("{:ok, :running}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::Atom("running"))
+        );
+    }
+
+    #[test]
+    fn parses_ok_integer() {
+        assert_eq!(
+            parse("{:ok, 42}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::Int(42))
+        );
+        assert_eq!(
+            parse("{:ok,-7 }").unwrap(),
+            ElixirTuple::OkEx(OkVariant::Int(-7))
+        );
+    }
+
+    #[test]
+    fn parses_nested_error() {
+        assert_eq!(
+            parse("{:error, {:not_found, \"game\"}}").unwrap(),
+            ElixirTuple::ErrorDetailed {
+                atom: "not_found",
+                detail: "game"
+            }
+        );
+        assert_eq!(
+            parse("{ :error , { :invalid , :stage } }").unwrap(),
+            ElixirTuple::ErrorDetailed {
+                atom: "invalid",
+                detail: "stage"
+            }
+        );
+        assert_eq!(
+            parse("{:error, {:timeout, 5000}}").unwrap(),
+            ElixirTuple::ErrorDetailed {
+                atom: "timeout",
+                detail: "5000"
+            }
+        );
+    }
+
+    #[test]
+    fn parses_whitespace_variants() {
+        for input in [
+            "{:error,:not_found}",
+            "{ :error , :not_found }",
+            "{:error,   :not_found}\n",
+            "{\t:error,\t:not_found\t}",
+        ] {
+            assert_eq!(
+                parse(input).unwrap(),
+                ElixirTuple::ErrorEx("not_found"),
+                "input: {input:?}"
+            );
+        }
+        assert_eq!(
+            parse("{ :ok ,\"Finished\" }  ").unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("Finished"))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for input in [
+            "",
+            ":ok",
+            "{:ok}",
+            "{:ok, }",
+            "{:ok, \"\"}",
+            "{:ok, \"unterminated}",
+            "{:error, game_running}",
+            "{:error, :}",
+            "{:nope, :game_running}",
+            "{:ok, \"Finished\"",
+            "[:ok, \"Finished\"]",
+            "{:ok, 4.2}",
+            "{:ok, 99999999999999999999}",
+            "{:error, {:not_found}}",
+            "{:error, {not_found, \"game\"}}",
+            "{:error, {:not_found, \"game\"}",
+        ] {
+            assert!(parse(input).is_err(), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn lenient_parses_clean_input() {
+        assert_eq!(
+            parse_tuple_lenient("{:ok, \"Finished\"}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("Finished"))
+        );
+    }
+
+    #[test]
+    fn lenient_strips_ansi_codes() {
+        assert_eq!(
+            parse_tuple_lenient("\u{1b}[36m{:error, :game_running}\u{1b}[0m\n").unwrap(),
+            ElixirTuple::ErrorEx("game_running")
+        );
+    }
+
+    #[test]
+    fn lenient_skips_leading_log_lines() {
+        let input = format!(
+            "\u{1b}[36m09:12:13.597 debug [Job.Script] Crashed reading types(), or no types defined %ArgumentError{{message: \"argument error\"}}\n\u{1b}[0m{{:ok, \"{UUID}\"}}\n"
+        );
+        assert_eq!(
+            parse_tuple_lenient(&input).unwrap(),
+            ElixirTuple::OkEx(OkVariant::Uuid(UUID.parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn lenient_skips_log_output_on_the_same_line() {
+        assert_eq!(
+            parse_tuple_lenient(
+                "\u{1b}[33m09:12:13.597 warning [Game] slow\u{1b}[0m{:ok, \"Finished\"}"
+            )
+            .unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("Finished"))
+        );
+    }
+
+    #[test]
+    fn lenient_parses_nested_errors() {
+        assert_eq!(
+            parse_tuple_lenient(
+                "09:12:13.597 debug [Game] %{a: 1}\n{:error, {:not_found, \"game\"}}"
+            )
+            .unwrap(),
+            ElixirTuple::ErrorDetailed {
+                atom: "not_found",
+                detail: "game"
+            }
+        );
+    }
+
+    #[test]
+    fn lenient_parses_strings_with_braces() {
+        assert_eq!(
+            parse_tuple_lenient("{:ok, \"a{b\"}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("a{b"))
+        );
+        assert_eq!(
+            parse_tuple_lenient("\u{1b}[33mwarning %{x: 1}\u{1b}[0m{:ok, \"}{\"}").unwrap(),
+            ElixirTuple::OkEx(OkVariant::String("}{"))
+        );
+    }
+
+    #[test]
+    fn lenient_rejects_malformed_input() {
+        for input in [
+            "",
+            "\u{1b}[0m",
+            "\u{1b}[36m09:12:13.597 debug [Job.Script] %ArgumentError{message: \"argument error\"}\n\u{1b}[0m:ok",
+            "{:ok, \"Finished\"} trailing",
+            "{:ok, \"Finished\"}\nmore log output",
+            "{:error, {:not_found, \"game\"}",
+        ] {
+            assert!(parse_tuple_lenient(input).is_err(), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn strips_ansi_codes_everywhere() {
+        assert_eq!(
+            strip_ansi("\u{1b}[36m09:12:13.597 debug\u{1b}[0m\n\u{1b}[1;31m:ok\u{1b}[0m"),
+            "09:12:13.597 debug\n:ok"
+        );
+        assert_eq!(strip_ansi("no codes [here]"), "no codes [here]");
+        assert_eq!(strip_ansi(":ok\u{1b}[3"), ":ok");
+    }
+}
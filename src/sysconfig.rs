@@ -0,0 +1,199 @@
+//! Editing of Erlang `sys.config` files. Values are located by their keys in the `{Key, Value}` tuples, so the edits don't
+//! depend on the exact formatting of the file, which may change between MSDE versions.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    TupleStart,
+    TupleEnd,
+    ListStart,
+    ListEnd,
+    Comma,
+    /// Anything else: atoms, numbers, strings, or parts of terms that aren't relevant here, like `<<` or `=>`.
+    Term,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+fn tokenize(src: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '%' => {
+                // Comments run until the end of the line.
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '{' => TokenKind::TupleStart,
+            '}' => TokenKind::TupleEnd,
+            '[' => TokenKind::ListStart,
+            ']' => TokenKind::ListEnd,
+            ',' => TokenKind::Comma,
+            '"' | '\'' => {
+                let mut escaped = false;
+                let mut end = None;
+                for (i, next) in chars.by_ref() {
+                    match next {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        next if next == c => {
+                            end = Some(i + next.len_utf8());
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let Some(end) = end else {
+                    anyhow::bail!("unterminated quote starting at byte {start}")
+                };
+                tokens.push(Token {
+                    kind: TokenKind::Term,
+                    span: start..end,
+                });
+                continue;
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, next)) = chars.peek() {
+                    if next.is_whitespace() || "{}[],%\"'".contains(next) {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token {
+                    kind: TokenKind::Term,
+                    span: start..end,
+                });
+                continue;
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: start..start + c.len_utf8(),
+        });
+    }
+    Ok(tokens)
+}
+
+/// Whether `tokens[i..]` starts with `{Key,`.
+fn is_tuple_with_key(src: &str, tokens: &[Token], i: usize, key: &str) -> bool {
+    matches!(
+        tokens.get(i..i + 3),
+        Some([start, k, comma])
+            if start.kind == TokenKind::TupleStart
+                && k.kind == TokenKind::Term
+                && &src[k.span.clone()] == key
+                && comma.kind == TokenKind::Comma
+    )
+}
+
+/// Collect the indices of the value tokens found by following `path` from the tuple's value at `tokens[i]`.
+fn find_values(src: &str, tokens: &[Token], i: usize, path: &[&str], found: &mut Vec<usize>) {
+    let Some((key, rest)) = path.split_first() else {
+        // A single term followed by the end of the tuple.
+        if let Some([value, end]) = tokens.get(i..i + 2) {
+            if value.kind == TokenKind::Term && end.kind == TokenKind::TupleEnd {
+                found.push(i);
+            }
+        }
+        return;
+    };
+    if tokens.get(i).map(|token| token.kind) != Some(TokenKind::ListStart) {
+        return;
+    }
+    // Only look at the direct elements of the list.
+    let mut depth = 0usize;
+    for j in i + 1..tokens.len() {
+        match tokens[j].kind {
+            TokenKind::TupleStart if depth == 0 && is_tuple_with_key(src, tokens, j, key) => {
+                find_values(src, tokens, j + 3, rest, found);
+            }
+            TokenKind::TupleStart | TokenKind::ListStart => depth += 1,
+            TokenKind::TupleEnd | TokenKind::ListEnd if depth == 0 => return,
+            TokenKind::TupleEnd | TokenKind::ListEnd => depth -= 1,
+            TokenKind::Comma | TokenKind::Term => {}
+        }
+    }
+}
+
+/// Set the value of the `{Key, Value}` tuples found by following `path` to `value`. The first key may be anywhere in the
+/// file, the following keys must be in the list value of the previous one, e.g. `["stats", "enable"]` matches
+/// `{stats, [{enable, true}]}`. Every match is updated, and it's an error if nothing matches, so a changed file format
+/// isn't silently ignored.
+pub fn set_value(src: &str, path: &[&str], value: &str) -> anyhow::Result<String> {
+    let Some((first, rest)) = path.split_first() else {
+        anyhow::bail!("the path of the value to set is empty")
+    };
+    let tokens = tokenize(src)?;
+    let mut found = Vec::new();
+    for i in 0..tokens.len() {
+        if is_tuple_with_key(src, &tokens, i, first) {
+            find_values(src, &tokens, i + 3, rest, &mut found);
+        }
+    }
+    anyhow::ensure!(
+        !found.is_empty(),
+        "`{}` was not found in sys.config",
+        path.join(".")
+    );
+    let mut result = src.to_owned();
+    // Replace from the back, so the earlier spans stay valid.
+    for i in found.into_iter().rev() {
+        result.replace_range(tokens[i].span.clone(), value);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_values_regardless_of_formatting() {
+        let src = "[{opentelemetry, [{traces_exporter,otlp}]},\n {msde, [{stats, [ {enable,\n true} ]}]}].";
+        let src = set_value(src, &["traces_exporter"], "none").unwrap();
+        let src = set_value(&src, &["stats", "enable"], "false").unwrap();
+        assert_eq!(
+            src,
+            "[{opentelemetry, [{traces_exporter,none}]},\n {msde, [{stats, [ {enable,\n false} ]}]}]."
+        );
+
+        let spaced = "[{ traces_exporter , otlp }].";
+        assert_eq!(
+            set_value(spaced, &["traces_exporter"], "none").unwrap(),
+            "[{ traces_exporter , none }]."
+        );
+    }
+
+    #[test]
+    fn only_follows_direct_list_elements() {
+        let src = r#"[{evmlistener, [{enable, true}, {pool, [{enable, true}]}]},
+                      {other, [{enable, true}]}, % {evmlistener, [{enable, true}]}
+                      {name, "{evmlistener, [{enable, true}]}"}]."#;
+        let updated = set_value(src, &["evmlistener", "enable"], "false").unwrap();
+        assert_eq!(
+            updated,
+            src.replacen("{enable, true}", "{enable, false}", 1)
+        );
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        let src = "[{stats, [{enabled, true}]}].";
+        assert!(set_value(src, &["stats", "enable"], "false").is_err());
+        assert!(set_value(src, &["traces_exporter"], "none").is_err());
+        assert!(set_value("[{name, \"unterminated}].", &["name"], "x").is_err());
+    }
+}
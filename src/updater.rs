@@ -1,72 +1,388 @@
+use anyhow::Context as _;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use md5::{Digest, Md5};
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fs::{self, File};
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use zip_extensions::*;
 
 use crate::env::Context;
 use crate::MERIGO_EXTENSION;
 
-pub fn md5_update_from_dir(directory: &Path, mut hash: Md5) -> io::Result<Md5> {
-    assert!(directory.is_dir());
+/// Extensions of the text files that may ship next to the BEAM files. Only these are affected by line ending normalization,
+/// the rest of the files (most importantly `.beam` files) are always hashed as-is.
+const TEXT_FILE_EXTENSIONS: &[&str] = &[
+    "app", "appup", "config", "erl", "ex", "exs", "hrl", "json", "md", "rel", "script", "src",
+    "txt", "yaml", "yml",
+];
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            TEXT_FILE_EXTENSIONS
+                .iter()
+                .any(|text_ext| ext.eq_ignore_ascii_case(text_ext))
+        })
+        .unwrap_or(false)
+}
 
-    let mut paths: Vec<_> = fs::read_dir(directory)?
-        .map(|res| res.expect("insufficient permissions").path())
-        .collect();
+/// Options controlling how the BEAM files are hashed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashingOpts {
+    /// Don't display progress while hashing.
+    pub quiet: bool,
+    /// Read files on multiple threads. The resulting checksum is the same as the sequential one.
+    pub parallel: bool,
+}
+
+/// How many files to read in memory at once in parallel mode.
+const PARALLEL_BATCH_SIZE: usize = 256;
+
+fn sorted_dir_entries(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    if !directory.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("`{}` is not a directory", directory.display()),
+        ));
+    }
+
+    let mut paths = fs::read_dir(directory)
+        .map_err(unreadable(directory))?
+        .map(|res| res.map(|entry| entry.path()).map_err(unreadable(directory)))
+        .collect::<io::Result<Vec<_>>>()?;
     paths.sort_by(|a, b| {
         a.to_string_lossy()
             .to_lowercase()
             .cmp(&b.to_string_lossy().to_lowercase())
     });
+    Ok(paths)
+}
 
-    for path in paths {
+/// Read the full content of a file the way it should be hashed.
+fn read_for_hash(path: &Path, normalize_line_endings: bool) -> io::Result<Vec<u8>> {
+    let content = fs::read(path).map_err(unreadable(path))?;
+    if !(normalize_line_endings && is_text_file(path)) {
+        return Ok(content);
+    }
+    let mut normalized = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        normalized.push(byte);
+    }
+    Ok(normalized)
+}
+
+/// Hash the content of the directory recursively. If `normalize_line_endings` is set, CRLF line endings in known text
+/// files are hashed as LF, so files checked out with CRLF conversion (e.g. on Windows) hash the same as the originals.
+/// The progress bar is incremented by one for every hashed file.
+pub fn md5_update_from_dir(
+    directory: &Path,
+    mut hash: Md5,
+    normalize_line_endings: bool,
+    pb: &ProgressBar,
+) -> io::Result<Md5> {
+    for path in sorted_dir_entries(directory)? {
         hash.update(path.file_name().unwrap().to_string_lossy().as_bytes());
 
-        if path.is_file() {
-            let mut file = fs::File::open(&path)?;
+        if path.is_file() && normalize_line_endings && is_text_file(&path) {
+            hash.update(read_for_hash(&path, normalize_line_endings)?);
+            pb.inc(1);
+        } else if path.is_file() {
+            let mut file = fs::File::open(&path).map_err(unreadable(&path))?;
             let mut buffer = [0; 4096];
             loop {
-                let bytes_read = file.read(&mut buffer)?;
+                let bytes_read = file.read(&mut buffer).map_err(unreadable(&path))?;
                 if bytes_read == 0 {
                     break;
                 }
                 hash.update(&buffer[0..bytes_read]);
             }
+            pb.inc(1);
+        } else if path.is_dir() {
+            hash = md5_update_from_dir(&path, hash, normalize_line_endings, pb)?;
+        }
+    }
+    Ok(hash)
+}
+
+/// A single step of the directory hash: either a file name, or the content of a file.
+enum HashInput {
+    Name(String),
+    Content(PathBuf),
+}
+
+/// Flatten the directory walk of [`md5_update_from_dir`] into the exact order its inputs are fed to the hasher.
+fn collect_hash_inputs(directory: &Path, inputs: &mut Vec<HashInput>) -> io::Result<()> {
+    for path in sorted_dir_entries(directory)? {
+        inputs.push(HashInput::Name(
+            path.file_name().unwrap().to_string_lossy().into_owned(),
+        ));
+        if path.is_file() {
+            inputs.push(HashInput::Content(path));
         } else if path.is_dir() {
-            hash = md5_update_from_dir(&path, hash)?;
+            collect_hash_inputs(&path, inputs)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_files_parallel(
+    files: &[&Path],
+    normalize_line_endings: bool,
+    workers: usize,
+) -> io::Result<Vec<Vec<u8>>> {
+    let chunk_size = files.len().div_ceil(workers).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| read_for_hash(path, normalize_line_endings))
+                        .collect::<io::Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut contents = Vec::with_capacity(files.len());
+        for handle in handles {
+            contents.extend(handle.join().expect("hashing thread panicked")?);
+        }
+        Ok(contents)
+    })
+}
+
+/// The same as [`md5_update_from_dir`], but files are read concurrently in batches, and folded into the hash in the original order.
+fn md5_update_from_dir_parallel(
+    directory: &Path,
+    mut hash: Md5,
+    normalize_line_endings: bool,
+    pb: &ProgressBar,
+) -> io::Result<Md5> {
+    let mut inputs = vec![];
+    collect_hash_inputs(directory, &mut inputs)?;
+    let workers = std::thread::available_parallelism()
+        .map(std::num::NonZeroUsize::get)
+        .unwrap_or(4);
+
+    for batch in inputs.chunks(PARALLEL_BATCH_SIZE) {
+        let files: Vec<_> = batch
+            .iter()
+            .filter_map(|input| match input {
+                HashInput::Content(path) => Some(path.as_path()),
+                HashInput::Name(_) => None,
+            })
+            .collect();
+        let mut contents =
+            read_files_parallel(&files, normalize_line_endings, workers)?.into_iter();
+        for input in batch {
+            match input {
+                HashInput::Name(name) => hash.update(name.as_bytes()),
+                HashInput::Content(_) => {
+                    hash.update(contents.next().expect("every file to be read"));
+                    pb.inc(1);
+                }
+            }
         }
     }
     Ok(hash)
 }
 
+/// Attach the offending path to an I/O error, so users know which file or directory couldn't be read.
+fn unreadable(path: &Path) -> impl Fn(io::Error) -> io::Error + '_ {
+    move |e| {
+        io::Error::new(
+            e.kind(),
+            format!("failed to read `{}`: {e}", path.display()),
+        )
+    }
+}
+
 pub fn md5_dir(directory: &Path) -> io::Result<String> {
+    md5_dir_with(directory, false, false, &ProgressBar::hidden())
+}
+
+fn md5_dir_with(
+    directory: &Path,
+    normalize_line_endings: bool,
+    parallel: bool,
+    pb: &ProgressBar,
+) -> io::Result<String> {
     let hasher = Md5::new();
-    let hasher = md5_update_from_dir(directory, hasher)?;
+    let hasher = if parallel {
+        md5_update_from_dir_parallel(directory, hasher, normalize_line_endings, pb)?
+    } else {
+        md5_update_from_dir(directory, hasher, normalize_line_endings, pb)?
+    };
     Ok(format!("{:x}", hasher.finalize()))
 }
 
+fn count_files(directory: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .map(|path| {
+            if path.is_dir() {
+                count_files(&path)
+            } else {
+                u64::from(path.is_file())
+            }
+        })
+        .sum()
+}
+
+fn hashing_progress_bar(directory: &Path, opts: HashingOpts) -> ProgressBar {
+    let pb = ProgressBar::new(count_files(directory));
+    if opts.quiet {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb.set_style(
+        ProgressStyle::with_template("{spinner:.blue} {msg} [{bar:30.blue}] {pos}/{len} files")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    pb.enable_steady_tick(std::time::Duration::from_millis(80));
+    pb.set_message("Hashing BEAM files..");
+    pb
+}
+
+/// Whether the BEAM directory matches the expected checksum. If it only matches after normalizing line endings, the files were
+/// most likely checked out with CRLF conversion. That's harmless for text files, so it's accepted, but we print a hint about it.
+fn matches_checksum(beam_dir: &Path, expected: &str, opts: HashingOpts) -> io::Result<bool> {
+    let pb = hashing_progress_bar(beam_dir, opts);
+    let checksum = md5_dir_with(beam_dir, false, opts.parallel, &pb);
+    pb.finish_and_clear();
+    if checksum?.trim() == expected.trim() {
+        return Ok(true);
+    }
+    let pb = hashing_progress_bar(beam_dir, opts);
+    let checksum = md5_dir_with(beam_dir, true, opts.parallel, &pb);
+    pb.finish_and_clear();
+    if checksum?.trim() == expected.trim() {
+        tracing::warn!(
+            path = %beam_dir.display(),
+            "BEAM files only verify with normalized line endings, they were probably checked out with CRLF conversion. \
+             Consider disabling it for this directory (e.g. `git config core.autocrlf false`, or mark the files as binary in `.gitattributes`)."
+        );
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Read the `checksum.txt` file of an extension directory, which is in the `version:checksum` format.
+pub fn read_checksum_file(
+    ext_priv_dir: impl AsRef<Path>,
+) -> anyhow::Result<(semver::Version, String)> {
+    let mut buf = String::new();
+    let mut f = std::fs::File::open(ext_priv_dir.as_ref().join("checksum.txt"))?;
+    f.read_to_string(&mut buf)?;
+    let Some((version, checksum)) = buf.split_once(':') else {
+        anyhow::bail!("invalid checksum file, file did not contain a ':'")
+    };
+    let version = semver::Version::parse(version)?;
+    Ok((version, checksum.trim().to_owned()))
+}
+
+/// Whether the extension directory already holds intact BEAM files for the given version.
+fn is_current(ext_priv_dir: &Path, version: &semver::Version, opts: HashingOpts) -> bool {
+    let beam_dir = ext_priv_dir.join("beam_files");
+    if !beam_dir.is_dir() {
+        return false;
+    }
+    match read_checksum_file(ext_priv_dir) {
+        Ok((existing_version, checksum)) => {
+            &existing_version == version
+                && matches_checksum(&beam_dir, &checksum, opts).unwrap_or(false)
+        }
+        Err(_) => false,
+    }
+}
+
+/// The outcome of verifying a single extension directory.
+#[derive(Debug)]
+pub struct BeamFilesCheck {
+    pub path: PathBuf,
+    pub version: Option<semver::Version>,
+    pub error: Option<String>,
+}
+
+/// Find every extension directory in the project (directories named after [`MERIGO_EXTENSION`] with a `checksum.txt` inside),
+/// and verify each of them against the version embedded in their own `checksum.txt`.
+pub fn verify_all_beam_files(
+    msde_dir: &Path,
+    opts: HashingOpts,
+) -> anyhow::Result<Vec<BeamFilesCheck>> {
+    let mut ext_priv_dirs: Vec<_> = fs::read_dir(msde_dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            name.starts_with(MERIGO_EXTENSION)
+                && !name.ends_with("-tmp")
+                && path.join("checksum.txt").is_file()
+        })
+        .collect();
+    ext_priv_dirs.sort();
+
+    Ok(ext_priv_dirs
+        .into_iter()
+        .map(|path| match read_checksum_file(&path) {
+            Ok((version, checksum)) => {
+                let error = verify_checksum(&path, &checksum, opts)
+                    .err()
+                    .map(|e| e.to_string());
+                BeamFilesCheck {
+                    path,
+                    version: Some(version),
+                    error,
+                }
+            }
+            Err(e) => BeamFilesCheck {
+                path,
+                version: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect())
+}
+
+fn verify_checksum(ext_priv_dir: &Path, checksum: &str, opts: HashingOpts) -> anyhow::Result<()> {
+    let beam_dir = ext_priv_dir.join("beam_files");
+    anyhow::ensure!(beam_dir.is_dir(), "beam_files directory is missing");
+    anyhow::ensure!(
+        matches_checksum(&beam_dir, checksum, opts)?,
+        "checksum mismatch, BEAM files might be incomplete"
+    );
+    Ok(())
+}
+
 #[tracing::instrument]
 pub fn verify_beam_files<P: AsRef<Path> + std::fmt::Debug>(
     vsn: semver::Version,
     ext_priv_dir: P,
+    opts: HashingOpts,
 ) -> anyhow::Result<()> {
     let beam_dir = ext_priv_dir.as_ref().join("beam_files");
     anyhow::ensure!(
         beam_dir.is_dir(),
         "The Merigo extension is missing. Run win the `--no-verify` flag to bypass."
     );
-    let current_checksum = md5_dir(&beam_dir)?;
-    let mut buf = String::new();
-    let mut f = std::fs::File::open(ext_priv_dir.as_ref().join("checksum.txt"))?;
-    f.read_to_string(&mut buf)?;
-    let Some((version, checksum)) = buf.split_once(':') else {
-        anyhow::bail!("invalid checksum file, file did not contain a ':'")
-    };
-    let version = semver::Version::parse(version)?;
+    let (version, checksum) = read_checksum_file(&ext_priv_dir)?;
+    let checksum_matches = matches_checksum(&beam_dir, &checksum, opts)
+        .context("Failed to hash the BEAM files, some of them are unreadable")?;
 
-    let success = match (version == vsn, checksum.trim() == current_checksum.trim()) {
+    let success = match (version == vsn, checksum_matches) {
         (true, true) => true,
         (false, _) => {
             tracing::warn!("BEAM files are built for version {version}, but you're running MSDE with version {vsn}.");
@@ -85,20 +401,71 @@ pub fn verify_beam_files<P: AsRef<Path> + std::fmt::Debug>(
     Ok(())
 }
 
-#[tracing::instrument]
+/// The default location the BEAM files are downloaded from. Can be overridden to point to an internal mirror.
+pub const DEFAULT_BEAM_FILES_BASE_URL: &str = "https://merigo-beam-files.s3.amazonaws.com";
+
+#[derive(Debug)]
+pub struct UpdateBeamFilesOpts<'a> {
+    /// The base URL to download the BEAM files from, e.g. [`DEFAULT_BEAM_FILES_BASE_URL`].
+    pub base_url: &'a str,
+    pub no_verify: bool,
+    /// Download the BEAM files even if the existing ones are already for the requested version.
+    pub force: bool,
+    /// Don't remove the downloaded zip and the extracted temporary directory, even on failure.
+    pub keep_tmp: bool,
+    /// How to hash the BEAM files when verifying them.
+    pub hashing: HashingOpts,
+}
+
+/// Removes the temporary files of a BEAM file update when dropped, so no error path leaves them behind.
+struct TmpFilesGuard {
+    paths: Vec<PathBuf>,
+    keep: bool,
+}
+
+impl Drop for TmpFilesGuard {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            if !path.exists() {
+                continue;
+            }
+            if self.keep {
+                tracing::info!(path = %path.display(), "Keeping temporary file");
+                continue;
+            }
+            let result = if path.is_dir() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            };
+            if let Err(error) = result {
+                tracing::warn!(path = %path.display(), %error, "Failed to remove temporary file");
+            }
+        }
+    }
+}
+
+/// Download and install the BEAM files for the given version. Returns whether anything was updated.
+#[tracing::instrument(skip(client))]
 pub async fn update_beam_files(
     ctx: &Context,
+    client: &reqwest::Client,
     version: semver::Version,
-    no_verify: bool,
-) -> anyhow::Result<()> {
-    const MERIGO_EXTENSION_TMP_ZIP: &str = "merigo-extension-tmp.zip";
+    opts: UpdateBeamFilesOpts<'_>,
+) -> anyhow::Result<bool> {
     let Some(msde_dir) = ctx.msde_dir.as_ref() else {
         anyhow::bail!("No active project found.");
     };
-    let response = reqwest::get(format!(
-        "https://merigo-beam-files.s3.amazonaws.com/{version}/merigo-extension.zip"
-    ))
-    .await?;
+    if !opts.force && is_current(&msde_dir.join(MERIGO_EXTENSION), &version, opts.hashing) {
+        tracing::info!("BEAM files are already at version `{version}`, skipping. Use `--force` to download them anyway.");
+        return Ok(false);
+    }
+    let base_url = opts.base_url.trim_end_matches('/');
+    let response = client
+        .get(format!("{base_url}/{version}/{MERIGO_EXTENSION}.zip"))
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the BEAM files server at `{base_url}`"))?;
 
     if response.status() != 200 {
         tracing::trace!("response was {}", response.text().await.unwrap());
@@ -106,32 +473,78 @@ pub async fn update_beam_files(
     }
 
     let body = response.bytes().await?;
+    if !opts.no_verify {
+        let expected = fetch_published_checksum(client, base_url, &version).await?;
+        verify_archive_checksum(&body, &expected)?;
+    }
 
-    let mut tmp_file = File::create(msde_dir.join(MERIGO_EXTENSION_TMP_ZIP))?;
+    let tmp_zip = msde_dir.join(format!("{MERIGO_EXTENSION}-tmp.zip"));
+    let tmp_dir = msde_dir.join(format!("{MERIGO_EXTENSION}-tmp"));
+    // Leftovers of an earlier failed run would be mixed into the new extraction.
+    let _ = std::fs::remove_dir_all(&tmp_dir);
+    let _guard = TmpFilesGuard {
+        paths: vec![tmp_zip.clone(), tmp_dir.clone()],
+        keep: opts.keep_tmp,
+    };
+
+    let mut tmp_file = File::create(&tmp_zip)?;
     io::copy(&mut body.as_ref(), &mut tmp_file)?;
     tracing::trace!(path = ?msde_dir, "extracting zip");
-    zip_extract(
-        &msde_dir.join(MERIGO_EXTENSION_TMP_ZIP),
-        &msde_dir.join("merigo-extension-tmp"),
-    )?;
-    if !no_verify {
-        verify_beam_files(version, msde_dir.join("merigo-extension-tmp"))?;
+    zip_extract(&tmp_zip, &tmp_dir)?;
+    if !opts.no_verify {
+        verify_beam_files(version, &tmp_dir, opts.hashing)?;
     }
     tracing::trace!("Copying BEAM files to their real destination..");
     // Ignoring the error, because it may not exist.
     let _ = std::fs::remove_dir_all(msde_dir.join(MERIGO_EXTENSION));
     fs_extra::move_items(
-        &[msde_dir.join("merigo-extension-tmp")],
+        &[&tmp_dir],
         msde_dir.join(MERIGO_EXTENSION),
         &fs_extra::dir::CopyOptions {
             copy_inside: true,
             ..Default::default()
         },
     )?;
-    tracing::trace!("Removing temporal zip.");
-
-    std::fs::remove_file(msde_dir.join(MERIGO_EXTENSION_TMP_ZIP))?;
     tracing::trace!("Done.");
+    Ok(true)
+}
+
+/// Download the published `merigo-extension.zip.sha256` manifest for the given version, and return the hex digest in it.
+/// The manifest is expected in the `sha256sum` output format, but a bare digest is accepted too.
+async fn fetch_published_checksum(
+    client: &reqwest::Client,
+    base_url: &str,
+    version: &semver::Version,
+) -> anyhow::Result<String> {
+    let response = client
+        .get(format!(
+            "{base_url}/{version}/{MERIGO_EXTENSION}.zip.sha256"
+        ))
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach the BEAM files server at `{base_url}`"))?;
+    if response.status() != 200 {
+        anyhow::bail!("Failed to fetch the checksum manifest of the Merigo extension for version `{version}`. To bypass the validation part, pass the `--no-verify` flag.");
+    }
+    let manifest = response.text().await?;
+    let Some(checksum) = manifest.split_whitespace().next() else {
+        anyhow::bail!("invalid checksum manifest, file was empty")
+    };
+    Ok(checksum.to_ascii_lowercase())
+}
+
+fn verify_archive_checksum(bytes: &[u8], expected: &str) -> anyhow::Result<()> {
+    let digest = ring::digest::digest(&ring::digest::SHA256, bytes);
+    let actual = digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    tracing::trace!(%actual, %expected, "comparing archive checksum");
+    anyhow::ensure!(
+        actual == expected,
+        "The downloaded Merigo extension archive doesn't match the published checksum, refusing to extract it."
+    );
     Ok(())
 }
 
@@ -208,8 +621,10 @@ pub trait PerformStep {
     fn perform(self, context: &Context, manual_only: bool) -> anyhow::Result<()>;
 }
 
+type UpgradeFn = Box<dyn FnOnce(&Context) -> anyhow::Result<()>>;
+
 pub struct Auto {
-    f: Box<dyn FnOnce(&Context) -> anyhow::Result<()>>,
+    f: UpgradeFn,
 }
 
 impl std::fmt::Debug for Auto {
@@ -279,25 +694,28 @@ pub fn consecutive_upgrade(
 
 /// This pipeline executes a series of consecutive upgrades, so we don't need to exponentially grow the upgrade matrix for
 /// every possible version we release.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct TransitiveUpgradePipeline {
     pub pipelines: Vec<PackageUpgradePipeline>,
 }
 
 impl TransitiveUpgradePipeline {
     pub fn new() -> Self {
-        Self {
-            pipelines: Vec::new(),
-        }
+        Self::default()
     }
 
-    pub fn with_default_writers(self_version: semver::Version) -> Self {
-        Self {
-            pipelines: vec![
-                PackageUpgradePipeline::default_version_writer(self_version),
-                PackageUpgradePipeline::default_project_extractor(),
-            ],
-        }
+    /// Runs the given pipelines between extracting the project files and writing the new version to `metadata.json`,
+    /// so a failed upgrade leaves the project at its old version.
+    pub fn with_default_writers_around(
+        self_version: semver::Version,
+        pipelines: Vec<PackageUpgradePipeline>,
+    ) -> Self {
+        let mut this = Self {
+            pipelines: vec![PackageUpgradePipeline::default_project_extractor()],
+        };
+        this.pipelines.extend(pipelines);
+        this.push_pipeline(PackageUpgradePipeline::default_version_writer(self_version));
+        this
     }
 
     pub fn push_pipeline(&mut self, pipeline: PackageUpgradePipeline) {
@@ -380,14 +798,102 @@ pub fn upgrade_project(
         }
         _ => {}
     }
-    tracing::info!("Upgrading project {project} -> {current}");
+    if manual_only {
+        tracing::info!("Manual steps to upgrade project {project} -> {current}");
+    } else {
+        tracing::info!("Upgrading project {project} -> {current}");
+    }
 
-    let mut pipeline = TransitiveUpgradePipeline::with_default_writers(current.clone());
-    pipeline.extend(
-        get_upgrade_path(&project, &current)
-            .into_iter()
-            .map(|(lower, upper)| consecutive_upgrade(lower, upper, &ctx)),
+    // Every step must be known before anything is touched, otherwise the project would end up with the new files and
+    // version, but without the steps in between.
+    let steps = get_upgrade_path(&project, &current)
+        .into_iter()
+        .map(|(lower, upper)| {
+            let step = format!("{lower} -> {upper}");
+            consecutive_upgrade(lower, upper, ctx)
+                .with_context(|| format!("No upgrade is defined for {step}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let pipeline = TransitiveUpgradePipeline::with_default_writers_around(
+        current.clone(),
+        steps.into_iter().flatten().collect(),
     );
-    pipeline.run(&ctx, manual_only)?;
+    pipeline.run(ctx, manual_only)?;
+    if !manual_only {
+        tracing::info!("Project upgraded to {current}.");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory.
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("msde-cli-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn crlf_checkouts_match_the_checksum() {
+        let opts = HashingOpts {
+            quiet: true,
+            parallel: false,
+        };
+        let published = temp_dir();
+        fs::write(published.join("app.erl"), "foo.\nbar.\n").unwrap();
+        fs::write(published.join("app.beam"), b"\x00\r\n\x01").unwrap();
+        let expected = md5_dir(&published).unwrap();
+        fs::remove_dir_all(published).unwrap();
+
+        let checkout = temp_dir();
+        fs::write(checkout.join("app.erl"), "foo.\r\nbar.\r\n").unwrap();
+        fs::write(checkout.join("app.beam"), b"\x00\r\n\x01").unwrap();
+        assert_ne!(md5_dir(&checkout).unwrap(), expected);
+        assert!(matches_checksum(&checkout, &expected, opts).unwrap());
+
+        // Binary files are never normalized.
+        fs::write(checkout.join("app.beam"), b"\x00\n\x01").unwrap();
+        assert!(!matches_checksum(&checkout, &expected, opts).unwrap());
+
+        fs::write(checkout.join("app.beam"), b"\x00\r\n\x01").unwrap();
+        fs::write(checkout.join("app.erl"), "foo.\r\nbaz.\r\n").unwrap();
+        assert!(!matches_checksum(&checkout, &expected, opts).unwrap());
+        fs::remove_dir_all(checkout).unwrap();
+    }
+
+    #[test]
+    fn parallel_hash_matches_the_sequential_one() {
+        let dir = temp_dir();
+        // More files than a single parallel batch, created in reverse order, with names that sort differently by bytes and
+        // case-insensitively.
+        for i in (0..PARALLEL_BATCH_SIZE + 20).rev() {
+            let sub = dir.join(format!("{}{}", if i % 2 == 0 { "B" } else { "a" }, i % 7));
+            let nested = sub.join(if i % 3 == 0 { "Y" } else { "x" });
+            fs::create_dir_all(&nested).unwrap();
+            let file = if i % 5 == 0 {
+                sub.join(format!("F{i}.erl"))
+            } else {
+                nested.join(format!("f{i}.beam"))
+            };
+            fs::write(file, format!("content {i}\r\n")).unwrap();
+        }
+        fs::write(dir.join("top.txt"), "top\r\n").unwrap();
+
+        for normalize_line_endings in [false, true] {
+            let hash = |parallel| {
+                md5_dir_with(
+                    &dir,
+                    normalize_line_endings,
+                    parallel,
+                    &ProgressBar::hidden(),
+                )
+                .unwrap()
+            };
+            assert_eq!(hash(true), hash(false));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::Context as _;
+
 use crate::env::{Context, Feature};
 
 #[cfg(target_os = "linux")]
@@ -16,6 +20,55 @@ pub fn wsl() -> bool {
     false
 }
 
+/// Whether the path is on the Windows filesystem mounted into WSL (e.g. `/mnt/c/..`). Always false outside of WSL.
+pub fn on_windows_filesystem(path: &Path) -> bool {
+    wsl()
+        && (path.starts_with("/mnt/")
+            || path
+                .canonicalize()
+                .map(|p| p.starts_with("/mnt/"))
+                .unwrap_or(false))
+}
+
+/// Open the directory in the file manager of the platform. In WSL, the Windows Explorer is used.
+pub fn reveal(dir: &Path) -> anyhow::Result<()> {
+    let mut command = if wsl() {
+        // The Explorer understands WSL paths when it's started in the directory.
+        let mut command = std::process::Command::new("explorer.exe");
+        command.arg(".").current_dir(dir);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("open");
+        command.arg(dir);
+        command
+    } else {
+        let mut command = std::process::Command::new("xdg-open");
+        command.arg(dir);
+        command
+    };
+    command
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to open `{}` in the file manager", dir.display()))?;
+    Ok(())
+}
+
+/// Replace the file at `path` with `contents`, so that the file is either fully written or left untouched. The contents
+/// are written to a temporary file next to it first, which is then renamed into place.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = std::path::PathBuf::from(tmp);
+    let result = std::fs::write(&tmp, contents)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .with_context(|| format!("failed to write `{}`", path.display()));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
 /// Determine what features are enabled based on the --features and --profile arguments, taking into account that
 /// the config file may or may not exist. Currently this falls back to the minimal profile on any error.
 pub fn resolve_features(
@@ -40,3 +93,16 @@ pub fn resolve_features(
         },
     }
 }
+
+/// Build the HTTP client used for talking to remote registries, with the given request and connect timeouts.
+pub fn http_client(
+    timeout: Duration,
+    connect_timeout: Duration,
+) -> anyhow::Result<reqwest::Client> {
+    // The proxy environment variables and the system proxy settings are picked up by reqwest itself.
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .build()
+        .map_err(Into::into)
+}